@@ -1,11 +1,28 @@
 use std::process::Command;
 
+use crate::decode::spawn_error;
+
+/// What ffmpeg prints when the input has no audio stream to extract (the wording
+/// differs between versions).
+const NO_AUDIO_MESSAGES: [&str; 2] = ["does not contain any stream", "matches no streams"];
+
+/// Lines of ffmpeg's stderr quoted when extraction fails.
+const STDERR_TAIL_LINES: usize = 5;
+
+/// Bytes of PCM per second of the extracted audio (44100 Hz, stereo, 16-bit).
+const BYTES_PER_SEC: f64 = 44100.0 * 2.0 * 2.0;
+
 /// Extract audio from a video file as raw PCM s16le, 44100Hz, stereo.
-/// Returns None if the video has no audio track.
-pub fn extract_audio(input_path: &str) -> anyhow::Result<Option<Vec<u8>>> {
+/// Returns None if the video has no audio track, and an error with ffmpeg's
+/// message if extraction fails for any other reason (e.g. an unsupported codec).
+/// `stream` picks an audio stream (counting only audio streams), which is then
+/// required to exist.
+pub fn extract_audio(input_path: &str, stream: Option<u32>) -> anyhow::Result<Option<Vec<u8>>> {
+    let map = stream.map(|n| format!("0:a:{n}"));
     let output = Command::new("ffmpeg")
+        .args(["-i", input_path])
+        .args(map.iter().flat_map(|map| ["-map", map.as_str()]))
         .args([
-            "-i", input_path,
             "-vn",
             "-acodec", "pcm_s16le",
             "-ar", "44100",
@@ -15,12 +32,66 @@ pub fn extract_audio(input_path: &str) -> anyhow::Result<Option<Vec<u8>>> {
         ])
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped())
-        .output()?;
+        .output()
+        .map_err(spawn_error)?;
 
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if let Some(n) = stream.filter(|_| output.stdout.is_empty()) {
+        let reason = stderr.lines().last().unwrap_or("no output");
+        anyhow::bail!("could not extract audio stream {n}: {reason}");
+    }
+    if !output.status.success() {
+        // With -vn, an input without audio leaves ffmpeg nothing to write
+        if says_no_audio(&stderr) {
+            return Ok(None);
+        }
+        anyhow::bail!("ffmpeg failed to extract audio ({}):\n{}", output.status, stderr_tail(&stderr));
+    }
     if output.stdout.is_empty() {
-        // No audio track or ffmpeg failed to extract audio
+        // An audio track with no samples
         return Ok(None);
     }
 
     Ok(Some(output.stdout))
 }
+
+/// Whether ffmpeg's stderr says the input has no audio stream to extract.
+fn says_no_audio(stderr: &str) -> bool {
+    NO_AUDIO_MESSAGES.iter().any(|msg| stderr.contains(msg))
+}
+
+/// The last lines of ffmpeg's stderr, where it explains a failure.
+fn stderr_tail(stderr: &str) -> String {
+    let lines: Vec<&str> = stderr.lines().collect();
+    lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..].join("\n")
+}
+
+/// Cut `pcm` to `secs` seconds, or pad it with silence up to that length, so an
+/// audio track from another file matches the video.
+pub fn fit_to_duration(pcm: &mut Vec<u8>, secs: f64) {
+    // Whole sample frames only, so the channels stay aligned
+    let len = (secs * BYTES_PER_SEC) as usize / 4 * 4;
+    pcm.resize(len, 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_audio_is_told_apart_from_failures() {
+        // ffmpeg 4 and later, then with an explicit -map
+        assert!(says_no_audio("Output #0, s16le, to 'pipe:1':\nOutput file #0 does not contain any stream\n"));
+        assert!(says_no_audio("Stream map '0:a' matches no streams.\nTo ignore this, add a trailing '?'.\n"));
+        assert!(!says_no_audio("[mp3float @ 0x1] Header missing\nError while decoding stream #0:1\n"));
+        assert!(!says_no_audio("Decoder (codec none) not found for input stream #0:1\n"));
+    }
+
+    #[test]
+    fn failures_quote_the_end_of_stderr() {
+        let stderr = (1..=8).map(|i| format!("line {i}\n")).collect::<String>();
+        assert_eq!(stderr_tail(&stderr), "line 4\nline 5\nline 6\nline 7\nline 8");
+        assert_eq!(stderr_tail("only line"), "only line");
+        assert_eq!(stderr_tail(""), "");
+    }
+}
@@ -0,0 +1,173 @@
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use rsfx_core::protocol::{encode_audio, encode_compressed_frame, encode_control, encode_frame, ControlCmd};
+
+/// Sample rate of the audio sent to the renderer (mono s16le); run rsfx-avatar
+/// with `--audio-rate 44100` to match.
+pub const AUDIO_RATE: u32 = 44100;
+
+/// Bytes of one mono s16le sample.
+const SAMPLE_BYTES: usize = 2;
+
+/// How often to send a heartbeat while there are no frames to send, well within
+/// any `--idle-timeout` the renderer may have.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Streams converted frames and their audio to a running rsfx-avatar renderer
+/// over its socket, paced to the frame rate, instead of writing them to a file.
+///
+/// Speaks `rsfx_core::protocol`: `RC` ready before anything else, then
+/// `RF` frames (raw RGB24) and `RA` audio, and `RC` stop at the end.
+pub struct AvatarStream {
+    stream: BufWriter<Box<dyn Write>>,
+    /// Duration of one frame, in seconds
+    frame_secs: f64,
+    /// When the first frame went out, for pacing the rest
+    start: Option<Instant>,
+    frames_sent: u64,
+    /// Mono PCM to send alongside the frames, and how much of it has been sent
+    audio: Vec<u8>,
+    audio_sent: usize,
+    /// The renderer hung up (e.g. the user quit it); nothing more is sent
+    closed: bool,
+    /// Send frames LZ4-compressed (`RZ`), through this buffer
+    compress: Option<Vec<u8>>,
+}
+
+impl AvatarStream {
+    /// Connect to the renderer listening on `socket` and tell it to start
+    /// rendering frames arriving at `fps_num / fps_den` per second, compressed
+    /// if `compress` is set.
+    pub fn connect(socket: &Path, (fps_num, fps_den): (u16, u16), compress: bool) -> anyhow::Result<Self> {
+        let stream = connect(socket).with_context(|| format!("connecting to rsfx-avatar at {}", socket.display()))?;
+        let mut avatar = Self {
+            stream: BufWriter::new(stream),
+            frame_secs: fps_den as f64 / fps_num as f64,
+            start: None,
+            frames_sent: 0,
+            audio: Vec::new(),
+            audio_sent: 0,
+            closed: false,
+            compress: compress.then(Vec::new),
+        };
+        avatar.control(ControlCmd::Ready).context("sending ready to rsfx-avatar")?;
+        Ok(avatar)
+    }
+
+    /// Queue the soundtrack (s16le, 44100 Hz, stereo) to be sent in step with the
+    /// frames; it's mixed down to mono, which is all the renderer plays.
+    pub fn set_audio(&mut self, pcm: &[u8]) {
+        self.audio = pcm
+            .chunks_exact(4)
+            .flat_map(|frame| {
+                let left = i16::from_le_bytes([frame[0], frame[1]]) as i32;
+                let right = i16::from_le_bytes([frame[2], frame[3]]) as i32;
+                (((left + right) / 2) as i16).to_le_bytes()
+            })
+            .collect();
+        self.audio_sent = 0;
+    }
+
+    /// Send a `width x height` RGB24 frame once its time has come, with the audio
+    /// that plays during it. Returns false once the renderer has gone away.
+    pub fn send_frame(&mut self, rgb: &[u8], width: u32, height: u32) -> anyhow::Result<bool> {
+        if self.closed {
+            return Ok(false);
+        }
+        let start = *self.start.get_or_insert_with(Instant::now);
+        let due = start + Duration::from_secs_f64(self.frames_sent as f64 * self.frame_secs);
+        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+            std::thread::sleep(wait);
+        }
+
+        let frame_end = (self.frames_sent + 1) as f64 * self.frame_secs;
+        let result = self
+            .frame(rgb, width, height)
+            .and_then(|()| self.audio_until(frame_end))
+            .and_then(|()| self.stream.flush());
+        self.frames_sent += 1;
+        self.check(result)
+    }
+
+    /// Send whatever audio is left, wait for it to play out and tell the
+    /// renderer to stop.
+    pub fn finish(mut self) -> anyhow::Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        let result = self.audio_until(f64::INFINITY).and_then(|()| self.stream.flush());
+        if !self.check(result)? {
+            return Ok(());
+        }
+        // Stopping drops the renderer's queued audio, so let the tail play first
+        let audio_secs = self.audio.len() as f64 / (AUDIO_RATE as usize * SAMPLE_BYTES) as f64;
+        if let Some(start) = self.start {
+            let end = start + Duration::from_secs_f64(audio_secs);
+            while let Some(wait) = end.checked_duration_since(Instant::now()) {
+                std::thread::sleep(wait.min(HEARTBEAT_INTERVAL));
+                let result = self.control(ControlCmd::Heartbeat);
+                if !self.check(result)? {
+                    return Ok(());
+                }
+            }
+        }
+        let result = self.control(ControlCmd::Stop);
+        self.check(result)?;
+        Ok(())
+    }
+
+    /// Turn a write error into `Ok(false)` if it means the renderer hung up.
+    fn check(&mut self, result: io::Result<()>) -> anyhow::Result<bool> {
+        match result {
+            Ok(()) => Ok(true),
+            Err(err) if matches!(err.kind(), io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset) => {
+                self.closed = true;
+                Ok(false)
+            }
+            Err(err) => Err(err).context("streaming to rsfx-avatar"),
+        }
+    }
+
+    /// Send the queued audio up to `secs` into the stream.
+    fn audio_until(&mut self, secs: f64) -> io::Result<()> {
+        let end = if secs.is_finite() {
+            ((secs * AUDIO_RATE as f64) as usize * SAMPLE_BYTES).min(self.audio.len())
+        } else {
+            self.audio.len()
+        };
+        if end <= self.audio_sent {
+            return Ok(());
+        }
+        encode_audio(&mut self.stream, &self.audio[self.audio_sent..end])?;
+        self.audio_sent = end;
+        Ok(())
+    }
+
+    fn frame(&mut self, rgb: &[u8], width: u32, height: u32) -> io::Result<()> {
+        // Wall-clock send time, which the renderer measures latency against
+        let timestamp_us = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_micros() as u64);
+        let (width, height) = (width as u16, height as u16);
+        match &mut self.compress {
+            Some(scratch) => encode_compressed_frame(&mut self.stream, width, height, timestamp_us, rgb, scratch),
+            None => encode_frame(&mut self.stream, width, height, timestamp_us, rgb),
+        }
+    }
+
+    fn control(&mut self, cmd: ControlCmd) -> io::Result<()> {
+        encode_control(&mut self.stream, cmd)?;
+        self.stream.flush()
+    }
+}
+
+#[cfg(unix)]
+fn connect(socket: &Path) -> io::Result<Box<dyn Write>> {
+    Ok(Box::new(std::os::unix::net::UnixStream::connect(socket)?))
+}
+
+#[cfg(not(unix))]
+fn connect(_socket: &Path) -> io::Result<Box<dyn Write>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "the avatar renderer listens on a Unix socket"))
+}
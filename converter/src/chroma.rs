@@ -0,0 +1,34 @@
+/// Green-screen style keying: pixels close to `color` become fully transparent.
+#[derive(Clone, Copy)]
+pub struct ChromaKey {
+    pub color: (u8, u8, u8),
+    /// Maximum per-channel distance from `color` that still counts as a match
+    pub tolerance: u8,
+}
+
+impl ChromaKey {
+    fn matches(&self, r: u8, g: u8, b: u8) -> bool {
+        r.abs_diff(self.color.0) <= self.tolerance
+            && g.abs_diff(self.color.1) <= self.tolerance
+            && b.abs_diff(self.color.2) <= self.tolerance
+    }
+
+    /// Key a resized frame. Input is RGB24, or RGBA when `has_alpha` is set;
+    /// output is always RGBA with matched pixels at alpha 0.
+    pub fn apply(&self, pixels: Vec<u8>, has_alpha: bool) -> Vec<u8> {
+        let mut rgba = if has_alpha {
+            pixels
+        } else {
+            pixels
+                .chunks_exact(3)
+                .flat_map(|p| [p[0], p[1], p[2], 255])
+                .collect()
+        };
+        for p in rgba.chunks_exact_mut(4) {
+            if self.matches(p[0], p[1], p[2]) {
+                p[3] = 0;
+            }
+        }
+        rgba
+    }
+}
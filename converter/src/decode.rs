@@ -1,34 +1,196 @@
 use anyhow::Context;
+use clap::ValueEnum;
 use ffmpeg_sidecar::command::FfmpegCommand;
 use ffmpeg_sidecar::event::FfmpegEvent;
 
+/// ffmpeg couldn't be started because it isn't on the PATH.
+#[derive(Debug)]
+pub struct FfmpegMissing;
+
+impl std::fmt::Display for FfmpegMissing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(
+            "ffmpeg not found in PATH; install it from https://ffmpeg.org/download.html \
+             (or e.g. `brew install ffmpeg`, `apt install ffmpeg`, `winget install ffmpeg`)",
+        )
+    }
+}
+
+impl std::error::Error for FfmpegMissing {}
+
+/// The error for a failed ffmpeg launch: `FfmpegMissing` when it isn't
+/// installed, otherwise the underlying error.
+pub fn spawn_error(err: std::io::Error) -> anyhow::Error {
+    if err.kind() == std::io::ErrorKind::NotFound {
+        FfmpegMissing.into()
+    } else {
+        anyhow::Error::new(err).context("failed to start ffmpeg")
+    }
+}
+
 pub struct VideoFrame {
     pub data: Vec<u8>,
     pub width: u32,
     pub height: u32,
 }
 
+/// How `VideoDecoder` has ffmpeg decode the input.
+pub struct DecodeOptions {
+    /// Resample the output to this frame rate; `None` keeps the source rate
+    pub fps: Option<u16>,
+    /// Output RGBA instead of RGB24
+    pub alpha: bool,
+    /// Run ffmpeg's `yadif` deinterlacer, for interlaced sources whose combing
+    /// would otherwise survive the resize
+    pub deinterlace: Option<FieldOrder>,
+    /// Decode on the GPU; falls back to software decoding if it doesn't work
+    pub hwaccel: Option<HwAccel>,
+    /// Which video stream to decode, counting only video streams; `None` lets
+    /// ffmpeg pick the default one
+    pub video_stream: Option<u32>,
+    /// Decoder threads (`-threads`); `None` leaves it to ffmpeg, which uses all cores
+    pub threads: Option<u32>,
+    /// The input names a capture device, opened with the platform's ffmpeg
+    /// capture format (see `device_input`)
+    pub device: bool,
+    /// Stop after this many seconds (`-t`)
+    pub duration: Option<f64>,
+}
+
+/// ffmpeg arguments that open capture device `name`: v4l2 on Linux,
+/// avfoundation on macOS and dshow on Windows.
+fn device_input(name: &str) -> ([&'static str; 2], String) {
+    if cfg!(target_os = "macos") {
+        (["-f", "avfoundation"], name.to_string())
+    } else if cfg!(windows) {
+        // DirectShow wants the kind of device along with its name
+        let name = if name.starts_with("video=") { name.to_string() } else { format!("video={name}") };
+        (["-f", "dshow"], name)
+    } else {
+        (["-f", "v4l2"], name.to_string())
+    }
+}
+
+/// Hardware decoders ffmpeg can use (`-hwaccel`). Which ones work depends on the
+/// platform and how ffmpeg was built.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum HwAccel {
+    /// Let ffmpeg pick one available on this machine
+    Auto,
+    /// Linux (Intel and AMD GPUs)
+    Vaapi,
+    /// macOS
+    Videotoolbox,
+    /// NVIDIA GPUs
+    Cuda,
+}
+
+impl HwAccel {
+    fn name(self) -> &'static str {
+        match self {
+            HwAccel::Auto => "auto",
+            HwAccel::Vaapi => "vaapi",
+            HwAccel::Videotoolbox => "videotoolbox",
+            HwAccel::Cuda => "cuda",
+        }
+    }
+}
+
+/// Which field of an interlaced frame comes first in time, for `--deinterlace`.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum FieldOrder {
+    /// As the source's flags say (top first when they don't)
+    Auto,
+    /// Top field first, as in most HD broadcast material
+    Tff,
+    /// Bottom field first, as in DV
+    Bff,
+}
+
+/// The `-vf` filter chain for `options`, if it needs one.
+fn video_filter(options: &DecodeOptions) -> Option<String> {
+    options.deinterlace.map(|order| {
+        match order {
+            FieldOrder::Auto => "yadif",
+            FieldOrder::Tff => "yadif=parity=tff",
+            FieldOrder::Bff => "yadif=parity=bff",
+        }
+        .to_string()
+    })
+}
+
 pub struct VideoDecoder {
     width: u32,
     height: u32,
+    fps: Option<f32>,
+    duration: Option<f64>,
     events: Box<dyn Iterator<Item = FfmpegEvent>>,
 }
 
 impl VideoDecoder {
-    pub fn new(input_path: &str) -> anyhow::Result<Self> {
+    /// Start decoding `input_path`. Frames are RGB24, or RGBA with `options.alpha`.
+    pub fn new(input_path: &str, options: &DecodeOptions) -> anyhow::Result<Self> {
+        match options.hwaccel {
+            Some(hwaccel) => match Self::start(input_path, options, Some(hwaccel)) {
+                Err(e) if !e.is::<FfmpegMissing>() => {
+                    eprintln!("Hardware decoding ({}) failed, using software: {e:#}", hwaccel.name());
+                    Self::start(input_path, options, None)
+                }
+                result => result,
+            },
+            None => Self::start(input_path, options, None),
+        }
+    }
+
+    /// Spawn ffmpeg and read up to the first frame.
+    fn start(input_path: &str, options: &DecodeOptions, hwaccel: Option<HwAccel>) -> anyhow::Result<Self> {
+        let mut command = FfmpegCommand::new();
+        if let Some(hwaccel) = hwaccel {
+            // Without -hwaccel_output_format, decoded frames are copied back to
+            // system memory, so the rawvideo output below works unchanged
+            command.args(["-hwaccel", hwaccel.name()]);
+        }
+        if let Some(threads) = options.threads {
+            // Before the input, so it applies to the decoder
+            command.args(["-threads", &threads.to_string()]);
+        }
+        if options.device {
+            let (format, name) = device_input(input_path);
+            command.args(format).input(name);
+        } else {
+            command.input(input_path);
+        }
+        if let Some(secs) = options.duration {
+            command.args(["-t", &secs.to_string()]);
+        }
+        if let Some(n) = options.video_stream {
+            command.args(["-map", &format!("0:v:{n}")]);
+        }
+        if let Some(filter) = video_filter(options) {
+            command.args(["-vf", &filter]);
+        }
+        if let Some(fps) = options.fps {
+            command.args(["-r", &fps.to_string()]);
+        }
+        if options.alpha {
+            command.format("rawvideo").pix_fmt("rgba").output("-");
+        } else {
+            command.rawvideo();
+        }
+
         // First probe to get dimensions
-        let mut probe = FfmpegCommand::new()
-            .input(input_path)
-            .rawvideo()
-            .spawn()
-            .context("failed to spawn ffmpeg — is it installed?")?;
+        let mut probe = command.spawn().map_err(spawn_error)?;
 
         let mut events = probe.iter().context("failed to iterate ffmpeg events")?;
 
         // Find the first output frame to get dimensions
         let mut width = 0u32;
         let mut height = 0u32;
+        let mut source_fps = None;
+        let mut duration = None;
         let mut first_frame = None;
+        let wanted_stream = options.video_stream.unwrap_or(0);
+        let mut video_streams = 0;
 
         let mut collected: Vec<FfmpegEvent> = Vec::new();
         for event in &mut events {
@@ -39,6 +201,20 @@ impl VideoDecoder {
                     first_frame = Some(event);
                     break;
                 }
+                FfmpegEvent::ParsedInputStream(stream) => {
+                    // Frame rate of the decoded video stream, as reported by ffmpeg
+                    if let Some(video) = stream.video_data() {
+                        if video_streams == wanted_stream && video.fps > 0.0 {
+                            source_fps = Some(video.fps);
+                        }
+                        video_streams += 1;
+                    }
+                    collected.push(event);
+                }
+                FfmpegEvent::ParsedDuration(parsed) => {
+                    duration = Some(parsed.duration);
+                    collected.push(event);
+                }
                 _ => {
                     collected.push(event);
                 }
@@ -46,6 +222,9 @@ impl VideoDecoder {
         }
 
         if width == 0 || height == 0 {
+            if let Some(n) = options.video_stream {
+                anyhow::bail!("no frames from video stream {n} (the input has {video_streams} video stream(s))");
+            }
             anyhow::bail!("could not determine video dimensions");
         }
 
@@ -55,6 +234,8 @@ impl VideoDecoder {
         Ok(Self {
             width,
             height,
+            fps: source_fps,
+            duration,
             events: Box::new(rest),
         })
     }
@@ -66,6 +247,39 @@ impl VideoDecoder {
     pub fn source_height(&self) -> u32 {
         self.height
     }
+
+    /// Length of the input in seconds, if ffmpeg reported one.
+    pub fn source_duration(&self) -> Option<f64> {
+        self.duration
+    }
+
+    /// Frame rate of the source video as `(numerator, denominator)`, if ffmpeg reported one.
+    pub fn source_fps(&self) -> Option<(u16, u16)> {
+        self.fps.and_then(fps_to_rational)
+    }
+}
+
+/// Convert the decimal rate ffmpeg prints (e.g. "23.98") into a fraction.
+/// NTSC-style rates snap to `N*1000/1001`; anything else is kept to 1/100 precision.
+fn fps_to_rational(fps: f32) -> Option<(u16, u16)> {
+    if !fps.is_finite() || fps <= 0.0 {
+        return None;
+    }
+
+    let ntsc = (fps * 1.001).round();
+    if (fps - fps.round()).abs() > 0.01 && (ntsc / 1.001 - fps).abs() < 0.01 && ntsc * 1000.0 <= u16::MAX as f32 {
+        return Some((ntsc as u16 * 1000, 1001));
+    }
+
+    let mut num = (fps * 100.0).round() as u32;
+    let mut den = 100u32;
+    let (mut a, mut b) = (num, den);
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    num /= a;
+    den /= a;
+    Some((u16::try_from(num).ok()?, den as u16))
 }
 
 impl Iterator for VideoDecoder {
@@ -84,3 +298,29 @@ impl Iterator for VideoDecoder {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(deinterlace: Option<FieldOrder>) -> DecodeOptions {
+        DecodeOptions {
+            fps: Some(25),
+            alpha: false,
+            deinterlace,
+            hwaccel: None,
+            video_stream: None,
+            threads: None,
+            device: false,
+            duration: None,
+        }
+    }
+
+    #[test]
+    fn deinterlace_filter_follows_field_order() {
+        assert_eq!(video_filter(&options(None)), None);
+        assert_eq!(video_filter(&options(Some(FieldOrder::Auto))).as_deref(), Some("yadif"));
+        assert_eq!(video_filter(&options(Some(FieldOrder::Tff))).as_deref(), Some("yadif=parity=tff"));
+        assert_eq!(video_filter(&options(Some(FieldOrder::Bff))).as_deref(), Some("yadif=parity=bff"));
+    }
+}
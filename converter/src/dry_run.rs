@@ -0,0 +1,81 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+/// Keyframe times listed in the summary before the rest are only counted.
+const MAX_LISTED: usize = 12;
+
+/// Output for `--dry-run`: takes the writer's bytes without keeping them, so the
+/// file's size comes out exactly as it would on disk.
+#[derive(Default)]
+pub struct ByteCounter {
+    pos: u64,
+    len: u64,
+}
+
+impl ByteCounter {
+    /// Size of the file written so far.
+    pub fn size(&self) -> u64 {
+        self.len
+    }
+}
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pos += buf.len() as u64;
+        self.len = self.len.max(self.pos);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for ByteCounter {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start"))?;
+        Ok(self.pos)
+    }
+}
+
+/// `m:ss.s`
+fn format_time(secs: f64) -> String {
+    let tenths = (secs * 10.0).round() as u64;
+    format!("{}:{:02}.{}", tenths / 600, tenths / 10 % 60, tenths % 10)
+}
+
+/// `1.5 MB`, in powers of 1000.
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1_000_000_000 => format!("{:.2} GB", b as f64 / 1e9),
+        b if b >= 1_000_000 => format!("{:.1} MB", b as f64 / 1e6),
+        b if b >= 1_000 => format!("{:.1} kB", b as f64 / 1e3),
+        b => format!("{b} bytes"),
+    }
+}
+
+/// Print what the conversion would have written: a file of `size` bytes holding
+/// `frames` frames at `fps`, the ones numbered in `keyframes` as keyframes.
+pub fn report(size: u64, frames: u32, keyframes: &[u32], fps: f64) {
+    let secs = frames as f64 / fps;
+    eprintln!("Dry run; nothing was written. The file would have:");
+    eprintln!(
+        "  {frames} frames ({}): {} keyframes, {} deltas",
+        format_time(secs),
+        keyframes.len(),
+        frames as usize - keyframes.len()
+    );
+    if !keyframes.is_empty() {
+        let mut times: Vec<String> = keyframes.iter().take(MAX_LISTED).map(|&f| format_time(f as f64 / fps)).collect();
+        if keyframes.len() > MAX_LISTED {
+            times.push(format!("and {} more", keyframes.len() - MAX_LISTED));
+        }
+        eprintln!("  Keyframes at {}", times.join(", "));
+    }
+    let bitrate = if secs > 0.0 { format!(", {:.2} Mbit/s on average", size as f64 * 8.0 / secs / 1e6) } else { String::new() };
+    eprintln!("  Size: {}{bitrate}", format_size(size));
+}
@@ -19,16 +19,67 @@ pub fn pixels_to_cells(rgb: &[u8], width: u32, height: u32) -> Vec<Cell> {
             let top_off = top_y * stride + col * 3;
             let bot_off = bot_y * stride + col * 3;
 
-            cells.push(Cell {
-                bg_r: rgb[top_off],
-                bg_g: rgb[top_off + 1],
-                bg_b: rgb[top_off + 2],
-                fg_r: rgb[bot_off],
-                fg_g: rgb[bot_off + 1],
-                fg_b: rgb[bot_off + 2],
-            });
+            let px = |off: usize| [rgb[off], rgb[off + 1], rgb[off + 2]];
+            cells.push(Cell::from_pixels(px(top_off), px(bot_off)));
         }
     }
 
     cells
 }
+
+/// Like `pixels_to_cells`, but for RGBA input: each half keeps its pixel's alpha.
+pub fn rgba_pixels_to_cells(rgba: &[u8], width: u32, height: u32) -> Vec<Cell> {
+    let cols = width as usize;
+    let rows = (height / 2) as usize;
+    let stride = cols * 4;
+    let mut cells = Vec::with_capacity(cols * rows);
+
+    for row in 0..rows {
+        let top_y = row * 2;
+        let bot_y = top_y + 1;
+
+        for col in 0..cols {
+            let top_off = top_y * stride + col * 4;
+            let bot_off = bot_y * stride + col * 4;
+
+            let px = |off: usize| [rgba[off], rgba[off + 1], rgba[off + 2], rgba[off + 3]];
+            cells.push(Cell::from_rgba_pixels(px(top_off), px(bot_off)));
+        }
+    }
+
+    cells
+}
+
+/// Pad a `width x height` frame of `channels`-byte pixels with a black row
+/// (transparent with alpha) if its height is odd, since each cell takes two
+/// pixel rows. Returns the new height. The resizer always gives an even height,
+/// but steps that change the frame's shape, like cropping or rotating, may not.
+pub fn ensure_even_height(pixels: &mut Vec<u8>, width: u32, height: u32, channels: usize) -> u32 {
+    if height % 2 == 0 {
+        return height;
+    }
+    pixels.resize(width as usize * (height as usize + 1) * channels, 0);
+    height + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn odd_height_is_padded() {
+        // 2x3 pixels, all white
+        let mut pixels = vec![255u8; 2 * 3 * 3];
+        let height = ensure_even_height(&mut pixels, 2, 3, 3);
+        assert_eq!(height, 4);
+        let cells = pixels_to_cells(&pixels, 2, height);
+        assert_eq!(cells.len(), 4);
+        assert_eq!(cells[0].to_pixels(), ([255; 3], [255; 3]));
+        assert_eq!(cells[3].to_pixels(), ([255; 3], [0; 3]));
+
+        let mut rgba = vec![255u8; 4];
+        assert_eq!(ensure_even_height(&mut rgba, 1, 1, 4), 2);
+        assert_eq!(rgba_pixels_to_cells(&rgba, 1, 2)[0].to_rgba_pixels().1, [0; 4]);
+        assert_eq!(ensure_even_height(&mut rgba, 1, 2, 4), 2);
+    }
+}
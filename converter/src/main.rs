@@ -1,125 +1,657 @@
 mod audio;
+mod avatar;
+mod chroma;
 mod decode;
-mod delta;
+mod dry_run;
 mod halfblock;
+mod overlay;
+mod palette;
 mod resize;
+mod scene;
+mod timing;
 
 use std::fs::File;
-use std::io::BufWriter;
-use std::path::PathBuf;
+use std::io::{BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 use anyhow::Context;
-use clap::Parser;
-use rsfx_core::encode::RsfxWriter;
+use clap::{ArgGroup, Parser, ValueEnum};
+use rsfx_core::delta::DeltaOptions;
+use rsfx_core::encode::{RsfxBuilder, RsfxWriter};
+use rsfx_core::format::{FrameType, RsfxHeader};
 
-use crate::decode::VideoDecoder;
-use crate::delta::{compute_delta, FrameDiff};
-use crate::halfblock::pixels_to_cells;
-use crate::resize::FrameResizer;
+use crate::avatar::AvatarStream;
+use crate::chroma::ChromaKey;
+use crate::decode::{DecodeOptions, FieldOrder, HwAccel, VideoDecoder};
+use crate::dry_run::ByteCounter;
+use crate::halfblock::{ensure_even_height, pixels_to_cells, rgba_pixels_to_cells};
+use crate::overlay::{draw_text, text_lines, Corner, TextStyle, TimecodeMode};
+use crate::palette::Dither;
+use crate::resize::{FrameResizer, FILTER_NAME};
+use crate::scene::{SceneDetector, SceneMethod};
+use crate::timing::{StageTimings, TimingReport};
 
 #[derive(Parser)]
 #[command(name = "rsfx-convert", about = "Convert MP4 video to .rsfx format")]
+#[command(group(ArgGroup::new("sink").args(["output", "avatar", "dry_run"]).multiple(true)))]
 struct Cli {
     /// Input video file path
-    input: PathBuf,
+    #[arg(required_unless_present = "device", conflicts_with = "device")]
+    input: Option<PathBuf>,
 
-    /// Output .rsfx file path (default: input with .rsfx extension)
+    /// Capture from this camera or capture device instead of a file: e.g.
+    /// `/dev/video0` on Linux, a device index like `0` on macOS, or the
+    /// DirectShow camera name on Windows. Needs --output, --avatar or --dry-run; audio only
+    /// comes from --audio
+    #[arg(long, requires = "sink")]
+    device: Option<String>,
+
+    /// Stop after this many seconds of video; the way to end a --device capture
+    #[arg(long, value_parser = parse_duration)]
+    duration: Option<f64>,
+
+    /// Output .rsfx file path, or `-` for stdout (default: input with .rsfx
+    /// extension, or no file with --avatar)
     #[arg(short, long)]
     output: Option<PathBuf>,
 
-    /// Terminal columns
-    #[arg(long, default_value = "120")]
-    cols: u16,
+    /// Stream the frames and audio live to the rsfx-avatar renderer listening on
+    /// this socket, at the video's own pace (run it with `--audio-rate 44100`)
+    #[arg(long, conflicts_with_all = ["alpha", "chroma_key"])]
+    avatar: Option<PathBuf>,
 
-    /// Terminal rows
-    #[arg(long, default_value = "40")]
-    rows: u16,
+    /// LZ4-compress the frames sent to --avatar, for a fraction of the bandwidth
+    /// (the renderer has to be new enough to read them)
+    #[arg(long, requires = "avatar")]
+    avatar_compress: bool,
 
-    /// Frames per second (0 = auto-detect, uses 30)
-    #[arg(long, default_value = "30")]
+    /// Convert without writing anything, then report the size the file would
+    /// have, its keyframes and deltas and where the keyframes land, for trying
+    /// settings before a long conversion
+    #[arg(long, conflicts_with_all = ["output", "avatar"])]
+    dry_run: bool,
+
+    /// Terminal columns (default: current terminal width, or 120)
+    #[arg(long)]
+    cols: Option<u16>,
+
+    /// Terminal rows (default: current terminal height, or 40)
+    #[arg(long)]
+    rows: Option<u16>,
+
+    /// Height-to-width ratio of the terminal's cells (about 2 in most fonts).
+    /// Shrinks the grid within --cols x --rows so the picture keeps its shape
+    /// instead of stretching to fill it, and records the ratio for players
+    #[arg(long, value_parser = parse_cell_aspect)]
+    cell_aspect: Option<f32>,
+
+    /// Frames per second (0 = auto-detect from source)
+    #[arg(long, default_value = "0")]
     fps: u16,
 
     /// Keyframe interval (frames between full keyframes)
     #[arg(long, default_value = "30")]
     keyframe_interval: u16,
+
+    /// Write every frame as a keyframe (larger file, but any frame can be
+    /// decoded on its own; the header records a keyframe interval of 1)
+    #[arg(long)]
+    keyframe_only: bool,
+
+    /// Write a keyframe instead of a delta once the delta's raw size passes this
+    /// fraction of a keyframe's (1.0 = whichever is smaller)
+    #[arg(long, default_value = "1.0")]
+    max_delta_ratio: f32,
+
+    /// Write a keyframe instead of a delta once more than this percentage of
+    /// cells changed (replaces the size comparison of --max-delta-ratio): lower
+    /// for high-motion content and faster seeking, higher for smaller files
+    #[arg(long, value_parser = parse_percent, conflicts_with = "max_delta_ratio")]
+    keyframe_threshold: Option<f32>,
+
+    /// What each delta is relative to: the previous frame, or the last keyframe
+    /// (any frame then decodes from two, at the cost of deltas that grow
+    /// towards the end of each keyframe interval)
+    #[arg(long, value_enum, default_value = "prev")]
+    delta_mode: DeltaMode,
+
+    /// Store deltas as rectangles around the changed cells instead of a list of
+    /// single cells; smaller and quicker to draw when changes cluster
+    #[arg(long)]
+    region_deltas: bool,
+
+    /// Compress keyframes in blocks of rows, so a part of one can be decoded
+    /// without the rest (quicker previews of large grids); slightly larger files
+    #[arg(long)]
+    framed_keyframes: bool,
+
+    /// Frames between checkpoints of the output file, which leave it playable
+    /// up to the last whole frame if the conversion is killed (0 for none)
+    #[arg(long, default_value = "300")]
+    checkpoint_interval: u32,
+
+    /// Also write a keyframe at scene cuts, found by comparing each frame with
+    /// the last: `histogram` ignores pans and camera motion, `sad` is cheaper
+    #[arg(long, value_enum)]
+    scene_detect: Option<SceneMethod>,
+
+    /// How different two frames must be to count as a cut, from 0 to 1
+    /// (default: 0.3 for sad, 0.4 for histogram)
+    #[arg(long, requires = "scene_detect")]
+    scene_threshold: Option<f32>,
+
+    /// Deinterlace the source (ffmpeg's yadif filter), for old DV or broadcast
+    /// material that otherwise shows comb lines on motion. Takes the field order
+    /// when the source's flags get it wrong
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "auto")]
+    deinterlace: Option<FieldOrder>,
+
+    /// Decode on the GPU (vaapi on Linux, videotoolbox on macOS, cuda on NVIDIA),
+    /// falling back to software if it fails
+    #[arg(long, value_enum)]
+    hwaccel: Option<HwAccel>,
+
+    /// Threads for ffmpeg's decoder and for compressing frames (default: all cores)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    threads: Option<u32>,
+
+    /// Take the audio from this file instead of the input video; it's cut or
+    /// padded with silence to the video's length
+    #[arg(long)]
+    audio: Option<PathBuf>,
+
+    /// Video stream to convert, counting from 0 among the video streams (default:
+    /// the one ffmpeg picks)
+    #[arg(long)]
+    video_stream: Option<u32>,
+
+    /// Audio stream to convert, counting from 0 among the audio streams (e.g. for
+    /// another language track); applies to --audio's file when given
+    #[arg(long)]
+    audio_stream: Option<u32>,
+
+    /// Keep the source's alpha channel (e.g. PNG/GIF/WebM with transparency)
+    /// so the player can composite over a background
+    #[arg(long)]
+    alpha: bool,
+
+    /// Make pixels near this color transparent, as `R,G,B` (e.g. 0,255,0 for green screen)
+    #[arg(long, value_parser = parse_rgb)]
+    chroma_key: Option<(u8, u8, u8)>,
+
+    /// Per-channel distance from --chroma-key that still counts as a match
+    #[arg(long, default_value = "40")]
+    chroma_tolerance: u8,
+
+    /// Reduce each frame to at most this many colors (median cut), for a retro
+    /// look and smaller deltas. Overlays are drawn afterwards in their own colors
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    max_colors: Option<u16>,
+
+    /// How --max-colors maps pixels onto its palette: `ordered` dithers with a
+    /// Bayer matrix, which hides banding in gradients
+    #[arg(long, value_enum, default_value = "none", requires = "max_colors")]
+    dither: Dither,
+
+    /// Mark the file as meant to repeat, so the player loops it by default
+    #[arg(long = "loop")]
+    loop_hint: bool,
+
+    /// Embed a SubRip (.srt) subtitle track, shown by the player as text
+    #[arg(long)]
+    subtitles: Option<PathBuf>,
+
+    /// Burn the frame's timestamp and/or number into the picture
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "time")]
+    timecode: Option<TimecodeMode>,
+
+    /// Corner for --timecode
+    #[arg(long, value_enum, default_value = "bottom-left")]
+    timecode_pos: Corner,
+
+    /// Text color for --timecode, as `R,G,B`
+    #[arg(long, value_parser = parse_rgb, default_value = "255,255,255")]
+    timecode_color: (u8, u8, u8),
+
+    /// Label to bake into every frame; `\n` starts a new line
+    #[arg(long)]
+    watermark: Option<String>,
+
+    /// Corner for --watermark
+    #[arg(long, value_enum, default_value = "bottom-right")]
+    watermark_pos: Corner,
+
+    /// Opacity of the dark box behind --watermark text (0 = no box)
+    #[arg(long, default_value = "0.5")]
+    watermark_box: f32,
+
+    /// Report time spent in decode, resize, cell conversion, delta and write
+    /// every 100 frames and at the end (on stderr)
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum DeltaMode {
+    /// Changes since the previous frame (smallest files)
+    Prev,
+    /// Changes since the last keyframe (faster seeking)
+    Keyframe,
+}
+
+impl Cli {
+    /// Frames between forced keyframes, 1 when every frame is a keyframe.
+    fn keyframe_interval(&self) -> u16 {
+        if self.keyframe_only {
+            1
+        } else {
+            self.keyframe_interval
+        }
+    }
+
+    /// Whether the output carries alpha: kept from the source or produced by keying.
+    fn alpha_output(&self) -> bool {
+        self.alpha || self.chroma_key.is_some()
+    }
+}
+
+fn parse_rgb(s: &str) -> Result<(u8, u8, u8), String> {
+    let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+    let [r, g, b] = parts[..] else {
+        return Err(format!("expected R,G,B, got `{s}`"));
+    };
+    let channel = |v: &str| v.parse::<u8>().map_err(|_| format!("invalid color channel `{v}` (0-255)"));
+    Ok((channel(r)?, channel(g)?, channel(b)?))
+}
+
+fn parse_percent(s: &str) -> Result<f32, String> {
+    match s.trim_end_matches('%').parse::<f32>() {
+        Ok(pct) if (0.0..=100.0).contains(&pct) => Ok(pct),
+        _ => Err(format!("expected a percentage from 0 to 100, got `{s}`")),
+    }
+}
+
+fn parse_duration(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(secs) if secs > 0.0 && secs.is_finite() => Ok(secs),
+        _ => Err(format!("expected a positive number of seconds, got `{s}`")),
+    }
+}
+
+fn parse_cell_aspect(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(ratio) if (0.5..=5.0).contains(&ratio) => Ok(ratio),
+        _ => Err(format!("expected a cell height-to-width ratio from 0.5 to 5, got `{s}`")),
+    }
 }
 
 fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
-    let output_path = cli.output.unwrap_or_else(|| {
-        let mut p = cli.input.clone();
-        p.set_extension("rsfx");
-        p
+    let input_path = cli.input.clone().or_else(|| cli.device.clone().map(PathBuf::from)).unwrap_or_default();
+    let output_path = cli.output.clone().or_else(|| {
+        cli.avatar.is_none().then(|| {
+            let mut p = input_path.clone();
+            p.set_extension("rsfx");
+            p
+        })
     });
 
-    let input_str = cli.input.to_str().context("invalid input path")?;
+    let input_str = input_path.to_str().context("invalid input path")?;
+    let (cols, rows) = target_size(cli.cols, cli.rows);
 
-    eprintln!("Decoding video: {}", cli.input.display());
-    let decoder = VideoDecoder::new(input_str)?;
+    let threads = cli
+        .threads
+        .map_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()), |n| n as usize);
+
+    match &cli.device {
+        Some(device) => eprintln!("Capturing from device: {device}"),
+        None => eprintln!("Decoding video: {}", input_path.display()),
+    }
+    let decode_options = DecodeOptions {
+        fps: (cli.fps != 0).then_some(cli.fps),
+        alpha: cli.alpha,
+        deinterlace: cli.deinterlace,
+        hwaccel: cli.hwaccel,
+        video_stream: cli.video_stream,
+        threads: cli.threads,
+        device: cli.device.is_some(),
+        duration: cli.duration,
+    };
+    let decoder = VideoDecoder::new(input_str, &decode_options)?;
     eprintln!(
         "Source: {}x{} pixels",
         decoder.source_width(),
         decoder.source_height()
     );
+    let (cols, rows) = match cli.cell_aspect {
+        Some(aspect) => fit_to_source(cols, rows, decoder.source_width(), decoder.source_height(), aspect),
+        None => (cols, rows),
+    };
+
+    let (fps_num, fps_den) = if cli.fps != 0 {
+        (cli.fps, 1)
+    } else {
+        match decoder.source_fps() {
+            Some((num, den)) => {
+                eprintln!("Source frame rate: {num}/{den} fps");
+                (num, den)
+            }
+            None => {
+                eprintln!("Could not detect source frame rate, using 30 fps");
+                (30, 1)
+            }
+        }
+    };
+
     eprintln!(
-        "Target: {}x{} cells ({}x{} pixels)",
-        cli.cols,
-        cli.rows,
-        cli.cols,
-        cli.rows * 2
+        "Target: {}x{} cells ({}x{} pixels) at {:.3} fps",
+        cols,
+        rows,
+        cols,
+        rows * 2,
+        fps_num as f64 / fps_den as f64
     );
 
-    let mut resizer = FrameResizer::new(cli.cols, cli.rows);
+    let mut resizer = FrameResizer::new(cols, rows, cli.alpha);
+    // Compress frames in the background while the next ones decode and resize
+    let builder = RsfxBuilder::new(cols.into(), rows.into())
+        .fps(fps_num, fps_den)
+        .keyframe_interval(cli.keyframe_interval())
+        .alpha(cli.alpha_output())
+        .looping(cli.loop_hint)
+        .keyframe_deltas(cli.delta_mode == DeltaMode::Keyframe)
+        .region_deltas(cli.region_deltas)
+        .framed_keyframes(cli.framed_keyframes)
+        .checkpoint_interval(cli.checkpoint_interval)
+        .cell_aspect(cli.cell_aspect.unwrap_or(0.0))
+        .threads(threads)
+        .delta_options(DeltaOptions {
+            max_delta_ratio: cli.max_delta_ratio,
+            keyframe_threshold: cli.keyframe_threshold,
+            ..DeltaOptions::default()
+        });
+    let metadata = conversion_metadata(&cli, &builder.header()?);
+    let builder = builder.metadata(metadata);
+    let fps = (fps_num, fps_den);
 
-    let file = File::create(&output_path)
-        .with_context(|| format!("failed to create {}", output_path.display()))?;
-    let buf_writer = BufWriter::new(file);
-    let mut writer = RsfxWriter::new(buf_writer, cli.cols, cli.rows, cli.fps, cli.keyframe_interval)?;
+    if cli.dry_run {
+        let mut writer = builder.build(ByteCounter::default())?;
+        let encoded = encode(Some(&mut writer), None, decoder, &mut resizer, &cli, fps, input_str)?;
+        let size = writer.finish()?.size();
+        dry_run::report(size, encoded.frames, &encoded.keyframes, fps_num as f64 / fps_den as f64);
+        return Ok(());
+    }
 
-    let mut prev_cells: Vec<rsfx_core::format::Cell> = Vec::new();
-    let mut frame_num = 0u32;
+    let mut avatar = match &cli.avatar {
+        Some(socket) => {
+            let avatar = AvatarStream::connect(socket, (fps_num, fps_den), cli.avatar_compress)?;
+            eprintln!("Streaming to rsfx-avatar at {}", socket.display());
+            Some(avatar)
+        }
+        None => None,
+    };
+    let Some(output_path) = output_path else {
+        encode::<std::io::Sink>(None, avatar.as_mut(), decoder, &mut resizer, &cli, fps, input_str)?;
+        return finish_avatar(avatar);
+    };
+    if output_path == Path::new("-") {
+        let stdout = std::io::stdout();
+        if stdout.is_terminal() {
+            anyhow::bail!("refusing to write binary .rsfx data to a terminal; redirect stdout or use -o <file>");
+        }
+        // Stdout can't seek back to patch the header, so use the footer layout
+        let mut writer = builder.build_streaming(BufWriter::new(stdout.lock()))?;
+        encode(Some(&mut writer), avatar.as_mut(), decoder, &mut resizer, &cli, fps, input_str)?;
+        writer.finish_streaming()?;
+        eprintln!("Wrote to stdout");
+    } else {
+        let file = File::create(&output_path)
+            .with_context(|| format!("failed to create {}", output_path.display()))?;
+        let mut writer = builder.build(BufWriter::new(file))?;
+        encode(Some(&mut writer), avatar.as_mut(), decoder, &mut resizer, &cli, fps, input_str)?;
+        writer.finish()?;
+        eprintln!("Wrote {}", output_path.display());
+    }
+
+    finish_avatar(avatar)
+}
+
+/// Let the streamed audio play out and stop the renderer, if streaming to one.
+fn finish_avatar(avatar: Option<AvatarStream>) -> anyhow::Result<()> {
+    match avatar {
+        Some(avatar) => avatar.finish(),
+        None => Ok(()),
+    }
+}
 
-    for frame in decoder {
-        let resized = resizer.resize(&frame.data, frame.width, frame.height)?;
-        let cells = pixels_to_cells(&resized, resizer.target_width(), resizer.target_height());
+/// How the file was made: source, converter version and the settings used, so
+/// a clip can be identified and reproduced later.
+fn conversion_metadata(cli: &Cli, header: &RsfxHeader) -> Vec<(String, String)> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    [
+        ("source", cli.input.as_ref().map_or_else(|| cli.device.clone().unwrap_or_default(), |p| p.display().to_string())),
+        ("converter", format!("rsfx-convert {}", env!("CARGO_PKG_VERSION"))),
+        ("cols", header.cols.to_string()),
+        ("rows", header.rows.to_string()),
+        ("fps", format!("{}/{}", header.fps_num, header.fps_den)),
+        ("keyframe_interval", header.keyframe_interval.to_string()),
+        ("filter", FILTER_NAME.to_string()),
+        ("args", args.join(" ")),
+    ]
+    .into_iter()
+    .map(|(key, value)| (key.to_string(), value))
+    .collect()
+}
+
+/// Frames between progress lines (and timing reports with `--verbose`).
+const PROGRESS_INTERVAL: u32 = 100;
 
-        let force_keyframe = frame_num % (cli.keyframe_interval as u32) == 0;
-        let diff = compute_delta(&prev_cells, &cells, cli.cols, force_keyframe);
+/// What `encode` got through: frames, and which of them were written as keyframes.
+struct Encoded {
+    frames: u32,
+    keyframes: Vec<u32>,
+}
 
-        match diff {
-            FrameDiff::Keyframe(ref kf) => {
-                writer.write_keyframe(kf)?;
+/// Write the audio track, then run every decoded frame through resize → cells → delta into `writer`.
+/// With `avatar`, each frame and its audio are also streamed to the renderer as they're ready.
+fn encode<W: Write>(
+    mut writer: Option<&mut RsfxWriter<W>>,
+    mut avatar: Option<&mut AvatarStream>,
+    mut decoder: VideoDecoder,
+    resizer: &mut FrameResizer,
+    cli: &Cli,
+    (fps_num, fps_den): (u16, u16),
+    input_str: &str,
+) -> anyhow::Result<Encoded> {
+    // Audio goes first so streamed output can be played while it arrives
+    eprintln!("Extracting audio...");
+    let pcm = match &cli.audio {
+        Some(path) => {
+            let path_str = path.to_str().context("invalid audio path")?;
+            let mut pcm = audio::extract_audio(path_str, cli.audio_stream)?
+                .with_context(|| format!("{} has no audio", path.display()))?;
+            let length = match (cli.duration, decoder.source_duration()) {
+                (Some(limit), Some(secs)) => Some(limit.min(secs)),
+                (limit, secs) => limit.or(secs),
+            };
+            match length {
+                Some(secs) => audio::fit_to_duration(&mut pcm, secs),
+                None => eprintln!("Video length unknown; keeping the full audio track"),
             }
-            FrameDiff::Delta(ref d) => {
-                writer.write_delta(d)?;
+            Some(pcm)
+        }
+        // A camera's microphone is a device of its own
+        None if cli.device.is_some() => None,
+        None => {
+            let mut pcm = audio::extract_audio(input_str, cli.audio_stream)?;
+            if let (Some(pcm), Some(limit)) = (&mut pcm, cli.duration) {
+                audio::fit_to_duration(pcm, limit);
+            }
+            pcm
+        }
+    };
+    match pcm {
+        Some(pcm) => {
+            eprintln!("Audio: {} bytes PCM", pcm.len());
+            if let Some(avatar) = avatar.as_deref_mut() {
+                avatar.set_audio(&pcm);
             }
+            if let Some(writer) = writer.as_deref_mut() {
+                writer.write_audio(&pcm, 44100, 2)?;
+            }
+        }
+        None => {
+            eprintln!("No audio track found.");
         }
+    }
+
+    if let (Some(path), Some(writer)) = (&cli.subtitles, writer.as_deref_mut()) {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let entries = rsfx_core::subtitle::parse_srt(&text)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+        eprintln!("Subtitles: {} entries", entries.len());
+        writer.write_subtitles(&entries)?;
+    }
+
+    let chroma_key = cli.chroma_key.map(|color| ChromaKey {
+        color,
+        tolerance: cli.chroma_tolerance,
+    });
+    let timecode_style = TextStyle {
+        color: cli.timecode_color,
+        outline: Some((0, 0, 0)),
+        background: None,
+    };
+    let watermark = cli.watermark.as_deref().map(text_lines);
+    let watermark_style = TextStyle {
+        color: (255, 255, 255),
+        outline: None,
+        background: (cli.watermark_box > 0.0).then_some(((0, 0, 0), cli.watermark_box.min(1.0))),
+    };
+    let mut scenes = cli
+        .scene_detect
+        .map(|method| SceneDetector::new(method, cli.scene_threshold.unwrap_or(method.default_threshold())));
+    let mut frame_num = 0u32;
+    let mut keyframes = Vec::new();
+    let mut report = cli.verbose.then(|| TimingReport::new(PROGRESS_INTERVAL));
+    let mut timings = StageTimings::default();
 
-        prev_cells = cells;
+    loop {
+        let mut clock = Instant::now();
+        let Some(frame) = decoder.next() else { break };
+        StageTimings::lap(&mut timings.decode, &mut clock);
+
+        let mut resized = resizer.resize(&frame.data, frame.width, frame.height)?;
+        if let Some(key) = &chroma_key {
+            resized = key.apply(resized, cli.alpha);
+        }
+        let channels = if cli.alpha_output() { 4 } else { 3 };
+        if let Some(max_colors) = cli.max_colors {
+            palette::quantize(&mut resized, resizer.target_width() as usize, channels, max_colors as usize, cli.dither);
+        }
+        // Compare the picture itself, before overlays that change every frame
+        let scene_cut = scenes.as_mut().is_some_and(|scenes| scenes.is_cut(&resized, channels));
+        let (width, height) = (resizer.target_width(), resizer.target_height());
+        if let Some(lines) = &watermark {
+            draw_text(&mut resized, width, height, channels, lines, cli.watermark_pos, &watermark_style);
+        }
+        if let Some(mode) = cli.timecode {
+            let text = mode.text(frame_num, fps_num, fps_den);
+            draw_text(&mut resized, width, height, channels, &[text], cli.timecode_pos, &timecode_style);
+        }
+        StageTimings::lap(&mut timings.resize, &mut clock);
+
+        let height = ensure_even_height(&mut resized, width, height, channels);
+        let cells = if cli.alpha_output() {
+            rgba_pixels_to_cells(&resized, width, height)
+        } else {
+            pixels_to_cells(&resized, width, height)
+        };
+        StageTimings::lap(&mut timings.cells, &mut clock);
+
+        if let Some(writer) = writer.as_deref_mut() {
+            if scene_cut {
+                writer.request_keyframe();
+            }
+            if writer.write_frame(&cells)? == FrameType::Keyframe {
+                keyframes.push(frame_num);
+            }
+        }
+        if let Some(avatar) = avatar.as_deref_mut() {
+            if !avatar.send_frame(&resized, width, height)? {
+                eprintln!("\nrsfx-avatar closed the connection");
+                break;
+            }
+        }
+        StageTimings::lap(&mut timings.write, &mut clock);
         frame_num += 1;
 
-        if frame_num % 100 == 0 {
+        if let Some(report) = &mut report {
+            report.frame_done(&timings, frame_num);
+        } else if frame_num % PROGRESS_INTERVAL == 0 {
             eprint!("\rProcessed {frame_num} frames...");
         }
     }
 
     eprintln!("\rProcessed {frame_num} frames total.");
+    if frame_num == 0 {
+        eprintln!("Warning: no video frames were decoded; the output has only audio (if any)");
+    }
+    if let Some(scenes) = &scenes {
+        eprintln!("Scene cuts: {}", scenes.cuts());
+    }
+    if let Some(report) = &report {
+        report.finish(&timings, frame_num);
+    }
 
-    // Extract and write audio
-    eprintln!("Extracting audio...");
-    match audio::extract_audio(input_str)? {
-        Some(pcm) => {
-            eprintln!("Audio: {} bytes PCM", pcm.len());
-            writer.write_audio(&pcm, 44100, 2)?;
-        }
-        None => {
-            eprintln!("No audio track found.");
-        }
+    Ok(Encoded { frames: frame_num, keyframes })
+}
+
+/// The largest grid within `cols x rows` that shows a `width x height` source
+/// in its own proportions on cells `cell_aspect` times taller than wide.
+fn fit_to_source(cols: u16, rows: u16, width: u32, height: u32, cell_aspect: f32) -> (u16, u16) {
+    if width == 0 || height == 0 {
+        return (cols, rows);
     }
+    // Measured in cell widths, the grid shows `cols` across and `rows * cell_aspect` down
+    let source = width as f64 / height as f64;
+    let fit_cols = rows as f64 * cell_aspect as f64 * source;
+    if fit_cols <= cols as f64 {
+        ((fit_cols.round() as u16).max(1), rows)
+    } else {
+        let fit_rows = cols as f64 / (cell_aspect as f64 * source);
+        (cols, (fit_rows.round() as u16).clamp(1, rows))
+    }
+}
 
-    writer.finish()?;
-    eprintln!("Wrote {}", output_path.display());
+/// Pick the output grid size. Explicit `--cols`/`--rows` win; otherwise, when run
+/// interactively, fill the current terminal (leaving a row for the shell prompt).
+fn target_size(cols: Option<u16>, rows: Option<u16>) -> (u16, u16) {
+    const DEFAULT_COLS: u16 = 120;
+    const DEFAULT_ROWS: u16 = 40;
+
+    if let (Some(cols), Some(rows)) = (cols, rows) {
+        return (cols, rows);
+    }
 
-    Ok(())
+    let detected = if std::io::stderr().is_terminal() {
+        crossterm::terminal::size()
+            .ok()
+            .filter(|&(c, r)| c > 0 && r > 1)
+            .map(|(c, r)| (c, r - 1))
+    } else {
+        None
+    };
+
+    match detected {
+        Some((term_cols, term_rows)) => {
+            let size = (cols.unwrap_or(term_cols), rows.unwrap_or(term_rows));
+            eprintln!("Using terminal size: {}x{} cells", size.0, size.1);
+            size
+        }
+        None => (cols.unwrap_or(DEFAULT_COLS), rows.unwrap_or(DEFAULT_ROWS)),
+    }
 }
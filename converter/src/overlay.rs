@@ -0,0 +1,339 @@
+use clap::ValueEnum;
+
+/// Corner of the frame an overlay is anchored to.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// What `--timecode` burns into each frame.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum TimecodeMode {
+    /// Presentation time as HH:MM:SS.mmm
+    Time,
+    /// Frame number
+    Frame,
+    /// Both, time first
+    Both,
+}
+
+impl TimecodeMode {
+    /// The overlay text for frame `frame_num` at `fps_num / fps_den` frames per second.
+    pub fn text(self, frame_num: u32, fps_num: u16, fps_den: u16) -> String {
+        let millis = frame_num as u64 * fps_den as u64 * 1000 / fps_num as u64;
+        let time = format!(
+            "{:02}:{:02}:{:02}.{:03}",
+            millis / 3_600_000,
+            millis / 60_000 % 60,
+            millis / 1000 % 60,
+            millis % 1000
+        );
+        match self {
+            TimecodeMode::Time => time,
+            TimecodeMode::Frame => format!("#{frame_num}"),
+            TimecodeMode::Both => format!("{time} #{frame_num}"),
+        }
+    }
+}
+
+pub struct TextStyle {
+    pub color: (u8, u8, u8),
+    /// Drawn one pixel around every glyph pixel so the text reads on any background
+    pub outline: Option<(u8, u8, u8)>,
+    /// Box behind the text, as a color and an opacity from 0 to 1
+    pub background: Option<((u8, u8, u8), f32)>,
+}
+
+const GLYPH_WIDTH: i32 = 5;
+const GLYPH_HEIGHT: i32 = 7;
+/// Glyph width plus one column of spacing
+const ADVANCE: i32 = GLYPH_WIDTH + 1;
+/// Glyph height plus two rows of spacing
+const LINE_HEIGHT: i32 = GLYPH_HEIGHT + 2;
+/// Gap between the text and the frame edge, leaving room for the outline or box
+const MARGIN: i32 = 2;
+
+/// Split overlay text into lines at newlines, including a typed `\n`.
+pub fn text_lines(text: &str) -> Vec<String> {
+    text.replace("\\n", "\n").lines().map(str::to_owned).collect()
+}
+
+/// A frame being drawn on: RGB24, or RGBA when `channels` is 4 (drawn pixels are
+/// made opaque). Drawing past the frame edge is clipped.
+struct Canvas<'a> {
+    pixels: &'a mut [u8],
+    width: i32,
+    height: i32,
+    channels: usize,
+}
+
+impl Canvas<'_> {
+    /// Blend `color` over the pixel at `opacity` (1 replaces it).
+    fn blend(&mut self, x: i32, y: i32, (r, g, b): (u8, u8, u8), opacity: f32) {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return;
+        }
+        let off = (y * self.width + x) as usize * self.channels;
+        let mix = |dst: u8, src: u8| (dst as f32 + (src as f32 - dst as f32) * opacity).round() as u8;
+        let px = &mut self.pixels[off..off + self.channels];
+        px[0] = mix(px[0], r);
+        px[1] = mix(px[1], g);
+        px[2] = mix(px[2], b);
+        if self.channels == 4 {
+            px[3] = mix(px[3], 255);
+        }
+    }
+}
+
+/// Draw `lines` of text into a corner of a frame. `pixels` is RGB24, or RGBA when
+/// `channels` is 4. Lines are aligned to the corner's side.
+pub fn draw_text(
+    pixels: &mut [u8],
+    width: u32,
+    height: u32,
+    channels: usize,
+    lines: &[impl AsRef<str>],
+    corner: Corner,
+    style: &TextStyle,
+) {
+    if lines.is_empty() {
+        return;
+    }
+    let mut canvas = Canvas {
+        pixels,
+        width: width as i32,
+        height: height as i32,
+        channels,
+    };
+    let line_width = |line: &str| (line.chars().count() as i32 * ADVANCE - 1).max(0);
+    let block_width = lines.iter().map(|l| line_width(l.as_ref())).max().unwrap_or(0);
+    let block_height = lines.len() as i32 * LINE_HEIGHT - (LINE_HEIGHT - GLYPH_HEIGHT);
+
+    let right = matches!(corner, Corner::TopRight | Corner::BottomRight);
+    let x0 = if right { canvas.width - MARGIN - block_width } else { MARGIN };
+    let y0 = match corner {
+        Corner::TopLeft | Corner::TopRight => MARGIN,
+        Corner::BottomLeft | Corner::BottomRight => canvas.height - MARGIN - block_height,
+    };
+
+    if let Some((color, opacity)) = style.background {
+        for y in y0 - 1..y0 + block_height + 1 {
+            for x in x0 - 1..x0 + block_width + 1 {
+                canvas.blend(x, y, color, opacity);
+            }
+        }
+    }
+
+    // Outline first so neighbouring glyph pixels aren't painted over
+    for outline in [true, false] {
+        if outline && style.outline.is_none() {
+            continue;
+        }
+        for (n, line) in lines.iter().enumerate() {
+            let line = line.as_ref();
+            let lx = if right { x0 + block_width - line_width(line) } else { x0 };
+            let ly = y0 + n as i32 * LINE_HEIGHT;
+            for (i, c) in line.chars().enumerate() {
+                let gx = lx + i as i32 * ADVANCE;
+                for (row, bits) in glyph(c).iter().enumerate() {
+                    for col in 0..GLYPH_WIDTH {
+                        if bits & (0x10 >> col) == 0 {
+                            continue;
+                        }
+                        let (x, y) = (gx + col, ly + row as i32);
+                        match style.outline {
+                            Some(color) if outline => {
+                                for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)] {
+                                    canvas.blend(x + dx, y + dy, color, 1.0);
+                                }
+                            }
+                            _ => canvas.blend(x, y, style.color, 1.0),
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 5x7 bitmap for `c`, one byte per row with the leftmost pixel in bit 4.
+/// Lowercase letters use the uppercase glyphs; anything else unknown is a `?`.
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        '#' => [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A],
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '!' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04],
+        '\'' => [0x0C, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00],
+        '"' => [0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '&' => [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D],
+        '@' => [0x0E, 0x11, 0x01, 0x0D, 0x15, 0x15, 0x0E],
+        '+' => [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00],
+        '=' => [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        '\u{a9}' => [0x0E, 0x11, 0x17, 0x19, 0x17, 0x11, 0x0E],
+        ' ' => [0x00; 7],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: TextStyle = TextStyle { color: (255, 255, 255), outline: None, background: None };
+
+    /// Draw white `lines` on a black `width x height` RGB frame.
+    fn draw(width: u32, height: u32, lines: &[&str], corner: Corner) -> Vec<u8> {
+        let mut pixels = vec![0; (width * height * 3) as usize];
+        draw_text(&mut pixels, width, height, 3, lines, corner, &WHITE);
+        pixels
+    }
+
+    /// Smallest `(left, top, right, bottom)` rectangle holding every drawn pixel.
+    fn drawn_bounds(pixels: &[u8], width: u32) -> Option<(u32, u32, u32, u32)> {
+        let drawn = pixels.chunks(3).enumerate().filter(|(_, px)| px.iter().any(|&c| c != 0));
+        drawn.fold(None, |bounds, (i, _)| {
+            let (x, y) = (i as u32 % width, i as u32 / width);
+            let (l, t, r, b) = bounds.unwrap_or((x, y, x, y));
+            Some((l.min(x), t.min(y), r.max(x), b.max(y)))
+        })
+    }
+
+    #[test]
+    fn text_lines_split_on_real_and_typed_newlines() {
+        assert_eq!(text_lines("one\\ntwo\nthree"), ["one", "two", "three"]);
+        assert_eq!(text_lines(""), Vec::<String>::new());
+    }
+
+    #[test]
+    fn text_sits_in_each_corner() {
+        // "00" is 11x7 pixels, and "0" fills its 5x7 cell
+        for (corner, bounds) in [
+            (Corner::TopLeft, (2, 2, 12, 8)),
+            (Corner::TopRight, (27, 2, 37, 8)),
+            (Corner::BottomLeft, (2, 21, 12, 27)),
+            (Corner::BottomRight, (27, 21, 37, 27)),
+        ] {
+            assert_eq!(drawn_bounds(&draw(40, 30, &["00"], corner), 40), Some(bounds));
+        }
+    }
+
+    #[test]
+    fn lines_stack_and_align_to_the_corner_side() {
+        let at = |pixels: &[u8], x: u32, y: u32| pixels[(y * 40 + x) as usize * 3] != 0;
+
+        // Lines are 9 pixels apart; the left side of "0" is lit on rows 1 to 5
+        let left = draw(40, 30, &["0", "000"], Corner::TopLeft);
+        assert_eq!(drawn_bounds(&left, 40), Some((2, 2, 18, 17)));
+        assert!(at(&left, 2, 3) && at(&left, 2, 12));
+        assert!((0..40).all(|x| !at(&left, x, 9) && !at(&left, x, 10)));
+        assert!(!at(&left, 8, 3) && at(&left, 8, 12));
+
+        // On the right, the short line ends where the long one does
+        let right = draw(40, 30, &["0", "000"], Corner::TopRight);
+        assert_eq!(drawn_bounds(&right, 40), Some((21, 2, 37, 17)));
+        assert!(at(&right, 33, 3) && !at(&right, 21, 3) && at(&right, 21, 12));
+
+        // Bottom corners put the last line at the bottom margin
+        let bottom = draw(40, 30, &["0", "000"], Corner::BottomLeft);
+        assert_eq!(drawn_bounds(&bottom, 40), Some((2, 12, 18, 27)));
+    }
+
+    #[test]
+    fn text_past_the_right_and_bottom_edges_is_clipped() {
+        // "000" needs 2 + 17 columns and 2 + 7 rows; the frame has 10 x 6
+        let pixels = draw(10, 6, &["000"], Corner::TopLeft);
+        assert_eq!(pixels.len(), 10 * 6 * 3);
+        assert_eq!(drawn_bounds(&pixels, 10), Some((2, 2, 9, 5)));
+        // Nothing wrapped onto the next row's left margin
+        assert!((0..6).all(|y| pixels[y * 30..y * 30 + 6].iter().all(|&c| c == 0)));
+
+        // Text bigger than the frame in a bottom-right corner runs off the top and left
+        let pixels = draw(10, 6, &["000", "000"], Corner::BottomRight);
+        assert_eq!(drawn_bounds(&pixels, 10), Some((0, 0, 7, 3)));
+    }
+
+    #[test]
+    fn background_box_blends_one_pixel_around_the_text() {
+        // "0" at the top-left margin covers x 2..=6, y 2..=8; the box adds a pixel each side
+        let style = TextStyle { background: Some(((200, 100, 0), 0.5)), ..WHITE };
+        let mut pixels = vec![100; 20 * 15 * 3];
+        draw_text(&mut pixels, 20, 15, 3, &["0"], Corner::TopLeft, &style);
+        let px = |x: usize, y: usize| &pixels[(y * 20 + x) * 3..][..3];
+
+        assert_eq!(px(1, 1), [150, 100, 50]);
+        assert_eq!(px(7, 9), [150, 100, 50]);
+        // Inside the "0", between its strokes
+        assert_eq!(px(3, 4), [150, 100, 50]);
+        assert_eq!(px(2, 3), [255, 255, 255]);
+        for (x, y) in [(0, 0), (8, 1), (1, 10), (8, 9), (19, 14)] {
+            assert_eq!(px(x, y), [100, 100, 100], "({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn rgba_frames_get_opaque_text_and_a_blended_box() {
+        let style = TextStyle { background: Some(((200, 100, 0), 0.5)), ..WHITE };
+        let mut pixels = vec![0; 12 * 12 * 4];
+        draw_text(&mut pixels, 12, 12, 4, &["0"], Corner::TopLeft, &style);
+        let px = |x: usize, y: usize| &pixels[(y * 12 + x) * 4..][..4];
+
+        // Over a transparent pixel the box is half covered: color and alpha both blend
+        assert_eq!(px(1, 1), [100, 50, 0, 128]);
+        assert_eq!(px(2, 3), [255, 255, 255, 255]);
+        assert_eq!(px(0, 0), [0, 0, 0, 0]);
+        assert_eq!(px(8, 8), [0, 0, 0, 0]);
+
+        // A full-opacity box replaces the pixels under it and makes them opaque
+        let style = TextStyle { background: Some(((10, 20, 30), 1.0)), ..WHITE };
+        let mut pixels = vec![0; 12 * 12 * 4];
+        draw_text(&mut pixels, 12, 12, 4, &["0"], Corner::TopLeft, &style);
+        assert_eq!(pixels[(12 + 1) * 4..][..4], [10, 20, 30, 255]);
+    }
+}
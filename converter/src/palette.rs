@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use clap::ValueEnum;
+
+/// How pixels are mapped onto the reduced palette.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Dither {
+    /// Every pixel takes its box's average, so smooth gradients turn into bands
+    None,
+    /// Offset each pixel by a 4x4 Bayer matrix threshold before picking the
+    /// nearest palette color, trading bands for a fine regular pattern. Pixels
+    /// don't depend on each other, so the pattern is stable between frames
+    Ordered,
+}
+
+/// 4x4 Bayer matrix, thresholds 0..16.
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Reduce a frame to at most `max_colors` colors with median cut, in place.
+/// `pixels` is RGB24, or RGBA when `channels` is 4 (alpha is left alone), and
+/// `width` pixels wide.
+///
+/// The frame's distinct colors are split into boxes, each time cutting the box
+/// with the widest channel range at its pixel-weighted median, and every pixel
+/// takes the average color of its box (or, dithered, the nearest average).
+/// Fewer distinct colors means more cells repeat between frames, so deltas
+/// shrink too.
+pub fn quantize(pixels: &mut [u8], width: usize, channels: usize, max_colors: usize, dither: Dither) {
+    let mut counts: HashMap<[u8; 3], u32> = HashMap::new();
+    for px in pixels.chunks_exact(channels) {
+        *counts.entry([px[0], px[1], px[2]]).or_default() += 1;
+    }
+    if counts.len() <= max_colors {
+        return;
+    }
+
+    let mut boxes = vec![ColorBox::new(counts.into_iter().collect())];
+    while boxes.len() < max_colors {
+        // Split the box spanning the most; stop once only single colors are left
+        let Some((i, _)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.colors.len() > 1)
+            .max_by_key(|(_, b)| b.widest().1)
+        else {
+            break;
+        };
+        let upper = boxes[i].split();
+        boxes.push(upper);
+    }
+
+    if dither == Dither::Ordered {
+        let palette: Vec<[u8; 3]> = boxes.iter().map(ColorBox::average).collect();
+        ordered_dither(pixels, width, channels, &palette);
+        return;
+    }
+    let mut palette = HashMap::new();
+    for b in &boxes {
+        let average = b.average();
+        for &(color, _) in &b.colors {
+            palette.insert(color, average);
+        }
+    }
+    for px in pixels.chunks_exact_mut(channels) {
+        let color = palette[&[px[0], px[1], px[2]]];
+        px[..3].copy_from_slice(&color);
+    }
+}
+
+/// Map every pixel to the palette color nearest to it once its Bayer threshold
+/// is added, scaled to roughly the spacing between palette colors.
+fn ordered_dither(pixels: &mut [u8], width: usize, channels: usize, palette: &[[u8; 3]]) {
+    let spread = 255.0 / (palette.len() as f32).cbrt();
+    let mut nearest_to: HashMap<[u8; 3], [u8; 3]> = HashMap::new();
+    for (i, px) in pixels.chunks_exact_mut(channels).enumerate() {
+        let (x, y) = (i % width.max(1), i / width.max(1));
+        let offset = ((BAYER[y % 4][x % 4] as f32 + 0.5) / 16.0 - 0.5) * spread;
+        let target = [0, 1, 2].map(|c| (px[c] as f32 + offset).round().clamp(0.0, 255.0) as u8);
+        let color = *nearest_to.entry(target).or_insert_with(|| nearest(palette, target));
+        px[..3].copy_from_slice(&color);
+    }
+}
+
+/// The palette color closest to `color` (squared RGB distance).
+fn nearest(palette: &[[u8; 3]], color: [u8; 3]) -> [u8; 3] {
+    let distance = |p: &[u8; 3]| (0..3).map(|c| (p[c] as i32 - color[c] as i32).pow(2)).sum::<i32>();
+    *palette.iter().min_by_key(|p| distance(p)).expect("palette has a color")
+}
+
+/// A set of distinct colors with their pixel counts.
+struct ColorBox {
+    colors: Vec<([u8; 3], u32)>,
+}
+
+impl ColorBox {
+    fn new(colors: Vec<([u8; 3], u32)>) -> Self {
+        Self { colors }
+    }
+
+    /// The channel with the largest spread, and that spread.
+    fn widest(&self) -> (usize, u8) {
+        (0..3)
+            .map(|c| {
+                let (min, max) = self
+                    .colors
+                    .iter()
+                    .fold((u8::MAX, u8::MIN), |(min, max), (color, _)| (min.min(color[c]), max.max(color[c])));
+                (c, max - min)
+            })
+            .max_by_key(|&(_, range)| range)
+            .expect("three channels")
+    }
+
+    /// Cut at the weighted median of the widest channel, keeping the lower half
+    /// and returning the upper one. Both halves are non-empty.
+    fn split(&mut self) -> ColorBox {
+        let (channel, _) = self.widest();
+        self.colors.sort_unstable_by_key(|(color, _)| color[channel]);
+        let total: u64 = self.colors.iter().map(|&(_, n)| n as u64).sum();
+        let mut seen = 0;
+        let mut cut = self.colors.len() - 1;
+        for (i, &(_, n)) in self.colors.iter().enumerate() {
+            seen += n as u64;
+            if seen * 2 >= total {
+                cut = i + 1;
+                break;
+            }
+        }
+        let cut = cut.clamp(1, self.colors.len() - 1);
+        ColorBox::new(self.colors.split_off(cut))
+    }
+
+    /// Pixel-weighted mean color.
+    fn average(&self) -> [u8; 3] {
+        let mut sums = [0u64; 3];
+        let mut total = 0u64;
+        for &(color, n) in &self.colors {
+            for c in 0..3 {
+                sums[c] += color[c] as u64 * n as u64;
+            }
+            total += n as u64;
+        }
+        sums.map(|sum| ((sum + total / 2) / total) as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::halfblock::pixels_to_cells;
+
+    #[test]
+    fn cells_use_at_most_max_colors() {
+        let (width, height) = (32u32, 16u32);
+        let mut pixels: Vec<u8> = (0..width * height)
+            .flat_map(|i| [(i * 7) as u8, (i * 13) as u8, (i * i) as u8])
+            .collect();
+        quantize(&mut pixels, width as usize, 3, 8, Dither::None);
+
+        let cells = pixels_to_cells(&pixels, width, height);
+        let colors: HashSet<(u8, u8, u8)> = cells
+            .iter()
+            .flat_map(|c| [(c.bg_r, c.bg_g, c.bg_b), (c.fg_r, c.fg_g, c.fg_b)])
+            .collect();
+        assert!(colors.len() <= 8, "{} colors", colors.len());
+        assert!(colors.len() > 1);
+    }
+
+    #[test]
+    fn frames_within_the_limit_are_untouched() {
+        let mut pixels = vec![10, 20, 30, 255, 40, 50, 60, 0, 10, 20, 30, 128];
+        let original = pixels.clone();
+        quantize(&mut pixels, 3, 4, 2, Dither::None);
+        assert_eq!(pixels, original);
+    }
+
+    /// Distinct averages of the 4x4 blocks of a `width` wide RGB frame.
+    fn block_averages(pixels: &[u8], width: usize) -> HashSet<[u32; 3]> {
+        let height = pixels.len() / 3 / width;
+        let mut averages = HashSet::new();
+        for by in (0..height).step_by(4) {
+            for bx in (0..width).step_by(4) {
+                let mut sum = [0u32; 3];
+                for y in by..by + 4 {
+                    for x in bx..bx + 4 {
+                        (0..3).for_each(|c| sum[c] += pixels[(y * width + x) * 3 + c] as u32);
+                    }
+                }
+                averages.insert(sum);
+            }
+        }
+        averages
+    }
+
+    #[test]
+    fn ordered_dither_breaks_up_bands() {
+        // A horizontal gray ramp
+        let (width, height) = (64usize, 8usize);
+        let ramp: Vec<u8> = (0..width * height).flat_map(|i| [(i % width * 4) as u8; 3]).collect();
+        let mut plain = ramp.clone();
+        quantize(&mut plain, width, 3, 4, Dither::None);
+        let mut dithered = ramp.clone();
+        quantize(&mut dithered, width, 3, 4, Dither::Ordered);
+
+        let colors: HashSet<&[u8]> = dithered.chunks_exact(3).collect();
+        assert!(colors.len() <= 4, "{} colors", colors.len());
+        // Dithered blocks mix palette colors, so their averages take more steps
+        let (plain_steps, dithered_steps) = (block_averages(&plain, width).len(), block_averages(&dithered, width).len());
+        assert!(dithered_steps > plain_steps, "{dithered_steps} vs {plain_steps}");
+
+        let mut again = ramp;
+        quantize(&mut again, width, 3, 4, Dither::Ordered);
+        assert_eq!(again, dithered);
+    }
+}
@@ -2,15 +2,20 @@ use anyhow::Context;
 use fast_image_resize::images::Image;
 use fast_image_resize::{FilterType, PixelType, ResizeAlg, ResizeOptions, Resizer};
 
+/// Name of the resize filter, recorded in the output's metadata.
+pub const FILTER_NAME: &str = "lanczos3";
+
 pub struct FrameResizer {
     target_width: u32,
     target_height: u32,
+    pixel_type: PixelType,
     resizer: Resizer,
     options: ResizeOptions,
 }
 
 impl FrameResizer {
-    pub fn new(target_cols: u16, target_rows: u16) -> Self {
+    /// Resizer for RGB24 frames, or RGBA frames when `alpha` is set.
+    pub fn new(target_cols: u16, target_rows: u16, alpha: bool) -> Self {
         let target_width = target_cols as u32;
         // Each row = 2 pixels tall (half-block trick)
         let target_height = (target_rows as u32) * 2;
@@ -18,21 +23,22 @@ impl FrameResizer {
         Self {
             target_width,
             target_height,
+            pixel_type: if alpha { PixelType::U8x4 } else { PixelType::U8x3 },
             resizer: Resizer::new(),
             options: ResizeOptions::new().resize_alg(ResizeAlg::Convolution(FilterType::Lanczos3)),
         }
     }
 
-    /// Resize an RGB24 frame to target dimensions. Returns RGB24 data.
+    /// Resize a frame to target dimensions. Returns data in the same pixel format.
     pub fn resize(&mut self, src_data: &[u8], src_width: u32, src_height: u32) -> anyhow::Result<Vec<u8>> {
         if src_width == self.target_width && src_height == self.target_height {
             return Ok(src_data.to_vec());
         }
 
-        let src_image = Image::from_vec_u8(src_width, src_height, src_data.to_vec(), PixelType::U8x3)
+        let src_image = Image::from_vec_u8(src_width, src_height, src_data.to_vec(), self.pixel_type)
             .context("failed to create source image")?;
 
-        let mut dst_image = Image::new(self.target_width, self.target_height, PixelType::U8x3);
+        let mut dst_image = Image::new(self.target_width, self.target_height, self.pixel_type);
 
         self.resizer
             .resize(&src_image, &mut dst_image, &self.options)
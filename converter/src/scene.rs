@@ -0,0 +1,141 @@
+use clap::ValueEnum;
+
+/// How consecutive frames are compared to spot a scene cut.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum SceneMethod {
+    /// Mean per-pixel luminance difference. Cheap, but a pan or camera shake
+    /// moves every pixel and reads as a cut
+    Sad,
+    /// Chi-squared distance between luminance histograms. Moving the same
+    /// content around leaves the histogram alone, so pans don't trigger it
+    Histogram,
+}
+
+impl SceneMethod {
+    /// Threshold used when `--scene-threshold` isn't given.
+    pub fn default_threshold(self) -> f32 {
+        match self {
+            SceneMethod::Sad => 0.3,
+            SceneMethod::Histogram => 0.4,
+        }
+    }
+}
+
+/// Luminance histogram buckets; coarse enough to ignore noise and slight grading.
+const BINS: usize = 64;
+
+/// Flags frames that start a new scene so they can be written as keyframes,
+/// which compress better than a delta that replaces most of the screen.
+pub struct SceneDetector {
+    method: SceneMethod,
+    threshold: f32,
+    /// The previous frame's luma (`Sad`) or normalized histogram (`Histogram`).
+    prev: Option<Vec<f32>>,
+    cuts: u32,
+}
+
+impl SceneDetector {
+    /// `threshold` is on a 0..1 scale for both methods: the mean luma change as
+    /// a fraction of full range, or the halved chi-squared histogram distance.
+    pub fn new(method: SceneMethod, threshold: f32) -> Self {
+        Self { method, threshold, prev: None, cuts: 0 }
+    }
+
+    /// Whether this frame differs enough from the last one to be a cut. The
+    /// first frame never is. `pixels` is RGB24, or RGBA when `channels` is 4.
+    pub fn is_cut(&mut self, pixels: &[u8], channels: usize) -> bool {
+        let current = match self.method {
+            SceneMethod::Sad => pixels.chunks_exact(channels).map(|px| luma(px) as f32).collect(),
+            SceneMethod::Histogram => histogram(pixels, channels),
+        };
+        let cut = match &self.prev {
+            Some(prev) if prev.len() == current.len() => self.distance(prev, &current) > self.threshold,
+            _ => false,
+        };
+        self.prev = Some(current);
+        self.cuts += cut as u32;
+        cut
+    }
+
+    /// Cuts found so far.
+    pub fn cuts(&self) -> u32 {
+        self.cuts
+    }
+
+    fn distance(&self, prev: &[f32], current: &[f32]) -> f32 {
+        match self.method {
+            SceneMethod::Sad => {
+                let sum: f32 = prev.iter().zip(current).map(|(a, b)| (a - b).abs()).sum();
+                sum / (current.len().max(1) as f32 * 255.0)
+            }
+            SceneMethod::Histogram => {
+                let chi: f32 = prev
+                    .iter()
+                    .zip(current)
+                    .filter(|(a, b)| *a + *b > 0.0)
+                    .map(|(a, b)| (a - b) * (a - b) / (a + b))
+                    .sum();
+                // Ranges 0..2 for normalized histograms
+                chi / 2.0
+            }
+        }
+    }
+}
+
+/// Rec. 601 luma, 0-255.
+fn luma(px: &[u8]) -> u8 {
+    ((px[0] as u32 * 77 + px[1] as u32 * 150 + px[2] as u32 * 29) >> 8) as u8
+}
+
+/// Luma histogram of a frame, normalized to sum to 1.
+fn histogram(pixels: &[u8], channels: usize) -> Vec<f32> {
+    let mut bins = vec![0f32; BINS];
+    let mut total = 0;
+    for px in pixels.chunks_exact(channels) {
+        bins[luma(px) as usize * BINS / 256] += 1.0;
+        total += 1;
+    }
+    if total > 0 {
+        for bin in &mut bins {
+            *bin /= total as f32;
+        }
+    }
+    bins
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 64x32 RGB frame of diagonal stripes, shifted `offset` pixels right.
+    fn stripes(offset: u32) -> Vec<u8> {
+        (0..32u32)
+            .flat_map(|y| (0..64u32).map(move |x| (x + y + 64 - offset) % 64))
+            .flat_map(|v| {
+                let shade = if v % 16 < 8 { 30 } else { 220 };
+                [shade, shade, shade]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pans_only_trip_sad() {
+        for (method, expect_cut) in [(SceneMethod::Sad, true), (SceneMethod::Histogram, false)] {
+            let mut detector = SceneDetector::new(method, method.default_threshold());
+            assert!(!detector.is_cut(&stripes(0), 3));
+            assert_eq!(detector.is_cut(&stripes(8), 3), expect_cut, "{method:?}");
+        }
+    }
+
+    #[test]
+    fn cuts_trip_both() {
+        let dark = vec![20u8; 64 * 32 * 3];
+        for method in [SceneMethod::Sad, SceneMethod::Histogram] {
+            let mut detector = SceneDetector::new(method, method.default_threshold());
+            assert!(!detector.is_cut(&dark, 3));
+            assert!(!detector.is_cut(&dark, 3), "{method:?}");
+            assert!(detector.is_cut(&stripes(0), 3), "{method:?}");
+            assert_eq!(detector.cuts(), 1);
+        }
+    }
+}
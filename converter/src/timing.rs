@@ -0,0 +1,86 @@
+use std::time::{Duration, Instant};
+
+/// Time spent in each stage of the frame loop, for `--verbose`.
+#[derive(Default, Clone, Copy)]
+pub struct StageTimings {
+    pub decode: Duration,
+    /// Resize plus pixel effects (chroma keying, palette reduction, overlays)
+    pub resize: Duration,
+    pub cells: Duration,
+    /// Diffing against the previous frame and handing the result to the writer;
+    /// with background compression this only includes the time spent waiting on
+    /// the compression threads
+    pub write: Duration,
+}
+
+impl StageTimings {
+    /// Add the time since `*start` to `stage` and restart the clock.
+    pub fn lap(stage: &mut Duration, start: &mut Instant) {
+        let now = Instant::now();
+        *stage += now - *start;
+        *start = now;
+    }
+
+    fn total(&self) -> Duration {
+        self.decode + self.resize + self.cells + self.write
+    }
+
+    fn since(&self, earlier: &StageTimings) -> StageTimings {
+        StageTimings {
+            decode: self.decode - earlier.decode,
+            resize: self.resize - earlier.resize,
+            cells: self.cells - earlier.cells,
+            write: self.write - earlier.write,
+        }
+    }
+
+    /// One line with per-frame averages over `frames` frames and each stage's share.
+    pub fn summary(&self, frames: u32) -> String {
+        let total = self.total().as_secs_f64();
+        let frames = frames.max(1) as f64;
+        let stage = |name: &str, d: Duration| {
+            let secs = d.as_secs_f64();
+            let share = if total > 0.0 { secs / total * 100.0 } else { 0.0 };
+            format!("{name} {:.2} ms ({share:.0}%)", secs * 1000.0 / frames)
+        };
+        format!(
+            "{}, {}, {}, {}",
+            stage("decode", self.decode),
+            stage("resize", self.resize),
+            stage("cells", self.cells),
+            stage("write", self.write),
+        )
+    }
+}
+
+/// Prints a timing breakdown every `interval` frames and totals at the end.
+pub struct TimingReport {
+    last: StageTimings,
+    interval: u32,
+}
+
+impl TimingReport {
+    pub fn new(interval: u32) -> Self {
+        Self {
+            last: StageTimings::default(),
+            interval,
+        }
+    }
+
+    /// Called after each frame; prints the last interval's averages when one completes.
+    pub fn frame_done(&mut self, timings: &StageTimings, frame_num: u32) {
+        if frame_num % self.interval == 0 {
+            let recent = timings.since(&self.last);
+            eprintln!("\rFrames {}-{}: {}", frame_num - self.interval + 1, frame_num, recent.summary(self.interval));
+            self.last = *timings;
+        }
+    }
+
+    pub fn finish(&self, timings: &StageTimings, frames: u32) {
+        eprintln!(
+            "Timing over {frames} frames ({:.2} s total), per frame: {}",
+            timings.total().as_secs_f64(),
+            timings.summary(frames)
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use std::io::{Read, Seek, Write};
+
+use anyhow::Context;
+use rsfx_core::decode::RsfxReader;
+use rsfx_core::format::FLAG_ALPHA;
+
+use crate::frames::{cells_to_pixels, for_each_frame, pixel_height};
+
+/// Write every frame as an Animated PNG at the file's frame rate. Colors (and
+/// alpha, for files that have it) are kept exactly. Loops forever if the file has
+/// the loop hint, otherwise plays once.
+pub fn write_apng<R: Read + Seek>(reader: &mut RsfxReader<R>, out: impl Write, scale: u32) -> anyhow::Result<()> {
+    let header = reader.header.clone();
+    let alpha = header.flags & FLAG_ALPHA != 0;
+    let pixel_height = pixel_height(&header, scale)?;
+    let size = header.cols.checked_mul(scale).zip(header.rows.checked_mul(2).and_then(|h| h.checked_mul(pixel_height)));
+    let (width, height) = size
+        .with_context(|| format!("a {}x{} grid at scale {scale} is too large for a PNG", header.cols, header.rows))?;
+
+    let mut encoder = png::Encoder::new(out, width, height);
+    encoder.set_color(if alpha { png::ColorType::Rgba } else { png::ColorType::Rgb });
+    encoder.set_depth(png::BitDepth::Eight);
+    let plays = if header.loop_hint() { 0 } else { 1 };
+    encoder.set_animated(header.frame_count, plays).context("setting up APNG animation")?;
+    // A frame lasts fps_den / fps_num seconds
+    encoder.set_frame_delay(header.fps_den, header.fps_num).context("setting APNG frame delay")?;
+    let mut writer = encoder.write_header().context("writing PNG header")?;
+
+    for_each_frame(reader, |idx, cells| {
+        let pixels = cells_to_pixels(cells, header.cols, header.rows, alpha, scale, pixel_height);
+        writer.write_image_data(&pixels).with_context(|| format!("writing frame {idx}"))
+    })?;
+    writer.finish().context("finishing PNG")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use rsfx_core::encode::RsfxWriter;
+    use rsfx_core::format::{Cell, DeltaCell, RsfxHeader};
+
+    use super::*;
+
+    fn read(file: Vec<u8>) -> RsfxReader<Cursor<Vec<u8>>> {
+        RsfxReader::new(Cursor::new(file)).unwrap()
+    }
+
+    #[test]
+    fn apng_has_every_frame_at_the_scaled_size() {
+        let (red, blue) = (Cell::from_pixels([255, 0, 0], [255, 0, 0]), Cell::from_pixels([0, 0, 255], [0, 0, 255]));
+        let mut writer = RsfxWriter::new_with_fps(Cursor::new(Vec::new()), 3, 2, 25, 2, 30).unwrap();
+        writer.write_keyframe(&[red; 6]).unwrap();
+        writer.write_delta(&[DeltaCell { x: 2, y: 1, cell: blue }]).unwrap();
+        writer.write_delta(&[]).unwrap();
+        let mut reader = read(writer.finish().unwrap().into_inner());
+
+        let mut apng = Vec::new();
+        write_apng(&mut reader, &mut apng, 2).unwrap();
+
+        let mut decoder = png::Decoder::new(Cursor::new(apng)).read_info().unwrap();
+        let info = decoder.info();
+        assert_eq!((info.width, info.height), (6, 8));
+        assert_eq!(info.color_type, png::ColorType::Rgb);
+        let control = info.animation_control().unwrap();
+        assert_eq!((control.num_frames, control.num_plays), (3, 1));
+
+        let mut buf = vec![0; decoder.output_buffer_size()];
+        let mut frames = Vec::new();
+        while let Ok(output) = decoder.next_frame(&mut buf) {
+            let control = decoder.info().frame_control().unwrap();
+            assert_eq!((control.delay_num, control.delay_den), (2, 25));
+            frames.push(buf[..output.buffer_size()].to_vec());
+        }
+        assert_eq!(frames.len(), 3);
+        // The last pixel turns blue from the second frame on
+        assert_eq!(frames[0][frames[0].len() - 3..], [255, 0, 0]);
+        assert_eq!(frames[1][frames[1].len() - 3..], [0, 0, 255]);
+        assert_eq!(frames[2], frames[1]);
+    }
+
+    #[test]
+    fn oversized_apng_is_an_error() {
+        // 0xFF_FFFF rows of cells five times as tall as wide don't fit in a PNG at scale 64
+        let mut header = RsfxHeader::new(1, 0xFF_FFFF, 30, 1, 30).unwrap();
+        header.cell_aspect = 1000;
+        let writer = RsfxWriter::with_header(Cursor::new(Vec::new()), header).unwrap();
+        let mut reader = read(writer.finish().unwrap().into_inner());
+        let err = write_apng(&mut reader, Vec::new(), 64).unwrap_err();
+        assert!(err.to_string().contains("too large for a PNG"), "{err}");
+    }
+}
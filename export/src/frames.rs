@@ -0,0 +1,189 @@
+use std::io::{Read, Seek};
+
+use rsfx_core::decode::{Frame, RsfxReader};
+use rsfx_core::format::{Cell, RsfxHeader};
+
+/// Decode every frame in order and call `f` with its index and the full cell grid
+/// at that point (keyframes replace the grid, deltas patch it).
+pub fn for_each_frame<R: Read + Seek>(
+    reader: &mut RsfxReader<R>,
+    mut f: impl FnMut(usize, &[Cell]) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut grid = Grid::new(&reader.header);
+    for idx in 0..reader.len() {
+        grid.apply(reader.read_frame(idx)?);
+        f(idx, &grid.cells)?;
+    }
+    Ok(())
+}
+
+/// The cell grid as frames are applied in order.
+struct Grid {
+    cells: Vec<Cell>,
+    cols: usize,
+    /// The last keyframe, kept when deltas are relative to it
+    keyframe: Option<Vec<Cell>>,
+}
+
+impl Grid {
+    fn new(header: &RsfxHeader) -> Self {
+        Self {
+            cells: Vec::new(),
+            cols: header.cols as usize,
+            keyframe: header.keyframe_deltas().then(Vec::new),
+        }
+    }
+
+    /// Make `cells` the grid after `frame`.
+    fn apply(&mut self, frame: Frame) {
+        match frame {
+            Frame::Keyframe(keyframe) => {
+                if let Some(kept) = &mut self.keyframe {
+                    kept.clone_from(&keyframe);
+                }
+                self.cells = keyframe;
+            }
+            Frame::Delta(deltas) => {
+                if let Some(kept) = &self.keyframe {
+                    self.cells.clone_from(kept);
+                }
+                for d in deltas {
+                    if let Some(cell) = self.cells.get_mut(d.y as usize * self.cols + d.x as usize) {
+                        *cell = d.cell;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Output rows for each half-block pixel at `scale`: `scale`, stretched for
+/// files made for non-square pixels (`RsfxHeader::pixel_aspect`).
+pub fn pixel_height(header: &RsfxHeader, scale: u32) -> anyhow::Result<u32> {
+    let height = (scale as f64 * header.pixel_aspect()).round().max(1.0);
+    if height > u32::MAX as f64 {
+        anyhow::bail!("pixels at scale {scale} are too tall to export");
+    }
+    Ok(height as u32)
+}
+
+/// Expand a `cols x rows` cell grid to pixels: each cell becomes a top (bg) and a
+/// bottom (fg) pixel, then every pixel a block `scale` wide and `height` tall.
+/// RGB24, or RGBA with each half's alpha when `alpha` is set. Glyphs aren't
+/// drawn, only colors.
+pub fn cells_to_pixels(cells: &[Cell], cols: u32, rows: u32, alpha: bool, scale: u32, height: u32) -> Vec<u8> {
+    let channels = if alpha { 4 } else { 3 };
+    let (cols, rows, scale, height) = (cols as usize, rows as usize, scale as usize, height as usize);
+    let mut pixels = Vec::with_capacity(cols * rows * 2 * scale * height * channels);
+    let mut line = Vec::with_capacity(cols * scale * channels);
+
+    for row in cells.chunks_exact(cols).take(rows) {
+        for bottom in [false, true] {
+            line.clear();
+            for cell in row {
+                let (upper, lower) = cell.to_rgba_pixels();
+                let px = if bottom { lower } else { upper };
+                for _ in 0..scale {
+                    line.extend_from_slice(&px[..channels]);
+                }
+            }
+            for _ in 0..height {
+                pixels.extend_from_slice(&line);
+            }
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use rsfx_core::encode::RsfxWriter;
+    use rsfx_core::format::{DeltaCell, FLAG_KEYFRAME_DELTAS};
+
+    use super::*;
+
+    #[test]
+    fn cells_become_scaled_half_block_pixels() {
+        // One row of two cells: red over green, then blue over half-transparent white
+        let cells = [
+            Cell::from_pixels([255, 0, 0], [0, 255, 0]),
+            Cell::from_rgba_pixels([0, 0, 255, 255], [255, 255, 255, 128]),
+        ];
+        let (red, green, blue, white) = ([255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]);
+        assert_eq!(cells_to_pixels(&cells, 2, 1, false, 1, 1), [red, blue, green, white].concat());
+
+        // Each pixel 2 wide and 3 tall: the bg row three times, then the fg row
+        let rgb = cells_to_pixels(&cells, 2, 1, false, 2, 3);
+        let (top, bottom) = ([red, red, blue, blue].concat(), [green, green, white, white].concat());
+        assert_eq!(rgb, [&top[..], &top, &top, &bottom, &bottom, &bottom].concat());
+
+        // RGBA keeps each half's alpha
+        let rgba = cells_to_pixels(&cells, 2, 1, true, 1, 2);
+        let top = [255, 0, 0, 255, 0, 0, 255, 255];
+        let bottom = [0, 255, 0, 255, 255, 255, 255, 128];
+        assert_eq!(rgba, [top, top, bottom, bottom].concat());
+
+        // Extra cells past `rows` are left out
+        assert_eq!(cells_to_pixels(&cells, 1, 1, false, 1, 1), [red, green].concat());
+    }
+
+    #[test]
+    fn pixel_height_follows_cell_aspect() {
+        let mut header = RsfxHeader::new(4, 2, 30, 1, 30).unwrap();
+        assert_eq!(pixel_height(&header, 3).unwrap(), 3);
+        // Cells twice as tall as wide are square pixels; taller cells stretch them
+        header.cell_aspect = 300;
+        assert_eq!(pixel_height(&header, 4).unwrap(), 6);
+        header.cell_aspect = 10;
+        assert_eq!(pixel_height(&header, 1).unwrap(), 1);
+    }
+
+    #[test]
+    fn grid_applies_keyframes_and_deltas() {
+        let [a, b, c] = [1, 2, 3].map(|v| Cell::from_pixels([v; 3], [v; 3]));
+        let delta = |x, y, cell| DeltaCell { x, y, cell };
+
+        // Deltas patch the previous frame
+        let mut grid = Grid::new(&RsfxHeader::new(2, 2, 30, 1, 30).unwrap());
+        grid.apply(Frame::Keyframe(vec![a; 4]));
+        grid.apply(Frame::Delta(vec![delta(1, 0, b)]));
+        assert_eq!(grid.cells, [a, b, a, a]);
+        grid.apply(Frame::Delta(vec![delta(0, 1, c)]));
+        assert_eq!(grid.cells, [a, b, c, a]);
+        // Positions outside the grid are ignored
+        grid.apply(Frame::Delta(vec![delta(5, 5, c)]));
+        assert_eq!(grid.cells, [a, b, c, a]);
+        grid.apply(Frame::Keyframe(vec![b; 4]));
+        assert_eq!(grid.cells, [b; 4]);
+
+        // With FLAG_KEYFRAME_DELTAS they patch the last keyframe instead
+        let mut header = RsfxHeader::new(2, 2, 30, 1, 30).unwrap();
+        header.flags |= FLAG_KEYFRAME_DELTAS;
+        let mut grid = Grid::new(&header);
+        grid.apply(Frame::Keyframe(vec![a; 4]));
+        grid.apply(Frame::Delta(vec![delta(1, 0, b)]));
+        assert_eq!(grid.cells, [a, b, a, a]);
+        grid.apply(Frame::Delta(vec![delta(0, 1, c)]));
+        assert_eq!(grid.cells, [a, a, c, a]);
+    }
+
+    #[test]
+    fn for_each_frame_visits_every_frame() {
+        let (a, b) = (Cell::from_pixels([1; 3], [1; 3]), Cell::from_pixels([2; 3], [2; 3]));
+        let mut writer = RsfxWriter::new(Cursor::new(Vec::new()), 2, 1, 30, 30).unwrap();
+        writer.write_keyframe(&[a, a]).unwrap();
+        writer.write_delta(&[DeltaCell { x: 0, y: 0, cell: b }]).unwrap();
+        writer.write_delta(&[]).unwrap();
+        let mut reader = RsfxReader::new(Cursor::new(writer.finish().unwrap().into_inner())).unwrap();
+
+        let mut seen = Vec::new();
+        for_each_frame(&mut reader, |idx, cells| {
+            seen.push((idx, cells.to_vec()));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(seen, [(0, vec![a, a]), (1, vec![b, a]), (2, vec![b, a])]);
+    }
+}
@@ -0,0 +1,136 @@
+mod apng;
+mod frames;
+mod markup;
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, Write};
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::{Parser, ValueEnum};
+use rsfx_core::decode::RsfxReader;
+use rsfx_core::format::Cell;
+
+use crate::frames::for_each_frame;
+use crate::markup::{Animation, HtmlWriter, SvgWriter};
+
+#[derive(Parser)]
+#[command(name = "rsfx-export", about = "Export an .rsfx file to other formats")]
+struct Cli {
+    /// Input .rsfx file
+    input: PathBuf,
+
+    /// Output file
+    output: PathBuf,
+
+    /// Output format (default: from the output file's extension)
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+
+    /// Draw each pixel as a SCALE x SCALE block (a cell is 1x2 pixels)
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=64))]
+    scale: u32,
+
+    /// Frame to export as a still (HTML and SVG)
+    #[arg(long, default_value_t = 0, conflicts_with = "animate")]
+    frame: usize,
+
+    /// Export every frame, played back with CSS animation (HTML and SVG; meant
+    /// for short clips, since every frame is written out in full)
+    #[arg(long)]
+    animate: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// Animated PNG, lossless
+    Apng,
+    /// Standalone HTML page of colored spans
+    Html,
+    /// SVG of colored rects
+    Svg,
+}
+
+impl Format {
+    fn from_extension(path: &std::path::Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "png" | "apng" => Some(Format::Apng),
+            "html" | "htm" => Some(Format::Html),
+            "svg" => Some(Format::Svg),
+            _ => None,
+        }
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let format = cli
+        .format
+        .or_else(|| Format::from_extension(&cli.output))
+        .context("can't tell the output format from the file name; pass --format")?;
+
+    let file = File::open(&cli.input)
+        .with_context(|| format!("failed to open {}", cli.input.display()))?;
+    let mut reader = RsfxReader::new(BufReader::new(file))?;
+    if reader.is_empty() {
+        anyhow::bail!("{} has no frames to export", cli.input.display());
+    }
+
+    let out = File::create(&cli.output)
+        .with_context(|| format!("failed to create {}", cli.output.display()))?;
+    let mut out = BufWriter::new(out);
+    let frames = match format {
+        Format::Apng if cli.frame != 0 => {
+            anyhow::bail!("--frame applies to HTML and SVG; APNG export always has every frame")
+        }
+        Format::Apng => {
+            apng::write_apng(&mut reader, out, cli.scale)?;
+            reader.len()
+        }
+        Format::Html | Format::Svg => {
+            let markup = write_markup(&mut reader, &cli, format)?;
+            out.write_all(markup.as_bytes())?;
+            out.flush()?;
+            if cli.animate { reader.len() } else { 1 }
+        }
+    };
+
+    eprintln!("Wrote {frames} frame(s) to {}", cli.output.display());
+    Ok(())
+}
+
+/// Render the chosen frame, or every frame with `--animate`, as HTML or SVG.
+fn write_markup<R: Read + Seek>(reader: &mut RsfxReader<R>, cli: &Cli, format: Format) -> anyhow::Result<String> {
+    let (cols, rows) = (reader.header.cols, reader.header.rows);
+    let pixel_aspect = reader.header.pixel_aspect();
+    let animation = cli.animate.then(|| Animation {
+        frame_secs: 1.0 / reader.fps(),
+        frame_count: reader.len(),
+    });
+    let title = cli.input.file_name().map_or_else(|| cli.input.to_string_lossy(), |name| name.to_string_lossy());
+
+    enum Writer {
+        Html(HtmlWriter),
+        Svg(SvgWriter),
+    }
+    let mut writer = match format {
+        Format::Html => Writer::Html(HtmlWriter::new(&title, cols, rows, cli.scale, pixel_aspect, animation)),
+        _ => Writer::Svg(SvgWriter::new(cols, rows, cli.scale, pixel_aspect, animation)),
+    };
+    let mut add_frame = |cells: &[Cell]| match &mut writer {
+        Writer::Html(w) => w.frame(cells),
+        Writer::Svg(w) => w.frame(cells),
+    };
+    if cli.animate {
+        for_each_frame(reader, |_, cells| {
+            add_frame(cells);
+            Ok(())
+        })?;
+    } else {
+        add_frame(&reader.reconstruct_frame(cli.frame)?);
+    }
+    Ok(match writer {
+        Writer::Html(w) => w.finish(),
+        Writer::Svg(w) => w.finish(),
+    })
+}
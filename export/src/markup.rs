@@ -0,0 +1,289 @@
+use std::fmt::Write as _;
+
+use rsfx_core::format::Cell;
+
+/// A frame's pixels, as one list of same-color runs per pixel row.
+type Runs = Vec<Vec<([u8; 4], usize)>>;
+
+/// Collapse each pixel row of a `cols x rows` grid (two per cell row: the bg
+/// halves, then the fg halves) into runs of equal color. Glyphs aren't drawn.
+fn color_runs(cells: &[Cell], cols: u32) -> Runs {
+    let mut rows = Vec::new();
+    for row in cells.chunks_exact(cols as usize) {
+        for bottom in [false, true] {
+            let mut runs: Vec<([u8; 4], usize)> = Vec::new();
+            for cell in row {
+                let (upper, lower) = cell.to_rgba_pixels();
+                let px = if bottom { lower } else { upper };
+                match runs.last_mut() {
+                    Some((color, len)) if *color == px => *len += 1,
+                    _ => runs.push((px, 1)),
+                }
+            }
+            rows.push(runs);
+        }
+    }
+    rows
+}
+
+/// CSS color: `#rrggbb`, or `rgba(...)` for partly transparent pixels.
+fn css_color([r, g, b, a]: [u8; 4]) -> String {
+    if a == Cell::OPAQUE {
+        format!("#{r:02x}{g:02x}{b:02x}")
+    } else {
+        format!("rgba({r},{g},{b},{:.3})", a as f32 / 255.0)
+    }
+}
+
+/// Timing for an animated export: frames shown one after another for
+/// `frame_secs` each, looping.
+pub struct Animation {
+    pub frame_secs: f64,
+    pub frame_count: usize,
+}
+
+impl Animation {
+    /// Stylesheet that shows element `.f` number `i` (given `animation-delay`
+    /// `i * frame_secs`) during its slot only. Frames are stacked, hidden by default.
+    fn css(&self, frame_selector: &str) -> String {
+        let total = self.frame_secs * self.frame_count as f64;
+        let shown = 100.0 / self.frame_count as f64;
+        format!(
+            "{frame_selector}{{visibility:hidden;animation:rsfx-frame {total:.3}s step-end infinite}}\
+             @keyframes rsfx-frame{{0%{{visibility:visible}}{shown:.4}%{{visibility:hidden}}}}"
+        )
+    }
+
+    fn delay(&self, idx: usize) -> String {
+        format!("animation-delay:{:.3}s", idx as f64 * self.frame_secs)
+    }
+}
+
+/// Writes an SVG image: one `<rect>` per color run, with each cell as two
+/// stacked 1x1 pixels scaled by `scale`, and stretched vertically by
+/// `pixel_aspect`. Animations stack one group per frame.
+pub struct SvgWriter {
+    out: String,
+    cols: u32,
+    animation: Option<Animation>,
+    frames: usize,
+}
+
+impl SvgWriter {
+    pub fn new(cols: u32, rows: u32, scale: u32, pixel_aspect: f64, animation: Option<Animation>) -> Self {
+        let (width, height) = (cols, rows * 2);
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {width} {height}\" preserveAspectRatio=\"none\" shape-rendering=\"crispEdges\">\n",
+            width * scale,
+            css_length(height as f64 * scale as f64 * pixel_aspect)
+        );
+        if let Some(animation) = &animation {
+            let _ = writeln!(out, "<style>{}</style>", animation.css(".f"));
+        }
+        Self { out, cols, animation, frames: 0 }
+    }
+
+    pub fn frame(&mut self, cells: &[Cell]) {
+        match &self.animation {
+            Some(animation) => {
+                let _ = writeln!(self.out, "<g class=\"f\" style=\"{}\">", animation.delay(self.frames));
+            }
+            None => self.out.push_str("<g>\n"),
+        }
+        for (y, runs) in color_runs(cells, self.cols).iter().enumerate() {
+            let mut x = 0;
+            for &(color, len) in runs {
+                // Fully transparent runs are left out
+                if color[3] != 0 {
+                    let _ = writeln!(
+                        self.out,
+                        "<rect x=\"{x}\" y=\"{y}\" width=\"{len}\" height=\"1\" fill=\"{}\"/>",
+                        css_color(color)
+                    );
+                }
+                x += len;
+            }
+        }
+        self.out.push_str("</g>\n");
+        self.frames += 1;
+    }
+
+    pub fn finish(mut self) -> String {
+        self.out.push_str("</svg>\n");
+        self.out
+    }
+}
+
+/// Writes a standalone HTML page: each pixel row is a `<div>` of `<span>`s, one
+/// per color run. Pixels are `scale` CSS pixels wide and `scale * pixel_aspect`
+/// tall. Animations stack one block per frame.
+pub struct HtmlWriter {
+    out: String,
+    cols: u32,
+    scale: u32,
+    animation: Option<Animation>,
+    frames: usize,
+}
+
+impl HtmlWriter {
+    pub fn new(title: &str, cols: u32, rows: u32, scale: u32, pixel_aspect: f64, animation: Option<Animation>) -> Self {
+        let row_height = css_length(scale as f64 * pixel_aspect);
+        let (width, height) = (cols * scale, css_length(rows as f64 * 2.0 * scale as f64 * pixel_aspect));
+        let mut out = String::new();
+        let _ = write!(
+            out,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\
+             .rsfx{{position:relative;width:{width}px;height:{height}px}}\
+             .rsfx .f{{position:absolute;top:0;left:0}}\
+             .rsfx div div{{display:flex;height:{row_height}px}}\
+             .rsfx span{{display:block;height:{row_height}px}}",
+            escape_html(title)
+        );
+        if let Some(animation) = &animation {
+            out.push_str(&animation.css(".rsfx .f"));
+        }
+        out.push_str("</style>\n</head>\n<body>\n<div class=\"rsfx\">\n");
+        Self { out, cols, scale, animation, frames: 0 }
+    }
+
+    pub fn frame(&mut self, cells: &[Cell]) {
+        match &self.animation {
+            Some(animation) => {
+                let _ = writeln!(self.out, "<div class=\"f\" style=\"{}\">", animation.delay(self.frames));
+            }
+            None => self.out.push_str("<div class=\"f\">\n"),
+        }
+        for runs in color_runs(cells, self.cols) {
+            self.out.push_str("<div>");
+            for (color, len) in runs {
+                let width = len as u32 * self.scale;
+                if color[3] == 0 {
+                    let _ = write!(self.out, "<span style=\"width:{width}px\"></span>");
+                } else {
+                    let _ = write!(self.out, "<span style=\"width:{width}px;background:{}\"></span>", css_color(color));
+                }
+            }
+            self.out.push_str("</div>\n");
+        }
+        self.out.push_str("</div>\n");
+        self.frames += 1;
+    }
+
+    pub fn finish(mut self) -> String {
+        self.out.push_str("</div>\n</body>\n</html>\n");
+        self.out
+    }
+}
+
+/// A length rounded to hundredths, without trailing zeros.
+fn css_length(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use rsfx_core::decode::RsfxReader;
+    use rsfx_core::encode::RsfxWriter;
+    use rsfx_core::format::DeltaCell;
+
+    use super::*;
+    use crate::frames::for_each_frame;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    #[test]
+    fn color_runs_merge_equal_neighbors() {
+        let (red, blue) = (Cell::from_rgba_pixels(RED, RED), Cell::from_rgba_pixels(RED, BLUE));
+        let runs = color_runs(&[red, red, blue, red, blue, blue], 3);
+        assert_eq!(
+            runs,
+            [
+                vec![(RED, 3)],
+                vec![(RED, 2), (BLUE, 1)],
+                vec![(RED, 3)],
+                vec![(RED, 1), (BLUE, 2)],
+            ]
+        );
+        assert_eq!(css_color(RED), "#ff0000");
+        assert_eq!(css_color([0, 0, 255, 0]), "rgba(0,0,255,0.000)");
+    }
+
+    /// Three 2x1 frames at 10 fps: all red, then the left cell blue, then no change.
+    fn tiny_clip() -> RsfxReader<Cursor<Vec<u8>>> {
+        let (red, blue) = (Cell::from_rgba_pixels(RED, RED), Cell::from_rgba_pixels(BLUE, BLUE));
+        let mut writer = RsfxWriter::new(Cursor::new(Vec::new()), 2, 1, 10, 30).unwrap();
+        writer.write_keyframe(&[red, red]).unwrap();
+        writer.write_delta(&[DeltaCell { x: 0, y: 0, cell: blue }]).unwrap();
+        writer.write_delta(&[]).unwrap();
+        RsfxReader::new(Cursor::new(writer.finish().unwrap().into_inner())).unwrap()
+    }
+
+    fn animation(reader: &RsfxReader<Cursor<Vec<u8>>>) -> Animation {
+        Animation { frame_secs: 1.0 / reader.fps(), frame_count: reader.len() }
+    }
+
+    #[test]
+    fn animated_svg_has_every_frame_in_its_slot() {
+        let mut reader = tiny_clip();
+        let mut svg = SvgWriter::new(2, 1, 3, 1.0, Some(animation(&reader)));
+        for_each_frame(&mut reader, |_, cells| {
+            svg.frame(cells);
+            Ok(())
+        })
+        .unwrap();
+        let svg = svg.finish();
+
+        assert!(svg.contains("width=\"6\" height=\"6\" viewBox=\"0 0 2 2\""), "{svg}");
+        assert!(svg.contains("animation:rsfx-frame 0.300s step-end infinite"), "{svg}");
+        assert_eq!(svg.matches("<g class=\"f\"").count(), 3);
+        for delay in ["0.000s", "0.100s", "0.200s"] {
+            assert!(svg.contains(&format!("<g class=\"f\" style=\"animation-delay:{delay}\">")), "{svg}");
+        }
+        // Merged runs: one rect per row for the first frame, two after the change
+        assert_eq!(svg.matches("<rect ").count(), 2 + 4 + 4);
+        assert!(svg.contains("<rect x=\"0\" y=\"0\" width=\"2\" height=\"1\" fill=\"#ff0000\"/>"));
+        assert!(svg.contains("<rect x=\"0\" y=\"1\" width=\"1\" height=\"1\" fill=\"#0000ff\"/>"));
+        assert!(svg.ends_with("</g>\n</svg>\n"));
+    }
+
+    #[test]
+    fn animated_html_has_every_frame_in_its_slot() {
+        let mut reader = tiny_clip();
+        let mut html = HtmlWriter::new("a <clip>", 2, 1, 4, 1.0, Some(animation(&reader)));
+        for_each_frame(&mut reader, |_, cells| {
+            html.frame(cells);
+            Ok(())
+        })
+        .unwrap();
+        let html = html.finish();
+
+        assert!(html.contains("<title>a &lt;clip&gt;</title>"));
+        assert!(html.contains(".rsfx{position:relative;width:8px;height:8px}"), "{html}");
+        assert!(html.contains("animation:rsfx-frame 0.300s step-end infinite"), "{html}");
+        assert_eq!(html.matches("<div class=\"f\"").count(), 3);
+        for delay in ["0.000s", "0.100s", "0.200s"] {
+            assert!(html.contains(&format!("<div class=\"f\" style=\"animation-delay:{delay}\">")), "{html}");
+        }
+        let changed_row = "<div><span style=\"width:4px;background:#0000ff\"></span>\
+                           <span style=\"width:4px;background:#ff0000\"></span></div>";
+        assert_eq!(html.matches(changed_row).count(), 4);
+    }
+
+    #[test]
+    fn still_frames_have_no_animation() {
+        let mut reader = tiny_clip();
+        let cells = reader.reconstruct_frame(1).unwrap();
+        let mut svg = SvgWriter::new(2, 1, 1, 1.0, None);
+        svg.frame(&cells);
+        let svg = svg.finish();
+        assert!(!svg.contains("<style>"));
+        assert_eq!(svg.matches("<g>").count(), 1);
+    }
+}
@@ -1,13 +1,46 @@
 use std::io::Cursor;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use anyhow::Context;
-use rodio::{Decoder, OutputStream, Sink};
+use clap::ValueEnum;
+use rodio::{Decoder, OutputStream, Sink, Source};
+use rsfx_core::audio::{wrap_pcm_as_wav, WAV_HEADER_SIZE};
+
+/// What to do with a track that has more channels than the speakers are likely
+/// to: rodio keeps only the first channels a device can play, which would drop
+/// the center (dialogue) and surrounds of a 5.1 mix.
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum Downmix {
+    /// Mix anything over two channels down to stereo
+    #[default]
+    Stereo,
+    /// Mix every track down to one channel
+    Mono,
+    /// Play the channels as they are
+    Off,
+}
+
+impl Downmix {
+    /// The number of channels a track of `channels` is played with.
+    fn target(self, channels: u16) -> u16 {
+        match self {
+            Downmix::Stereo => channels.min(2),
+            Downmix::Mono => 1,
+            Downmix::Off => channels,
+        }
+    }
+}
 
 pub struct AudioPlayer {
     _stream: OutputStream,
     sink: Sink,
     start_time: Option<Instant>,
+    /// The loaded track as WAV, kept so `restart` can queue it again and for `levels`
+    wav: Option<Vec<u8>>,
+    /// Length of one pass of a looping track
+    loop_length: Option<Duration>,
+    sample_rate: u32,
+    channels: u16,
 }
 
 impl AudioPlayer {
@@ -20,19 +53,94 @@ impl AudioPlayer {
             _stream: stream,
             sink,
             start_time: None,
+            wav: None,
+            loop_length: None,
+            sample_rate: 0,
+            channels: 0,
         })
     }
 
     /// Load raw PCM s16le data and prepare for playback.
-    pub fn load_pcm(&self, pcm_data: Vec<u8>, sample_rate: u32, channels: u16) -> anyhow::Result<()> {
+    /// `channels` is taken as-is from the file (1 = mono, 2 = stereo, ...), and
+    /// mixed down as `downmix` says (see `mix_down`).
+    ///
+    /// With `loop_secs`, the track will repeat along with the video: it's made
+    /// exactly that long and its end crossfaded into its start (see `loop_seamlessly`),
+    /// and the next pass is queued behind the current one.
+    pub fn load_pcm(
+        &mut self,
+        mut pcm_data: Vec<u8>,
+        sample_rate: u32,
+        channels: u16,
+        loop_secs: Option<f64>,
+        downmix: Downmix,
+    ) -> anyhow::Result<()> {
+        if channels == 0 || sample_rate == 0 {
+            anyhow::bail!("invalid audio format: {channels} channel(s) at {sample_rate} Hz");
+        }
+        let mixed = downmix.target(channels);
+        if mixed != channels {
+            pcm_data = mix_down(&pcm_data, channels, mixed);
+        }
+        let channels = mixed;
+        if let Some(secs) = loop_secs {
+            let frames = (secs * sample_rate as f64).round() as usize;
+            loop_seamlessly(&mut pcm_data, channels, frames, sample_rate as usize * LOOP_CROSSFADE_MS / 1000);
+            self.loop_length = Some(Duration::from_secs_f64(frames as f64 / sample_rate as f64));
+        }
         // Wrap PCM in a WAV header so rodio's Decoder can read it
-        let wav_data = wrap_pcm_as_wav(pcm_data, sample_rate, channels);
-        let cursor = Cursor::new(wav_data);
-        let source = Decoder::new(cursor).context("failed to decode audio")?;
+        let wav_data = wrap_pcm_as_wav(&pcm_data, sample_rate, channels, 16)?;
+        let passes = if loop_secs.is_some() { 2 } else { 1 };
+        for _ in 0..passes {
+            let source = Decoder::new(Cursor::new(wav_data.clone())).context("failed to decode audio")?;
+            self.sink.append(source);
+        }
+        self.wav = Some(wav_data);
+        self.sample_rate = sample_rate;
+        self.channels = channels;
+        Ok(())
+    }
+
+    /// Move on to the next pass of a looping track, when the video starts over.
+    /// That pass is already playing straight on from the last one, so this only
+    /// queues the pass after it and winds the clock back by one loop.
+    pub fn restart(&mut self) -> anyhow::Result<()> {
+        let (Some(wav), Some(loop_length)) = (&self.wav, self.loop_length) else {
+            return Ok(());
+        };
+        let source = Decoder::new(Cursor::new(wav.clone())).context("failed to decode audio")?;
+        self.sink.append(source);
+        if let Some(start) = &mut self.start_time {
+            *start += loop_length;
+        }
+        Ok(())
+    }
+
+    /// Jump to `secs` into the track (the current pass of a looping one) and play
+    /// on from there.
+    pub fn seek(&mut self, secs: f64) -> anyhow::Result<()> {
+        let Some(wav) = &self.wav else { return Ok(()) };
+        let pos = Duration::from_secs_f64(secs.max(0.0));
+        self.sink.clear();
+        let mut source = Decoder::new(Cursor::new(wav.clone())).context("failed to decode audio")?;
+        source.try_seek(pos).map_err(|e| anyhow::anyhow!("failed to seek audio: {e}"))?;
         self.sink.append(source);
+        if self.loop_length.is_some() {
+            // Keep the next pass queued behind this one, as `load_pcm` does
+            let source = Decoder::new(Cursor::new(wav.clone())).context("failed to decode audio")?;
+            self.sink.append(source);
+        }
+        self.start_time = Instant::now().checked_sub(pos);
+        self.sink.play();
         Ok(())
     }
 
+    /// Raise or lower the volume by `step`, where 1 is the track's own level,
+    /// keeping it between silent and twice that.
+    pub fn change_volume(&mut self, step: f32) {
+        self.sink.set_volume((self.sink.volume() + step).clamp(0.0, 2.0));
+    }
+
     /// Start playback and record the start time.
     pub fn play(&mut self) {
         self.start_time = Some(Instant::now());
@@ -46,33 +154,198 @@ impl AudioPlayer {
             .unwrap_or(0.0)
     }
 
+    /// RMS level of each channel over the `window` of the track ending at `secs`,
+    /// from 0 (silence) to 1 (full scale). Empty if no track is loaded.
+    pub fn levels(&self, secs: f64, window: Duration) -> Vec<f32> {
+        let Some(wav) = &self.wav else { return Vec::new() };
+        let frame_at = |t: f64| (t.max(0.0) * self.sample_rate as f64) as usize;
+        let end = frame_at(secs);
+        let start = end.saturating_sub(frame_at(window.as_secs_f64()));
+        pcm_levels(&wav[WAV_HEADER_SIZE..], self.channels, start, end)
+    }
+
+    /// Whether everything queued has played out.
+    pub fn is_done(&self) -> bool {
+        self.sink.empty()
+    }
+
     pub fn stop(&self) {
         self.sink.stop();
     }
 }
 
-/// Wrap raw PCM s16le data in a minimal WAV header.
-fn wrap_pcm_as_wav(pcm: Vec<u8>, sample_rate: u32, channels: u16) -> Vec<u8> {
-    let data_len = pcm.len() as u32;
-    let bits_per_sample: u16 = 16;
-    let byte_rate = sample_rate * channels as u32 * (bits_per_sample as u32 / 8);
-    let block_align = channels * (bits_per_sample / 8);
-    let file_size = 36 + data_len;
-
-    let mut wav = Vec::with_capacity(44 + pcm.len());
-    wav.extend_from_slice(b"RIFF");
-    wav.extend_from_slice(&file_size.to_le_bytes());
-    wav.extend_from_slice(b"WAVE");
-    wav.extend_from_slice(b"fmt ");
-    wav.extend_from_slice(&16u32.to_le_bytes()); // chunk size
-    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM format
-    wav.extend_from_slice(&channels.to_le_bytes());
-    wav.extend_from_slice(&sample_rate.to_le_bytes());
-    wav.extend_from_slice(&byte_rate.to_le_bytes());
-    wav.extend_from_slice(&block_align.to_le_bytes());
-    wav.extend_from_slice(&bits_per_sample.to_le_bytes());
-    wav.extend_from_slice(b"data");
-    wav.extend_from_slice(&data_len.to_le_bytes());
-    wav.extend(pcm);
-    wav
+/// Length of the crossfade at the seam of a looping track.
+const LOOP_CROSSFADE_MS: usize = 10;
+
+/// Prepare s16le `pcm` to repeat back to back without a click: make it `frames`
+/// sample frames long and blend the `fade` frames that follow (audio past the
+/// end, or silence) into its start. The end of each pass then runs straight into
+/// what it used to be followed by, which fades into the real start.
+fn loop_seamlessly(pcm: &mut Vec<u8>, channels: u16, frames: usize, fade: usize) {
+    let frame_size = channels as usize * 2;
+    let fade = fade.min(frames / 2);
+    pcm.resize((frames + fade) * frame_size, 0);
+    for i in 0..fade {
+        let t = i as f32 / fade as f32;
+        for c in 0..channels as usize {
+            let head_off = i * frame_size + c * 2;
+            let tail_off = (frames + i) * frame_size + c * 2;
+            let head = i16::from_le_bytes([pcm[head_off], pcm[head_off + 1]]) as f32;
+            let tail = i16::from_le_bytes([pcm[tail_off], pcm[tail_off + 1]]) as f32;
+            let mixed = (head * t + tail * (1.0 - t)).round() as i16;
+            pcm[head_off..head_off + 2].copy_from_slice(&mixed.to_le_bytes());
+        }
+    }
+    pcm.truncate(frames * frame_size);
+}
+
+/// Gain of a center or surround channel folded into the front left and right
+/// (-3 dB), as in the usual ITU downmix.
+const FOLD_GAIN: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// How much each of `channels` input channels feeds each of `to` (1 or 2) output
+/// channels, one row per output.
+///
+/// 5.1 and 7.1 are taken in WAV order (FL FR FC LFE BL BR [SL SR]): the fronts go
+/// to their side, the center to both at `FOLD_GAIN`, the surrounds to their side
+/// at `FOLD_GAIN`, and the LFE is dropped. Any other layout is split by
+/// alternating channels, even ones to the left and odd ones to the right. Mono
+/// sums both sides. Each row is scaled to add up to 1, so the mix can't clip.
+fn mix_matrix(channels: usize, to: usize) -> Vec<Vec<f32>> {
+    let (l, r, c, lfe) = ((1.0, 0.0), (0.0, 1.0), (FOLD_GAIN, FOLD_GAIN), (0.0, 0.0));
+    let (sl, sr) = ((FOLD_GAIN, 0.0), (0.0, FOLD_GAIN));
+    let sides: Vec<(f32, f32)> = match channels {
+        6 => vec![l, r, c, lfe, sl, sr],
+        8 => vec![l, r, c, lfe, sl, sr, sl, sr],
+        _ => (0..channels).map(|i| if i % 2 == 0 || channels == 1 { l } else { r }).collect(),
+    };
+    let rows: Vec<Vec<f32>> = if to == 1 {
+        vec![sides.iter().map(|(left, right)| left + right).collect()]
+    } else {
+        vec![sides.iter().map(|s| s.0).collect(), sides.iter().map(|s| s.1).collect()]
+    };
+    rows.into_iter()
+        .map(|row| {
+            let total: f32 = row.iter().sum();
+            row.iter().map(|gain| gain / total.max(f32::EPSILON)).collect()
+        })
+        .collect()
+}
+
+/// Mix s16le `pcm` of `channels` interleaved channels down to `to` (1 or 2),
+/// with the gains of `mix_matrix`.
+fn mix_down(pcm: &[u8], channels: u16, to: u16) -> Vec<u8> {
+    let matrix = mix_matrix(channels as usize, to as usize);
+    let frame_size = channels as usize * 2;
+    let mut out = Vec::with_capacity(pcm.len() / frame_size * to as usize * 2);
+    for frame in pcm.chunks_exact(frame_size) {
+        for row in &matrix {
+            let mixed: f32 = row
+                .iter()
+                .zip(frame.chunks_exact(2))
+                .map(|(gain, sample)| gain * i16::from_le_bytes([sample[0], sample[1]]) as f32)
+                .sum();
+            out.extend_from_slice(&(mixed.round() as i16).to_le_bytes());
+        }
+    }
+    out
+}
+
+/// RMS level of each channel of s16le `pcm` over sample frames `start..end`
+/// (clamped to the data), from 0 to 1.
+fn pcm_levels(pcm: &[u8], channels: u16, start: usize, end: usize) -> Vec<f32> {
+    let channels = channels as usize;
+    let frame_size = channels * 2;
+    let total = pcm.len() / frame_size.max(1);
+    let (start, end) = (start.min(total), end.min(total));
+    let mut sums = vec![0f64; channels];
+    for frame in pcm[start * frame_size..end * frame_size].chunks_exact(frame_size) {
+        for (sum, sample) in sums.iter_mut().zip(frame.chunks_exact(2)) {
+            let v = i16::from_le_bytes([sample[0], sample[1]]) as f64 / i16::MAX as f64;
+            *sum += v * v;
+        }
+    }
+    let count = (end - start).max(1) as f64;
+    sums.iter().map(|sum| (sum / count).sqrt().min(1.0) as f32).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::Source;
+
+    #[test]
+    fn mono_wav_header() {
+        // 1 second of mono s16le at 44.1 kHz
+        let pcm = vec![0u8; 44100 * 2];
+        let wav = wrap_pcm_as_wav(&pcm, 44100, 1, 16).unwrap();
+
+        assert_eq!(u16::from_le_bytes([wav[22], wav[23]]), 1); // channels
+        assert_eq!(u32::from_le_bytes(wav[28..32].try_into().unwrap()), 44100 * 2); // byte rate
+        assert_eq!(u16::from_le_bytes([wav[32], wav[33]]), 2); // block align
+
+        let source = Decoder::new(Cursor::new(wav)).unwrap();
+        assert_eq!(source.channels(), 1);
+        assert_eq!(source.sample_rate(), 44100);
+        assert_eq!(source.count(), 44100);
+    }
+
+    #[test]
+    fn loop_seam_is_continuous() {
+        // Mono ramp 0, 1, 2, ... 99: loop it every 80 frames with a 10-frame fade
+        let mut pcm: Vec<u8> = (0..100i16).flat_map(|v| v.to_le_bytes()).collect();
+        loop_seamlessly(&mut pcm, 1, 80, 10);
+        let samples: Vec<i16> = pcm.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+
+        assert_eq!(samples.len(), 80);
+        // The last sample (79) runs on into 80, which starts the fade back to 10, 11, ...
+        assert_eq!(samples[0], 80);
+        assert_eq!(samples[5], 45);
+        assert_eq!(samples[10..], (10..80).collect::<Vec<i16>>()[..]);
+
+        // Short tracks are padded with silence
+        let mut short = vec![1, 0, 1, 0];
+        loop_seamlessly(&mut short, 1, 4, 0);
+        assert_eq!(short, [1, 0, 1, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn surround_mixes_down() {
+        let samples =
+            |pcm: Vec<u8>| -> Vec<i16> { pcm.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect() };
+        // Three 5.1 frames (FL FR FC LFE BL BR): front left only, center only, LFE only
+        let frames: [[i16; 6]; 3] = [[10000, 0, 0, 0, 0, 0], [0, 0, 10000, 0, 0, 0], [0, 0, 0, 10000, 0, 0]];
+        let pcm: Vec<u8> = frames.iter().flatten().flat_map(|v| v.to_le_bytes()).collect();
+
+        // Each side is 1 + 0.707 + 0.707 = 2.414 before scaling
+        let stereo = samples(mix_down(&pcm, 6, 2));
+        assert_eq!(stereo, [4142, 0, 2929, 2929, 0, 0]);
+        // Mono sums both sides: 2 * 2.414
+        let mono = samples(mix_down(&pcm, 6, 1));
+        assert_eq!(mono, [2071, 2929, 0]);
+
+        // Other layouts alternate left and right, and full scale stays in range
+        let pcm: Vec<u8> =
+            [300i16, 600, 900, i16::MAX, i16::MAX, i16::MAX].iter().flat_map(|v| v.to_le_bytes()).collect();
+        assert_eq!(samples(mix_down(&pcm, 3, 2)), [600, 600, i16::MAX, i16::MAX]);
+
+        assert_eq!(Downmix::Stereo.target(6), 2);
+        assert_eq!(Downmix::Stereo.target(1), 1);
+        assert_eq!(Downmix::Off.target(6), 6);
+    }
+
+    #[test]
+    fn levels_per_channel() {
+        // Left at full scale (alternating sign), right silent, for 4 frames
+        let mut pcm = Vec::new();
+        for i in 0..4 {
+            let left = if i % 2 == 0 { i16::MAX } else { -i16::MAX };
+            pcm.extend_from_slice(&left.to_le_bytes());
+            pcm.extend_from_slice(&0i16.to_le_bytes());
+        }
+        assert_eq!(pcm_levels(&pcm, 2, 0, 4), vec![1.0, 0.0]);
+        // Windows past the end are clamped
+        assert_eq!(pcm_levels(&pcm, 2, 2, 100), vec![1.0, 0.0]);
+        assert_eq!(pcm_levels(&pcm, 2, 10, 20), vec![0.0, 0.0]);
+    }
 }
@@ -0,0 +1,49 @@
+use std::io::Write;
+
+/// Terminal rows at the bottom kept for the control bar.
+pub const BAR_ROWS: u16 = 1;
+
+/// Splits the terminal into the video area on top and the control bar below it.
+///
+/// The video area is set as the scroll region with origin mode on, so while the
+/// video (or anything drawn over it, like subtitles) is drawn the cursor can't
+/// leave it: rows past its bottom clamp to its last row and never reach the bar.
+/// The bar itself is drawn with origin mode switched off for the moment.
+#[derive(Clone, Copy)]
+pub struct Layout {
+    /// Rows available to the video, from the top
+    pub video_rows: u16,
+    /// Terminal row of the bar (1-indexed)
+    bar_row: u16,
+}
+
+impl Layout {
+    pub fn new(term_rows: u16) -> Self {
+        let video_rows = term_rows.saturating_sub(BAR_ROWS).max(1);
+        Self { video_rows, bar_row: video_rows + 1 }
+    }
+
+    /// Terminal row of the bar (1-indexed).
+    pub fn bar_row(&self) -> u16 {
+        self.bar_row
+    }
+
+    /// Confine cursor movement to the video area. Row numbers stay the same,
+    /// since it starts at the top.
+    pub fn enter(&self, buf: &mut Vec<u8>) {
+        let _ = write!(buf, "\x1b[1;{}r\x1b[?6h", self.video_rows);
+    }
+
+    /// Undo `enter`: origin mode off and the whole screen as the scroll region.
+    pub const LEAVE: &'static [u8] = b"\x1b[?6l\x1b[r";
+
+    /// Draw the bar: `draw` appends its content from the left edge, and the rest
+    /// of the row is cleared to `bg`.
+    pub fn render_bar(&self, bg: (u8, u8, u8), buf: &mut Vec<u8>, draw: impl FnOnce(&mut Vec<u8>)) {
+        buf.clear();
+        let _ = write!(buf, "\x1b[?6l\x1b[{};1H", self.bar_row);
+        draw(buf);
+        let (r, g, b) = bg;
+        let _ = write!(buf, "\x1b[48;2;{r};{g};{b}m\x1b[K\x1b[0m\x1b[?6h");
+    }
+}
@@ -1,54 +1,298 @@
 mod audio;
+mod layout;
+mod meter;
 mod render;
+mod resume;
+mod seek;
+mod source;
+mod stats;
+mod term;
 
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Write};
-use std::path::PathBuf;
+use std::io::{BufReader, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
 use anyhow::Context;
-use clap::Parser;
-use crossterm::event::{self, Event, KeyCode, KeyEvent};
+use clap::{Parser, ValueEnum};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEventKind};
 use crossterm::terminal;
 
-use rsfx_core::decode::RsfxReader;
-use rsfx_core::format::FrameType;
+use rsfx_core::decode::{Frame, RsfxReader, SequentialReader};
+use rsfx_core::format::{Cell, DeltaCell, HalfBlock, RsfxHeader, FLAG_ALPHA};
+use rsfx_core::subtitle::{self, SubtitleEntry};
+
+use crate::audio::Downmix;
+use crate::layout::Layout;
+use crate::render::Glyph;
+use crate::seek::Scrub;
+use crate::source::{FrameSource, IndexedSource};
+use crate::stats::PlaybackStats;
+use crate::term::{Capabilities, ColorMode, ColorWriter};
 
 #[derive(Parser)]
 #[command(name = "rsfx-play", about = "Play .rsfx files in the terminal")]
 struct Cli {
-    /// Path to .rsfx file
+    /// Path to .rsfx file, `-` to read a stream from stdin, or an http(s) URL
+    /// (in builds with the `http` feature)
     input: PathBuf,
+
+    /// Render at this size instead of the file's native grid, e.g. `80x24`
+    #[arg(long, value_parser = parse_size)]
+    size: Option<(u16, u16)>,
+
+    /// Height-to-width ratio of this terminal's cells (default: measured, when
+    /// the terminal reports its size in pixels). Files that record the cells
+    /// they were made for are stretched to keep their proportions here
+    #[arg(long, value_parser = parse_cell_aspect)]
+    cell_aspect: Option<f32>,
+
+    /// What to do when the video (or `--size`) doesn't fit in the terminal
+    #[arg(long, value_enum, default_value = "scale", default_value_if("no_scale", "true", "clip"))]
+    on_oversize: Oversize,
+
+    /// Old name for `--on-oversize clip`, kept for scripts that use it
+    #[arg(long, hide = true, conflicts_with = "on_oversize")]
+    no_scale: bool,
+
+    /// Color for transparent cells and the screen area around the video, as `R,G,B`
+    #[arg(long, value_parser = parse_rgb, default_value = "0,0,0")]
+    bg: (u8, u8, u8),
+
+    /// Audio output latency to compensate for, in milliseconds (positive delays
+    /// the video). Adjust during playback with `[` and `]`
+    #[arg(long, default_value = "0", allow_hyphen_values = true)]
+    audio_offset: i32,
+
+    /// Start over after the last frame (the default for files converted with --loop)
+    #[arg(long = "loop", conflicts_with = "no_loop")]
+    loop_playback: bool,
+
+    /// Play once even if the file asks to loop
+    #[arg(long)]
+    no_loop: bool,
+
+    /// Half-block character to draw with; try `upper` if the font shows gaps between
+    /// rows. `full` draws one color per cell with no background, for `--frame`
+    /// stills meant to be pasted as text
+    #[arg(long, value_enum, default_value = "lower")]
+    glyph: Glyph,
+
+    /// `--glyph` for the half-blocks only, as `rsfx-avatar` takes it
+    #[arg(long, value_enum, conflicts_with = "glyph")]
+    half: Option<HalfBlock>,
+
+    /// Print this frame (counting from 0) where the cursor is and exit, instead of
+    /// playing. Scaled down to fit when printed to a terminal; written as is
+    /// (ANSI) when redirected
+    #[arg(long)]
+    frame: Option<usize>,
+
+    /// Don't play the audio track
+    #[arg(long)]
+    no_audio: bool,
+
+    /// Don't wrap each frame in synchronized-update markers. They're only sent
+    /// to terminals that say they have the mode, so this is for ones that claim
+    /// it but handle it badly
+    #[arg(long)]
+    no_sync_output: bool,
+
+    /// Colors to draw with (default: what the terminal says it supports, or
+    /// `truecolor` if it doesn't say). `256` and `16` pick the nearest palette
+    /// colors, for terminals without 24-bit color
+    #[arg(long, value_enum)]
+    color: Option<ColorMode>,
+
+    /// Start where the last play of this file stopped, without asking. Files over
+    /// a minute long remember where they were quit; by default you're asked
+    /// whether to resume
+    #[arg(long, conflicts_with = "no_resume")]
+    resume: bool,
+
+    /// Start from the beginning, and don't remember where playback stops
+    #[arg(long)]
+    no_resume: bool,
+
+    /// Mix audio with more channels than this down before playing it (`off`
+    /// plays all of the file's channels)
+    #[arg(long, value_enum, default_value = "stereo")]
+    downmix: Downmix,
+
+    /// Start playing straight away, without the splash screen
+    #[arg(long, conflicts_with = "splash_duration")]
+    no_splash: bool,
+
+    /// How long the splash screen shows, in milliseconds (any key skips it).
+    /// Without this, clips shorter than 5 seconds start without one
+    #[arg(long)]
+    splash_duration: Option<u64>,
+}
+
+/// Policy for a video larger than the terminal.
+#[derive(Clone, Copy, ValueEnum)]
+enum Oversize {
+    /// Scale it down to fit, keeping the aspect ratio
+    Scale,
+    /// Exit with an error
+    Error,
+    /// Show only the top-left part that fits
+    Clip,
+}
+
+fn parse_cell_aspect(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(ratio) if (0.5..=5.0).contains(&ratio) => Ok(ratio),
+        _ => Err(format!("expected a cell height-to-width ratio from 0.5 to 5, got `{s}`")),
+    }
+}
+
+/// Parse a `COLSxROWS` size argument.
+fn parse_size(s: &str) -> Result<(u16, u16), String> {
+    let (cols, rows) = s
+        .split_once(['x', 'X'])
+        .ok_or_else(|| format!("expected COLSxROWS, got `{s}`"))?;
+    let cols: u16 = cols.trim().parse().map_err(|_| format!("invalid column count `{cols}`"))?;
+    let rows: u16 = rows.trim().parse().map_err(|_| format!("invalid row count `{rows}`"))?;
+    if cols == 0 || rows == 0 {
+        return Err("size must be at least 1x1".into());
+    }
+    Ok((cols, rows))
+}
+
+/// Parse an `R,G,B` color argument.
+fn parse_rgb(s: &str) -> Result<(u8, u8, u8), String> {
+    let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+    let [r, g, b] = parts[..] else {
+        return Err(format!("expected R,G,B, got `{s}`"));
+    };
+    let channel = |v: &str| v.parse::<u8>().map_err(|_| format!("invalid color channel `{v}` (0-255)"));
+    Ok((channel(r)?, channel(g)?, channel(b)?))
+}
+
+/// Open a file on a web server, fetching frames as they're played.
+#[cfg(feature = "http")]
+fn open_url(url: &str) -> anyhow::Result<Box<dyn FrameSource>> {
+    let reader = rsfx_core::http::HttpReader::open(url)?;
+    Ok(Box::new(IndexedSource::new(RsfxReader::new(reader)?)))
+}
+
+#[cfg(not(feature = "http"))]
+fn open_url(url: &str) -> anyhow::Result<Box<dyn FrameSource>> {
+    anyhow::bail!("can't play {url}: this build has no HTTP support (build with `--features http`)")
+}
+
+/// Fixed parameters of a playback session.
+struct Playback {
+    /// Native grid size of the file
+    cols: u16,
+    rows: u16,
+    /// Grid size to render at, when it differs from the native one
+    scale_to: Option<(u16, u16)>,
+    /// Top-left part of the (scaled) grid that fits the terminal, when not all of it does
+    clip_to: Option<(u16, u16)>,
+    /// Unknown for streams read from stdin
+    frame_count: Option<usize>,
+    frame_duration: Duration,
+    /// Color that transparent cells are composited over
+    background: (u8, u8, u8),
+    /// Initial audio latency compensation, in seconds
+    audio_offset: f64,
+    /// Video area and control bar
+    layout: Layout,
+    /// Start over after the last frame
+    looping: bool,
+    glyph: Glyph,
+    /// What the terminal supports; frames are wrapped in `render::SYNC_BEGIN` /
+    /// `SYNC_END` when it has synchronized output
+    caps: Capabilities,
+    /// Frame to start at instead of the first (when resuming)
+    start_frame: usize,
 }
 
 fn main() -> anyhow::Result<()> {
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
+    if let Some(half) = cli.half {
+        cli.glyph = Glyph::Half(half);
+    }
+
+    let mut source: Box<dyn FrameSource> = if cli.input == Path::new("-") {
+        // Streams can only be read front to back
+        Box::new(SequentialReader::new(BufReader::new(std::io::stdin().lock()))?)
+    } else if let Some(url) = cli.input.to_str().filter(|s| s.starts_with("http://") || s.starts_with("https://")) {
+        open_url(url)?
+    } else {
+        let file = File::open(&cli.input)
+            .with_context(|| format!("failed to open {}", cli.input.display()))?;
+        Box::new(IndexedSource::new(RsfxReader::new(BufReader::new(file))?))
+    };
 
-    let file = File::open(&cli.input)
-        .with_context(|| format!("failed to open {}", cli.input.display()))?;
-    let mut reader = RsfxReader::new(BufReader::new(file))?;
+    let header = source.header().clone();
+    // No terminal is anywhere near 65535 cells a side
+    let (Ok(cols), Ok(rows)) = (u16::try_from(header.cols), u16::try_from(header.rows)) else {
+        anyhow::bail!("the {}x{} grid is too large to play in a terminal", header.cols, header.rows);
+    };
+    let fps = header.fps_num as f64 / header.fps_den as f64;
+    let frame_count = source.frame_count();
 
-    let cols = reader.header.cols;
-    let rows = reader.header.rows;
-    let fps = reader.fps();
-    let frame_count = reader.header.frame_count as usize;
+    // Taller cells than the file was made for need fewer rows, and vice versa
+    let native = match (header.cell_aspect, terminal_cell_aspect(cli.cell_aspect)) {
+        (0, _) | (_, None) => (cols, rows),
+        (made_for, Some(here)) => (cols, ((rows as f64 * made_for as f64 / 100.0 / here).round() as u16).max(1)),
+    };
+    let (want_cols, want_rows) = cli.size.unwrap_or(native);
+
+    if let Some(frame_idx) = cli.frame {
+        return print_frame(source.as_mut(), frame_idx, (want_cols, want_rows), cli.bg, cli.glyph, cli.color);
+    }
+    if frame_count == Some(0) {
+        return play_audio_only(source.as_mut(), &cli);
+    }
 
     // Check terminal size
     let (term_cols, term_rows) = terminal::size()?;
-    if term_cols < cols || term_rows < rows {
-        eprintln!(
-            "Warning: terminal is {}x{} but video needs {}x{}. Resize your terminal for best results.",
-            term_cols, term_rows, cols, rows
-        );
-    }
+    // The bottom row is the control bar, so the video gets the rest
+    let layout = Layout::new(term_rows);
+    let video_rows = layout.video_rows;
+    let resized = Some((want_cols, want_rows)).filter(|&size| size != (cols, rows));
+    let (scale_to, clip_to) = if term_cols >= want_cols && video_rows >= want_rows {
+        (resized, None)
+    } else {
+        match cli.on_oversize {
+            Oversize::Scale => {
+                let fit = fit_size(want_cols, want_rows, term_cols, video_rows);
+                eprintln!(
+                    "Terminal has room for {}x{}; scaling the {}x{} video down to {}x{} (see --on-oversize).",
+                    term_cols, video_rows, want_cols, want_rows, fit.0, fit.1
+                );
+                (Some(fit), None)
+            }
+            Oversize::Error => anyhow::bail!(
+                "terminal has room for {term_cols}x{video_rows} but the video needs {want_cols}x{want_rows}; \
+                 resize the terminal or pass --on-oversize scale or clip"
+            ),
+            Oversize::Clip => {
+                let clip = (want_cols.min(term_cols), want_rows.min(video_rows));
+                eprintln!(
+                    "Terminal has room for {}x{}; showing the top-left {}x{} of the {}x{} video.",
+                    term_cols, video_rows, clip.0, clip.1, want_cols, want_rows
+                );
+                (resized, Some(clip))
+            }
+        }
+    };
+
+    let looping = !cli.no_loop && (cli.loop_playback || header.loop_hint());
+    // A looping track is fitted to the video's length (only files, whose length is known, can loop)
+    let loop_secs = frame_count.filter(|_| looping).map(|count| count as f64 / fps);
 
     // Load audio
     let mut audio_player = None;
-    if reader.header.audio_length > 0 {
-        let pcm = reader.read_audio()?;
+    if let Some(pcm) = source.take_audio()?.filter(|_| !cli.no_audio) {
+        let header = source.header();
         match audio::AudioPlayer::new() {
-            Ok(player) => {
-                player.load_pcm(pcm, reader.header.audio_sample_rate, reader.header.audio_channels)?;
+            Ok(mut player) => {
+                player.load_pcm(pcm, header.audio_sample_rate, header.audio_channels, loop_secs, cli.downmix)?;
                 audio_player = Some(player);
             }
             Err(e) => {
@@ -57,136 +301,648 @@ fn main() -> anyhow::Result<()> {
         }
     }
 
+    let subtitles = source.subtitles()?;
+    let title = title_line(&cli.input, source.header(), frame_count);
+
+    // Long files remember where they were stopped; loops and streams don't
+    let remember = !cli.no_resume
+        && !looping
+        && source.can_seek()
+        && frame_count.is_some_and(|count| count as f64 / fps >= resume::MIN_LENGTH.as_secs_f64());
+    let saved = remember
+        .then(|| resume::saved_position(&cli.input))
+        .flatten()
+        .filter(|&frame| frame_count.is_some_and(|count| frame < count));
+    let start_frame = match saved {
+        Some(frame) if cli.resume => frame,
+        Some(frame) if std::io::stdin().is_terminal() => {
+            let time = seek::format_time((frame as f64 / fps) as u64);
+            if resume::ask(&time)? {
+                frame
+            } else {
+                0
+            }
+        }
+        _ => 0,
+    };
+
     // Set up panic hook for terminal cleanup
     let original_hook = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |info| {
-        let _ = cleanup_terminal();
+        cleanup_terminal();
         original_hook(info);
     }));
 
     // Enter alternate screen, raw mode, hide cursor
     terminal::enable_raw_mode()?;
+    let caps = Capabilities::detect(cli.color, !cli.no_sync_output);
     let stdout = std::io::stdout();
-    let mut stdout = BufWriter::with_capacity(256 * 1024, stdout.lock());
-    stdout.write_all(b"\x1b[?1049h")?; // enter alternate screen
+    let mut stdout = BufWriter::with_capacity(256 * 1024, ColorWriter::new(stdout.lock(), caps.color));
+    if caps.alt_screen {
+        stdout.write_all(b"\x1b[?1049h")?; // enter alternate screen
+    }
     stdout.write_all(b"\x1b[?25l")?; // hide cursor
+    stdout.write_all(MOUSE_ON)?;
     stdout.flush()?;
 
-    // Show splash screen
-    show_splash(&mut stdout, term_cols, term_rows)?;
-
-    // Start audio
-    if let Some(ref mut player) = audio_player {
-        player.play();
-    }
-
-    let frame_duration = Duration::from_secs_f64(1.0 / fps);
-    let playback_start = Instant::now();
-    let mut render_buf = Vec::with_capacity(256 * 1024);
-    let mut current_cells: Vec<rsfx_core::format::Cell> = Vec::new();
-
-    let result = run_playback_loop(
-        &mut reader,
-        &mut stdout,
-        &mut render_buf,
-        &mut current_cells,
-        &audio_player,
+    let playback = Playback {
         cols,
         rows,
+        scale_to,
+        clip_to,
         frame_count,
-        frame_duration,
-        playback_start,
-    );
+        frame_duration: Duration::from_secs_f64(1.0 / fps),
+        background: cli.bg,
+        audio_offset: cli.audio_offset as f64 / 1000.0,
+        layout,
+        looping,
+        glyph: cli.glyph,
+        caps,
+        start_frame,
+    };
+
+    // The splash would outlast short clips, so they skip it unless asked for one
+    let short = frame_count.is_some_and(|count| (count as f64 / fps) < SPLASH_MIN_CLIP.as_secs_f64());
+    let splash = match cli.splash_duration {
+        _ if cli.no_splash => Duration::ZERO,
+        Some(ms) => Duration::from_millis(ms),
+        None if short => Duration::ZERO,
+        None => SPLASH_DURATION,
+    };
+
+    // Everything up to cleanup runs in here so an error still restores the terminal.
+    // Gives the frame playback stopped at, or None if it never started
+    let result = show_splash(&mut stdout, term_cols, term_rows, splash).and_then(|keep_going| {
+        if !keep_going {
+            return Ok(None);
+        }
+
+        // Clear to the background color so margins around the video are filled
+        let (bg_r, bg_g, bg_b) = cli.bg;
+        write!(stdout, "\x1b[48;2;{bg_r};{bg_g};{bg_b}m\x1b[2J")?;
+        let mut layout_buf = Vec::new();
+        layout.enter(&mut layout_buf);
+        stdout.write_all(&layout_buf)?;
+        stdout.flush()?;
+
+        // Start audio
+        if let Some(ref mut player) = audio_player {
+            player.play();
+        }
+
+        let playback_start = Instant::now();
+        run_playback_loop(
+            source.as_mut(),
+            &mut stdout,
+            &mut audio_player,
+            &playback,
+            &subtitles,
+            &title,
+            playback_start,
+        )
+        .map(Some)
+    });
 
     // Cleanup
     if let Some(ref player) = audio_player {
         player.stop();
     }
     stdout.write_all(b"\x1b[0m")?; // reset colors
+    stdout.write_all(Layout::LEAVE)?;
+    stdout.write_all(MOUSE_OFF)?;
     stdout.write_all(b"\x1b[?25h")?; // show cursor
-    stdout.write_all(b"\x1b[?1049l")?; // leave alternate screen
+    if caps.alt_screen {
+        stdout.write_all(b"\x1b[?1049l")?; // leave alternate screen
+    } else {
+        stdout.write_all(b"\x1b[2J\x1b[H")?; // don't leave the last frame behind
+    }
     stdout.flush()?;
     terminal::disable_raw_mode()?;
 
-    result
+    if let (true, Some(stopped_at), Some(count)) = (remember, result?, frame_count) {
+        if let Err(e) = resume::save_position(&cli.input, stopped_at, count, 1.0 / fps) {
+            eprintln!("Warning: could not save the playback position: {e:#}");
+        }
+    }
+    Ok(())
 }
 
-fn run_playback_loop<R: std::io::Read + std::io::Seek>(
-    reader: &mut RsfxReader<R>,
-    stdout: &mut impl Write,
-    render_buf: &mut Vec<u8>,
-    current_cells: &mut Vec<rsfx_core::format::Cell>,
-    audio_player: &Option<audio::AudioPlayer>,
-    cols: u16,
-    rows: u16,
-    frame_count: usize,
+/// The status line shown when playback starts: file name, grid size, frame rate,
+/// duration (when known) and audio format.
+fn title_line(input: &Path, header: &RsfxHeader, frame_count: Option<usize>) -> String {
+    let name = match input.file_name() {
+        _ if input == Path::new("-") => "stdin".into(),
+        Some(name) => name.to_string_lossy(),
+        None => input.to_string_lossy(),
+    };
+    let fps = header.fps_num as f64 / header.fps_den as f64;
+    let mut parts = vec![name.into_owned(), format!("{}x{}", header.cols, header.rows), format!("{fps:.2} fps")];
+    if let Some(count) = frame_count.filter(|&count| count > 0) {
+        let secs = (count as f64 / fps).round() as u64;
+        parts.push(seek::format_time(secs));
+    }
+    parts.push(match (header.audio_length, header.audio_channels) {
+        (0, _) => "no audio".into(),
+        (_, 1) => format!("{} Hz mono", header.audio_sample_rate),
+        (_, 2) => format!("{} Hz stereo", header.audio_sample_rate),
+        (_, channels) => format!("{} Hz {channels} ch", header.audio_sample_rate),
+    });
+    parts.join(" | ")
+}
+
+/// How long the title line stays up, including its fade.
+const TITLE_DURATION: Duration = Duration::from_secs(3);
+/// Final stretch of `TITLE_DURATION` over which the title fades out.
+const TITLE_FADE: Duration = Duration::from_millis(600);
+
+/// Current playback time in seconds. Audio is the master clock when present,
+/// shifted back by the output latency.
+fn clock(audio_player: &Option<audio::AudioPlayer>, audio_offset: f64, playback_start: Instant) -> f64 {
+    match audio_player {
+        Some(player) => player.position_secs() - audio_offset,
+        None => playback_start.elapsed().as_secs_f64(),
+    }
+}
+
+/// How far one `[` / `]` press moves the audio offset, in seconds.
+const AUDIO_OFFSET_STEP: f64 = 0.010;
+/// How much one scroll wheel step changes the volume.
+const VOLUME_STEP: f32 = 0.05;
+/// Stretch of audio the VU meter averages over.
+const METER_WINDOW: Duration = Duration::from_millis(50);
+
+/// Continue playback from frame `target`: the source from its keyframe, and the
+/// clock and audio from its time. Returns the keyframe's index and the new
+/// playback start.
+fn seek_to(
+    source: &mut dyn FrameSource,
+    audio_player: &mut Option<audio::AudioPlayer>,
+    target: usize,
     frame_duration: Duration,
     playback_start: Instant,
-) -> anyhow::Result<()> {
-    for frame_idx in 0..frame_count {
+) -> anyhow::Result<(usize, Instant)> {
+    let keyframe = source.seek(target)?;
+    let secs = target as f64 * frame_duration.as_secs_f64();
+    let start = Instant::now().checked_sub(Duration::from_secs_f64(secs)).unwrap_or(playback_start);
+    if let Some(player) = audio_player {
+        player.seek(secs)?;
+    }
+    Ok((keyframe, start))
+}
+
+/// Play until the last frame or a quit key, returning the frame it stopped at.
+fn run_playback_loop(
+    source: &mut dyn FrameSource,
+    stdout: &mut impl Write,
+    audio_player: &mut Option<audio::AudioPlayer>,
+    playback: &Playback,
+    subtitles: &[SubtitleEntry],
+    title: &str,
+    mut playback_start: Instant,
+) -> anyhow::Result<usize> {
+    let Playback {
+        cols,
+        rows,
+        scale_to,
+        clip_to,
+        frame_count,
+        frame_duration,
+        background,
+        mut audio_offset,
+        layout,
+        looping,
+        glyph,
+        caps,
+        start_frame,
+    } = *playback;
+    let has_alpha = source.header().flags & FLAG_ALPHA != 0;
+    let keyframe_deltas = source.header().keyframe_deltas();
+    let erase = caps.back_color_erase;
+
+    let mut render_buf = Vec::with_capacity(256 * 1024);
+    let mut current_cells: Vec<Cell> = Vec::new();
+    // The last keyframe and the grid being built from it, for files whose deltas
+    // are relative to it (FLAG_KEYFRAME_DELTAS)
+    let mut keyframe_cells: Vec<Cell> = Vec::new();
+    let mut next_cells: Vec<Cell> = Vec::new();
+    // Scaled grids (current and last rendered) when rendering at a non-native size
+    let mut scaled_cells: Vec<Cell> = Vec::new();
+    let mut shown_cells: Vec<Cell> = Vec::new();
+    let mut scaled_deltas: Vec<DeltaCell> = Vec::new();
+    let mut clipped_cells: Vec<Cell> = Vec::new();
+    // Set when the screen no longer matches current_cells (after skipped frames)
+    let mut redraw = false;
+    let mut stats = PlaybackStats::new();
+    let mut show_stats = false;
+    // Start time of the subtitle on screen, which identifies it
+    let mut shown_subtitle: Option<u32> = None;
+    let mut subtitle_buf = Vec::new();
+    // Dismissed on the first key press or once it has faded out
+    let title_start = Instant::now();
+    let mut show_title = true;
+    let mut title_buf = Vec::new();
+    let (shown_cols, shown_rows) = clip_to.or(scale_to).unwrap_or((cols, rows));
+    let mut meter = meter::VuMeter::new();
+    let mut show_meter = false;
+    // The control bar holds the stats and meter; redrawn while either is up, and
+    // once more to clear it when they're hidden
+    let mut bar_dirty = false;
+    let mut bar_buf = Vec::new();
+    // A seek being picked with the arrows (files only), its thumbnail's size, and
+    // the frame playback is catching up to after a seek, decoding from the keyframe
+    let seek_frames = frame_count.filter(|_| source.can_seek());
+    let mut scrub: Option<Scrub> = None;
+    let (thumb_cols, thumb_rows) =
+        fit_size(cols, rows, (shown_cols / 4).max(8).min(shown_cols), (shown_rows / 3).max(1));
+    let mut thumb_cells: Vec<Cell> = Vec::new();
+    let mut thumb_buf = Vec::new();
+    let mut catch_up_to = 0usize;
+    // Whether the seek bar is being dragged with the mouse
+    let mut dragging = false;
+
+    let mut frame_idx = 0usize;
+    if start_frame > 0 {
+        (frame_idx, playback_start) = seek_to(source, audio_player, start_frame, frame_duration, playback_start)?;
+        catch_up_to = start_frame;
+    }
+    loop {
+        let frame = match source.next_frame()? {
+            Some(frame) => frame,
+            // Restart the clock and audio along with the frames
+            None if looping && frame_idx > 0 && source.rewind() => {
+                frame_idx = 0;
+                playback_start = Instant::now();
+                if let Some(player) = audio_player {
+                    player.restart()?;
+                }
+                continue;
+            }
+            None => break,
+        };
+
         // Check for input (non-blocking)
-        if event::poll(Duration::ZERO)? {
-            if let Event::Key(KeyEvent { code, .. }) = event::read()? {
-                match code {
-                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
-                    _ => {}
+        let mut commit_seek = false;
+        while event::poll(Duration::ZERO)? {
+            let key = match event::read()? {
+                Event::Key(key) => key,
+                Event::Mouse(mouse) => {
+                    let on_bar = mouse.row + 1 == layout.bar_row();
+                    match mouse.kind {
+                        // Press on the bar (files only) to pick a point, drag to
+                        // move it, and release to seek there
+                        MouseEventKind::Down(MouseButton::Left) if on_bar && seek_frames.is_some() => {
+                            let count = seek_frames.unwrap_or(0);
+                            scrub
+                                .get_or_insert_with(|| Scrub::new(frame_idx, count, frame_duration.as_secs_f64()))
+                                .point_at(mouse.column, shown_cols);
+                            dragging = true;
+                        }
+                        MouseEventKind::Drag(MouseButton::Left) if dragging => {
+                            if let Some(pending) = &mut scrub {
+                                pending.point_at(mouse.column, shown_cols);
+                            }
+                        }
+                        MouseEventKind::Up(MouseButton::Left) if dragging => {
+                            dragging = false;
+                            commit_seek = true;
+                        }
+                        MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => {
+                            if let Some(player) = audio_player {
+                                let up = mouse.kind == MouseEventKind::ScrollUp;
+                                player.change_volume(if up { VOLUME_STEP } else { -VOLUME_STEP });
+                            }
+                        }
+                        _ => {}
+                    }
+                    continue;
                 }
+                _ => continue,
+            };
+            if is_quit_key(&key) {
+                return Ok(frame_idx.max(catch_up_to));
+            }
+            if show_title {
+                show_title = false;
+                redraw = true;
+            }
+            match key.code {
+                // Nudge A/V sync: `]` delays video relative to audio, `[` advances it
+                KeyCode::Char(']') => audio_offset += AUDIO_OFFSET_STEP,
+                KeyCode::Char('[') => audio_offset -= AUDIO_OFFSET_STEP,
+                KeyCode::Char('s') => {
+                    show_stats = !show_stats;
+                    bar_dirty = true;
+                }
+                KeyCode::Char('v') if audio_player.is_some() => {
+                    show_meter = !show_meter;
+                    bar_dirty = true;
+                }
+                KeyCode::Left | KeyCode::Right if seek_frames.is_some() => {
+                    let step = (seek::SEEK_STEP.as_secs_f64() / frame_duration.as_secs_f64()).round() as isize;
+                    let count = seek_frames.unwrap_or(0);
+                    let scrub = scrub.get_or_insert_with(|| Scrub::new(frame_idx, count, frame_duration.as_secs_f64()));
+                    scrub.step(if key.code == KeyCode::Left { -step } else { step });
+                }
+                KeyCode::Enter if scrub.is_some() => commit_seek = true,
+                _ => {}
+            }
+        }
+
+        if let Some(pending) = &mut scrub {
+            if commit_seek || (!dragging && pending.commit_due()) {
+                // Drop the frame just read and go on from the target's keyframe
+                let target = pending.target();
+                scrub = None;
+                (frame_idx, playback_start) = seek_to(source, audio_player, target, frame_duration, playback_start)?;
+                catch_up_to = target;
+                redraw = true;
+                bar_dirty = true;
+                continue;
+            }
+            if let Some(idx) = pending.preview_due() {
+                let mut cells = source.reconstruct_frame(idx)?;
+                if has_alpha {
+                    cells.iter_mut().for_each(|c| *c = c.composite(background));
+                }
+                render::scale_cells(&cells, cols, rows, thumb_cols, thumb_rows, &mut thumb_cells);
+                pending.set_preview(idx, std::mem::take(&mut thumb_cells));
             }
         }
 
         // Determine target time for this frame
-        let target_time = if let Some(ref player) = audio_player {
-            // Audio is master clock
-            player.position_secs()
-        } else {
-            playback_start.elapsed().as_secs_f64()
-        };
+        let target_time = clock(audio_player, audio_offset, playback_start);
 
         let frame_time = frame_idx as f64 * frame_duration.as_secs_f64();
 
-        // Skip frame if we're behind
-        if frame_time + frame_duration.as_secs_f64() < target_time && frame_idx + 1 < frame_count {
-            // We need to still process keyframes to keep current_cells up to date
-            if matches!(reader.frame_type(frame_idx), FrameType::Keyframe) {
-                *current_cells = reader.read_keyframe(frame_idx)?;
+        // Apply the frame to current_cells; keyframes always redraw everything
+        let mut deltas = match frame {
+            Frame::Keyframe(mut cells) => {
+                if has_alpha {
+                    cells.iter_mut().for_each(|c| *c = c.composite(background));
+                }
+                if keyframe_deltas {
+                    keyframe_cells.clone_from(&cells);
+                }
+                current_cells = cells;
+                redraw = true;
+                Vec::new()
+            }
+            Frame::Delta(mut deltas) => {
+                if has_alpha {
+                    deltas.iter_mut().for_each(|d| d.cell = d.cell.composite(background));
+                }
+                if keyframe_deltas {
+                    // Rebuild from the keyframe, then work out what changed on screen
+                    next_cells.clone_from(&keyframe_cells);
+                    apply_deltas(&mut next_cells, &deltas, cols);
+                    render::diff_cells(&current_cells, &next_cells, cols, &mut deltas);
+                    std::mem::swap(&mut current_cells, &mut next_cells);
+                } else {
+                    apply_deltas(&mut current_cells, &deltas, cols);
+                }
+                deltas
             }
+        };
+
+        // Frames between a seek's keyframe and its target are applied but not shown
+        if frame_idx < catch_up_to {
+            redraw = true;
+            frame_idx += 1;
             continue;
         }
 
-        // Decode and render frame
-        match reader.frame_type(frame_idx) {
-            FrameType::Keyframe => {
-                *current_cells = reader.read_keyframe(frame_idx)?;
-                render::render_keyframe(current_cells, cols, rows, render_buf);
-            }
-            FrameType::Delta => {
-                let deltas = reader.read_delta(frame_idx)?;
-                // Apply deltas to current_cells for future reference
-                for d in &deltas {
-                    let idx = d.y as usize * cols as usize + d.x as usize;
-                    if idx < current_cells.len() {
-                        current_cells[idx] = d.cell;
+        // Skip rendering if we're behind (never the last frame)
+        let is_last = !looping && frame_count.is_some_and(|count| frame_idx + 1 >= count);
+        if frame_time + frame_duration.as_secs_f64() < target_time && !is_last {
+            redraw = true;
+            stats.frame_skipped();
+            frame_idx += 1;
+            continue;
+        }
+
+        let subtitle = subtitle::active_at(subtitles, (frame_time * 1000.0) as u32);
+        if shown_subtitle.is_some() && shown_subtitle != subtitle.map(|s| s.start_ms) {
+            // Repaint the video under the old text
+            redraw = true;
+        }
+        shown_subtitle = subtitle.map(|s| s.start_ms);
+
+        if show_title && title_start.elapsed() >= TITLE_DURATION {
+            // Repaint the video row under it
+            show_title = false;
+            redraw = true;
+        }
+
+        match scale_to {
+            Some((dst_cols, dst_rows)) => {
+                // Re-sample the whole grid and redraw only what changed on screen
+                render::scale_cells(&current_cells, cols, rows, dst_cols, dst_rows, &mut scaled_cells);
+                let (dst_cols, dst_rows) = match clip_to {
+                    Some((clip_cols, clip_rows)) => {
+                        render::crop_cells(&scaled_cells, dst_cols, clip_cols, clip_rows, &mut clipped_cells);
+                        std::mem::swap(&mut scaled_cells, &mut clipped_cells);
+                        (clip_cols, clip_rows)
                     }
+                    None => (dst_cols, dst_rows),
+                };
+                if redraw {
+                    render::render_keyframe(&scaled_cells, dst_cols, dst_rows, glyph, erase, &mut render_buf);
+                } else {
+                    render::diff_cells(&shown_cells, &scaled_cells, dst_cols, &mut scaled_deltas);
+                    render::render_delta(&scaled_deltas, glyph, &mut render_buf);
                 }
-                render::render_delta(&deltas, render_buf);
+                std::mem::swap(&mut scaled_cells, &mut shown_cells);
             }
+            None => match clip_to {
+                Some((clip_cols, clip_rows)) if redraw => {
+                    render::crop_cells(&current_cells, cols, clip_cols, clip_rows, &mut clipped_cells);
+                    render::render_keyframe(&clipped_cells, clip_cols, clip_rows, glyph, erase, &mut render_buf);
+                }
+                Some((clip_cols, clip_rows)) => {
+                    deltas.retain(|d| d.x < clip_cols as u32 && d.y < clip_rows as u32);
+                    render::render_delta(&deltas, glyph, &mut render_buf);
+                }
+                None if redraw => render::render_keyframe(&current_cells, cols, rows, glyph, erase, &mut render_buf),
+                None => render::render_delta(&deltas, glyph, &mut render_buf),
+            },
         }
+        redraw = false;
 
-        stdout.write_all(render_buf)?;
+        if caps.sync_output {
+            stdout.write_all(render::SYNC_BEGIN)?;
+        }
+        stdout.write_all(&render_buf)?;
+        if let Some(subtitle) = subtitle {
+            // Redrawn every frame since deltas may have painted over it
+            render::render_subtitle(&subtitle.text, shown_cols, shown_rows, &mut subtitle_buf);
+            stdout.write_all(&subtitle_buf)?;
+        }
+        if show_title {
+            // Like subtitles, redrawn every frame on top of the video
+            let remaining = TITLE_DURATION.saturating_sub(title_start.elapsed());
+            let opacity = remaining.as_secs_f32() / TITLE_FADE.as_secs_f32();
+            render::render_title(title, shown_cols, opacity, &mut title_buf);
+            stdout.write_all(&title_buf)?;
+        }
+        if let Some((pending, thumb)) = scrub.as_ref().and_then(|s| Some((s, s.preview()?))) {
+            // Above the bar, centered on the marker where the video allows
+            let left = pending.marker_col(shown_cols).saturating_sub(thumb_cols / 2).min(shown_cols - thumb_cols) + 1;
+            render::render_thumbnail(thumb, thumb_cols, shown_rows - thumb_rows + 1, left, glyph, &mut thumb_buf);
+            stdout.write_all(&thumb_buf)?;
+        }
+        stats.frame_rendered(target_time - frame_time, source.decode_stats());
+        if scrub.is_some() || show_stats || show_meter || bar_dirty {
+            layout.render_bar(background, &mut bar_buf, |buf| {
+                if let Some(pending) = &scrub {
+                    pending.render_bar(shown_cols, buf);
+                    return;
+                }
+                let used = if show_stats { stats.render(buf) } else { 0 };
+                if let (true, Some(player)) = (show_meter, audio_player.as_ref()) {
+                    meter.update(&player.levels(target_time, METER_WINDOW));
+                    meter.render(shown_cols.saturating_sub(used), buf);
+                }
+            });
+            stdout.write_all(&bar_buf)?;
+            bar_dirty = false;
+        }
+        if caps.sync_output {
+            stdout.write_all(render::SYNC_END)?;
+        }
         stdout.flush()?;
 
         // Sleep until next frame
-        let elapsed = playback_start.elapsed();
-        let next_frame_time = Duration::from_secs_f64((frame_idx + 1) as f64 * frame_duration.as_secs_f64());
-        if let Some(sleep_time) = next_frame_time.checked_sub(elapsed) {
-            std::thread::sleep(sleep_time);
+        let elapsed = clock(audio_player, audio_offset, playback_start);
+        let next_frame_time = (frame_idx + 1) as f64 * frame_duration.as_secs_f64();
+        if next_frame_time > elapsed {
+            std::thread::sleep(Duration::from_secs_f64(next_frame_time - elapsed));
+        }
+        frame_idx += 1;
+    }
+
+    Ok(frame_idx)
+}
+
+/// Patch a `cols`-wide grid with changed cells; out-of-range ones are ignored.
+fn apply_deltas(cells: &mut [Cell], deltas: &[DeltaCell], cols: u16) {
+    for d in deltas {
+        let idx = d.y as usize * cols as usize + d.x as usize;
+        if let Some(cell) = cells.get_mut(idx) {
+            *cell = d.cell;
+        }
+    }
+}
+
+/// `q`, `Esc`, or Ctrl-C (which arrives as a key press in raw mode, not SIGINT).
+fn is_quit_key(key: &KeyEvent) -> bool {
+    match key.code {
+        KeyCode::Char('q') | KeyCode::Esc => true,
+        KeyCode::Char('c') => key.modifiers.contains(KeyModifiers::CONTROL),
+        _ => false,
+    }
+}
+
+/// Play a file with no frames: just its audio track, until it ends or a quit key
+/// is pressed. Without audio there's nothing to play, which is an error.
+fn play_audio_only(source: &mut dyn FrameSource, cli: &Cli) -> anyhow::Result<()> {
+    let name = cli.input.display();
+    let Some(pcm) = source.take_audio()?.filter(|_| !cli.no_audio) else {
+        anyhow::bail!("{name} has no frames to play");
+    };
+    let header = source.header();
+    let mut player =
+        audio::AudioPlayer::new().with_context(|| format!("{name} has no video, and its audio can't be played"))?;
+    player.load_pcm(pcm, header.audio_sample_rate, header.audio_channels, None, cli.downmix)?;
+    eprintln!("{}", title_line(&cli.input, header, Some(0)));
+    eprintln!("{name} has no video; playing its audio (q to stop)");
+
+    let interactive = std::io::stdin().is_terminal();
+    if interactive {
+        terminal::enable_raw_mode()?;
+    }
+    player.play();
+    let result = wait_for_audio(&player, interactive);
+    player.stop();
+    if interactive {
+        terminal::disable_raw_mode()?;
+    }
+    result
+}
+
+/// Block until `player` runs out of audio, or (`interactive`) a quit key is pressed.
+fn wait_for_audio(player: &audio::AudioPlayer, interactive: bool) -> anyhow::Result<()> {
+    const POLL: Duration = Duration::from_millis(100);
+    while !player.is_done() {
+        if !interactive {
+            std::thread::sleep(POLL);
+        } else if event::poll(POLL)? {
+            if let Event::Key(key) = event::read()? {
+                if is_quit_key(&key) {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `--frame`: print frame `frame_idx` at `size` where the cursor is, without
+/// the alternate screen, control bar or audio. Transparent cells are drawn over `bg`.
+/// Colors are `color` if given, else what the environment says the terminal
+/// supports (truecolor when redirected).
+fn print_frame(
+    source: &mut dyn FrameSource,
+    frame_idx: usize,
+    size: (u16, u16),
+    bg: (u8, u8, u8),
+    glyph: Glyph,
+    mut color: Option<ColorMode>,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(source.can_seek(), "--frame needs a file; a stream can only be played from the start");
+    let header = source.header();
+    let (cols, rows) = (header.cols as u16, header.rows as u16);
+    let has_alpha = header.flags & FLAG_ALPHA != 0;
+    let mut cells = source.reconstruct_frame(frame_idx)?;
+    if has_alpha {
+        cells.iter_mut().for_each(|c| *c = c.composite(bg));
+    }
+
+    let stdout = std::io::stdout();
+    let (mut out_cols, mut out_rows) = size;
+    if stdout.is_terminal() {
+        color = color.or_else(term::env_color);
+        // Leave a row for the shell prompt
+        let (term_cols, term_rows) = terminal::size()?;
+        let max_rows = term_rows.saturating_sub(1).max(1);
+        if out_cols > term_cols || out_rows > max_rows {
+            (out_cols, out_rows) = fit_size(out_cols, out_rows, term_cols, max_rows);
         }
     }
+    if (out_cols, out_rows) != (cols, rows) {
+        let mut scaled = Vec::new();
+        render::scale_cells(&cells, cols, rows, out_cols, out_rows, &mut scaled);
+        cells = scaled;
+    }
 
+    let mut buf = Vec::new();
+    render::render_still(&cells, out_cols, out_rows, glyph, &mut buf);
+    let mut stdout = ColorWriter::new(stdout.lock(), color.unwrap_or(ColorMode::Truecolor));
+    stdout.write_all(&buf)?;
+    stdout.flush()?;
     Ok(())
 }
 
-fn show_splash(stdout: &mut impl Write, term_cols: u16, term_rows: u16) -> anyhow::Result<()> {
+/// How long the splash screen shows unless `--splash-duration` says otherwise.
+const SPLASH_DURATION: Duration = Duration::from_secs(4);
+/// Clips shorter than this skip the splash unless `--splash-duration` is given.
+const SPLASH_MIN_CLIP: Duration = Duration::from_secs(5);
+
+/// Show the splash screen for `duration` (not at all if it's zero) or until a
+/// key is pressed. Returns false if the key was a quit key.
+fn show_splash(stdout: &mut impl Write, term_cols: u16, term_rows: u16, duration: Duration) -> anyhow::Result<bool> {
+    if duration.is_zero() {
+        return Ok(true);
+    }
     const LOGO: &[&str] = &[
         " ######   ######  ########  ##     ##",
         " ##   ## ##       ##         ##   ## ",
@@ -260,9 +1016,9 @@ fn show_splash(stdout: &mut impl Write, term_cols: u16, term_rows: u16) -> anyho
         (150, 50, 210),
     ];
 
-    let deadline = Instant::now() + Duration::from_secs(4);
+    let deadline = Instant::now() + duration;
     let mut tick = 0usize;
-    while Instant::now() < deadline {
+    while let Some(left) = deadline.checked_duration_since(Instant::now()).filter(|left| !left.is_zero()) {
         let (r, g, b) = purples[tick % purples.len()];
         let spin_char = SPINNER[tick % SPINNER.len()];
         write!(
@@ -273,22 +1029,72 @@ fn show_splash(stdout: &mut impl Write, term_cols: u16, term_rows: u16) -> anyho
         stdout.flush()?;
         tick += 1;
 
-        if event::poll(Duration::from_millis(80))? {
-            if let Event::Key(_) = event::read()? {
+        if event::poll(left.min(Duration::from_millis(80)))? {
+            if let Event::Key(key) = event::read()? {
+                if is_quit_key(&key) {
+                    return Ok(false);
+                }
                 break;
             }
         }
     }
 
-    // Clear for video
-    stdout.write_all(b"\x1b[48;2;0;0;0m\x1b[2J")?;
-    stdout.flush()?;
+    stdout.write_all(b"\x1b[0m")?;
+    Ok(true)
+}
 
-    Ok(())
+/// Height-to-width ratio of the terminal's cells: the one given, or the window's
+/// pixel size over its cell count if the terminal reports it (many don't).
+fn terminal_cell_aspect(given: Option<f32>) -> Option<f64> {
+    given.map(f64::from).or_else(|| {
+        let size = terminal::window_size().ok()?;
+        let known = size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0;
+        known.then(|| (size.height as f64 / size.rows as f64) / (size.width as f64 / size.columns as f64))
+    })
+}
+
+/// Largest grid that fits in `max_cols x max_rows` while keeping the aspect ratio of `cols x rows`.
+fn fit_size(cols: u16, rows: u16, max_cols: u16, max_rows: u16) -> (u16, u16) {
+    let scale = (max_cols as f64 / cols as f64).min(max_rows as f64 / rows as f64);
+    let fit_cols = ((cols as f64 * scale) as u16).clamp(1, max_cols.max(1));
+    let fit_rows = ((rows as f64 * scale) as u16).clamp(1, max_rows.max(1));
+    (fit_cols, fit_rows)
 }
 
+/// Report mouse presses, releases, drags and the wheel, SGR-encoded so columns
+/// past 223 come through.
+const MOUSE_ON: &[u8] = b"\x1b[?1002h\x1b[?1006h";
+const MOUSE_OFF: &[u8] = b"\x1b[?1006l\x1b[?1002l";
+
 fn cleanup_terminal() {
-    let _ = std::io::stdout().write_all(b"\x1b[0m\x1b[?25h\x1b[?1049l");
+    let _ = std::io::stdout().write_all(b"\x1b[0m");
+    let _ = std::io::stdout().write_all(Layout::LEAVE);
+    let _ = std::io::stdout().write_all(MOUSE_OFF);
+    let _ = std::io::stdout().write_all(b"\x1b[?25h\x1b[?1049l");
     let _ = std::io::stdout().flush();
     let _ = terminal::disable_raw_mode();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_scale_means_clip() {
+        let oversize = |args: &[&str]| {
+            Cli::try_parse_from([&["rsfx-player", "in.rsfx"], args].concat()).map(|cli| cli.on_oversize)
+        };
+        assert!(matches!(oversize(&[]), Ok(Oversize::Scale)));
+        assert!(matches!(oversize(&["--no-scale"]), Ok(Oversize::Clip)));
+        assert!(matches!(oversize(&["--on-oversize", "error"]), Ok(Oversize::Error)));
+        assert!(oversize(&["--no-scale", "--on-oversize", "scale"]).is_err());
+    }
+
+    #[test]
+    fn half_takes_only_the_half_blocks() {
+        let half = |value: &str| Cli::try_parse_from(["rsfx-player", "in.rsfx", "--half", value]).map(|cli| cli.half);
+        assert!(matches!(half("upper"), Ok(Some(HalfBlock::Upper))));
+        assert!(half("full").is_err());
+        assert!(Cli::try_parse_from(["rsfx-player", "in.rsfx", "--glyph", "full"]).is_ok());
+    }
+}
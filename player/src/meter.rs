@@ -0,0 +1,70 @@
+use std::io::Write;
+use std::time::Instant;
+
+/// Levels below this many dB show as an empty bar.
+const FLOOR_DB: f32 = -48.0;
+/// How fast a bar falls back when the level drops, in dB per second, so short
+/// peaks stay readable.
+const FALL_DB_PER_SEC: f32 = 40.0;
+
+/// A VU meter: one horizontal bar per channel (at most two), on one terminal row.
+pub struct VuMeter {
+    /// Displayed level per channel, from 0 (floor) to 1 (full scale)
+    shown: Vec<f32>,
+    last_update: Instant,
+}
+
+impl VuMeter {
+    pub fn new() -> Self {
+        Self {
+            shown: Vec::new(),
+            last_update: Instant::now(),
+        }
+    }
+
+    /// Feed the current RMS levels (0 to 1) of each channel.
+    pub fn update(&mut self, levels: &[f32]) {
+        let fall = self.last_update.elapsed().as_secs_f32() * FALL_DB_PER_SEC / -FLOOR_DB;
+        self.last_update = Instant::now();
+        self.shown.resize(levels.len().min(2), 0.0);
+        for (shown, &level) in self.shown.iter_mut().zip(levels) {
+            let db = 20.0 * level.max(1e-6).log10();
+            let target = (1.0 - db / FLOOR_DB).clamp(0.0, 1.0);
+            *shown = target.max(*shown - fall);
+        }
+    }
+
+    /// Append the meter at the cursor, `width` columns wide. Bars go from green
+    /// through yellow to red near full scale.
+    pub fn render(&self, width: u16, buf: &mut Vec<u8>) {
+        let _ = write!(buf, "\x1b[48;2;0;0;0m");
+        let labels: &[&str] = match self.shown.len() {
+            0 => &[],
+            1 => &[" "],
+            _ => &["L", "R"],
+        };
+        // Each bar gets its share of the row after its label and a space
+        let bar_width = (width as usize / labels.len().max(1)).saturating_sub(2);
+        let mut used = 0;
+        for (label, &level) in labels.iter().zip(&self.shown) {
+            let _ = write!(buf, "\x1b[38;2;200;200;200m{label} ");
+            let lit = (level * bar_width as f32).round() as usize;
+            for i in 0..bar_width {
+                let pos = i as f32 / bar_width as f32;
+                let (r, g, b) = match pos {
+                    p if p >= 0.9 => (230, 40, 40),
+                    p if p >= 0.7 => (230, 200, 40),
+                    _ => (40, 200, 70),
+                };
+                if i < lit {
+                    let _ = write!(buf, "\x1b[38;2;{r};{g};{b}m█");
+                } else {
+                    let _ = write!(buf, "\x1b[38;2;{};{};{}m·", r / 4, g / 4, b / 4);
+                }
+            }
+            used += bar_width + 2;
+        }
+        buf.resize(buf.len() + (width as usize).saturating_sub(used), b' ');
+        buf.extend_from_slice(b"\x1b[0m");
+    }
+}
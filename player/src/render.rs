@@ -1,39 +1,124 @@
-use rsfx_core::format::{Cell, DeltaCell};
+use clap::builder::PossibleValue;
+use clap::ValueEnum;
+use rsfx_core::format::{Cell, DeltaCell, HalfBlock};
 
-const HALF_BLOCK: &str = "▄";
+/// What draws a cell: a half-block, or `Full`: `█` in the average of the two
+/// pixels, with no background color. That halves the vertical resolution, but the
+/// colors survive pasting into places that drop backgrounds, for sharing frames
+/// as ANSI art.
+#[derive(Clone, Copy)]
+pub enum Glyph {
+    Half(HalfBlock),
+    Full,
+}
+
+impl ValueEnum for Glyph {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Glyph::Half(HalfBlock::Lower), Glyph::Half(HalfBlock::Upper), Glyph::Full]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        match self {
+            Glyph::Half(half) => half.to_possible_value(),
+            Glyph::Full => Some(PossibleValue::new("full").help("`█` in the average color, with no background")),
+        }
+    }
+}
+
+impl Glyph {
+    fn glyph(self) -> &'static str {
+        match self {
+            Glyph::Half(half) => half.glyph(),
+            Glyph::Full => "█",
+        }
+    }
+
+    /// Draws with the fg color only, never setting a background.
+    fn fg_only(self) -> bool {
+        matches!(self, Glyph::Full)
+    }
+
+    /// Terminal (bg, fg) colors for a cell. Glyph cells keep their colors as is.
+    fn colors(self, cell: &Cell) -> ((u8, u8, u8), (u8, u8, u8)) {
+        match self {
+            Glyph::Half(half) => half.colors(cell),
+            Glyph::Full if cell.glyph.is_none() => {
+                let mean = |a: u8, b: u8| (a as u16 + b as u16).div_ceil(2) as u8;
+                let color = (mean(cell.bg_r, cell.fg_r), mean(cell.bg_g, cell.fg_g), mean(cell.bg_b, cell.fg_b));
+                (color, color)
+            }
+            Glyph::Full => HalfBlock::Lower.colors(cell),
+        }
+    }
+}
+
+/// Start of a synchronized update (mode 2026): the terminal holds off drawing
+/// until `SYNC_END`, so a frame never shows half-drawn. Terminals without the
+/// mode ignore both.
+pub const SYNC_BEGIN: &[u8] = b"\x1b[?2026h";
+pub const SYNC_END: &[u8] = b"\x1b[?2026l";
 
 /// Render a full keyframe to an ANSI byte buffer.
 /// Writes every cell, row by row, with color optimization (skip escape if same as previous).
-pub fn render_keyframe(cells: &[Cell], cols: u16, rows: u16, buf: &mut Vec<u8>) {
+/// Solid cells (same color top and bottom) are drawn as spaces, and with `erase` a
+/// solid run at the end of a row is cleared in one erase, which keeps letterboxed
+/// frames small; neither happens with `Glyph::Full`, which sets no background.
+/// `erase` needs a terminal that erases in the current background color
+/// (`Capabilities::back_color_erase`).
+pub fn render_keyframe(cells: &[Cell], cols: u16, rows: u16, glyph: Glyph, erase: bool, buf: &mut Vec<u8>) {
     buf.clear();
 
     // Move cursor to top-left
-    buf.extend_from_slice(b"\x1b[H");
+    buf.extend_from_slice(CURSOR_HOME);
 
     let mut prev_bg = (255u8, 255u8, 255u8);
     let mut prev_fg = (255u8, 255u8, 255u8);
-    let mut first = true;
+    let mut first_bg = true;
+    let mut first_fg = true;
 
     for row in 0..rows as usize {
         if row > 0 {
             buf.extend_from_slice(b"\r\n");
         }
-        for col in 0..cols as usize {
-            let cell = &cells[row * cols as usize + col];
-            let bg = (cell.bg_r, cell.bg_g, cell.bg_b);
-            let fg = (cell.fg_r, cell.fg_g, cell.fg_b);
+        let row_cells = &cells[row * cols as usize..(row + 1) * cols as usize];
+        let trailing = if glyph.fg_only() || !erase { 0 } else { trailing_solid_run(row_cells) };
+        let end = if trailing >= MIN_ERASE_RUN { row_cells.len() - trailing } else { row_cells.len() };
+
+        for cell in &row_cells[..end] {
+            let (bg, fg) = glyph.colors(cell);
 
-            if first || bg != prev_bg {
+            if !glyph.fg_only() && (first_bg || bg != prev_bg) {
                 write_bg(buf, bg.0, bg.1, bg.2);
                 prev_bg = bg;
+                first_bg = false;
             }
-            if first || fg != prev_fg {
+            if bg == fg && cell.glyph.is_none() && !glyph.fg_only() {
+                // A space shows just the background, so the fg color can stay as is
+                buf.push(b' ');
+                continue;
+            }
+            if first_fg || fg != prev_fg {
                 write_fg(buf, fg.0, fg.1, fg.2);
                 prev_fg = fg;
+                first_fg = false;
             }
-            first = false;
 
-            buf.extend_from_slice(HALF_BLOCK.as_bytes());
+            write_glyph(buf, cell, glyph);
+        }
+
+        if end < row_cells.len() {
+            // Erase Character fills with the current background without touching
+            // anything past the video's last column
+            let cell = &row_cells[end];
+            let bg = (cell.bg_r, cell.bg_g, cell.bg_b);
+            if first_bg || bg != prev_bg {
+                write_bg(buf, bg.0, bg.1, bg.2);
+                prev_bg = bg;
+                first_bg = false;
+            }
+            buf.extend_from_slice(b"\x1b[");
+            write_u16(buf, trailing as u16);
+            buf.push(b'X');
         }
     }
 
@@ -41,16 +126,149 @@ pub fn render_keyframe(cells: &[Cell], cols: u16, rows: u16, buf: &mut Vec<u8>)
     buf.extend_from_slice(b"\x1b[0m");
 }
 
+const CURSOR_HOME: &[u8] = b"\x1b[H";
+
+/// Render a grid to print at the cursor, e.g. into a scrolling terminal or a
+/// file: `render_keyframe` without moving to the top-left, ending with a newline.
+/// Nothing is erased, since a file may be shown on any terminal.
+pub fn render_still(cells: &[Cell], cols: u16, rows: u16, glyph: Glyph, buf: &mut Vec<u8>) {
+    render_keyframe(cells, cols, rows, glyph, false, buf);
+    buf.drain(..CURSOR_HOME.len());
+    buf.extend_from_slice(b"\r\n");
+}
+
+/// Shortest trailing run worth an erase sequence instead of spaces.
+const MIN_ERASE_RUN: usize = 8;
+
+/// Number of identical solid cells at the end of a row.
+fn trailing_solid_run(row: &[Cell]) -> usize {
+    let Some(last) = row.last() else { return 0 };
+    if last.glyph.is_some() || (last.bg_r, last.bg_g, last.bg_b) != (last.fg_r, last.fg_g, last.fg_b) {
+        return 0;
+    }
+    row.iter().rev().take_while(|c| c == &last).count()
+}
+
+/// Draw a small `cols x rows` grid with its top-left cell at terminal row `top`
+/// and column `left` (1-indexed), over whatever is on screen there.
+pub fn render_thumbnail(cells: &[Cell], cols: u16, top: u16, left: u16, glyph: Glyph, buf: &mut Vec<u8>) {
+    buf.clear();
+    for (y, row) in cells.chunks_exact(cols as usize).enumerate() {
+        write_cursor_pos(buf, top + y as u16, left);
+        for cell in row {
+            let (bg, fg) = glyph.colors(cell);
+            if !glyph.fg_only() {
+                write_bg(buf, bg.0, bg.1, bg.2);
+            }
+            write_fg(buf, fg.0, fg.1, fg.2);
+            write_glyph(buf, cell, glyph);
+        }
+    }
+    buf.extend_from_slice(b"\x1b[0m");
+}
+
 /// Render a delta frame: only update changed cells.
-pub fn render_delta(deltas: &[DeltaCell], buf: &mut Vec<u8>) {
+pub fn render_delta(deltas: &[DeltaCell], glyph: Glyph, buf: &mut Vec<u8>) {
     buf.clear();
 
+    let mut prev: Option<&DeltaCell> = None;
     for d in deltas {
-        // Move cursor to position (1-indexed)
-        write_cursor_pos(buf, d.y + 1, d.x + 1);
-        write_bg(buf, d.cell.bg_r, d.cell.bg_g, d.cell.bg_b);
-        write_fg(buf, d.cell.fg_r, d.cell.fg_g, d.cell.fg_b);
-        buf.extend_from_slice(HALF_BLOCK.as_bytes());
+        // Move cursor to position (1-indexed), unless it's already there after
+        // the cell to the left (rows of a region delta). Only single-width
+        // glyphs are known to advance it by exactly one column.
+        let follows = prev.is_some_and(|p| {
+            p.y == d.y && p.x.checked_add(1) == Some(d.x) && p.cell.glyph.unwrap_or(' ').is_ascii()
+        });
+        if !follows {
+            write_cursor_pos(buf, (d.y + 1) as u16, (d.x + 1) as u16);
+        }
+        prev = Some(d);
+        let (bg, fg) = glyph.colors(&d.cell);
+        if !glyph.fg_only() {
+            write_bg(buf, bg.0, bg.1, bg.2);
+        }
+        write_fg(buf, fg.0, fg.1, fg.2);
+        write_glyph(buf, &d.cell, glyph);
+    }
+}
+
+/// The cell's glyph if it has one, otherwise the half-block.
+fn write_glyph(buf: &mut Vec<u8>, cell: &Cell, glyph: Glyph) {
+    match cell.glyph {
+        Some(c) => buf.extend_from_slice(c.encode_utf8(&mut [0u8; 4]).as_bytes()),
+        None => buf.extend_from_slice(glyph.glyph().as_bytes()),
+    }
+}
+
+/// Draw subtitle text centered over the bottom rows of a `cols x rows` video,
+/// white on black, one terminal row per line. Lines longer than the video are cut.
+pub fn render_subtitle(text: &str, cols: u16, rows: u16, buf: &mut Vec<u8>) {
+    buf.clear();
+    let lines: Vec<&str> = text.lines().collect();
+    let first_row = rows.saturating_sub(lines.len() as u16);
+    for (i, line) in lines.iter().enumerate().take(rows as usize) {
+        let line: String = line.chars().take(cols as usize).collect();
+        // Pad with a space on each side where there's room, for legibility
+        let padded = if line.chars().count() + 2 <= cols as usize { format!(" {line} ") } else { line };
+        let width = padded.chars().count() as u16;
+        write_cursor_pos(buf, first_row + i as u16 + 1, (cols - width) / 2 + 1);
+        write_bg(buf, 0, 0, 0);
+        write_fg(buf, 255, 255, 255);
+        buf.extend_from_slice(padded.as_bytes());
+    }
+    buf.extend_from_slice(b"\x1b[0m");
+}
+
+/// Draw a status line across the top row of a `cols`-wide video: light text on a
+/// dark bar, cut to the video width so nothing is left in the margins. `opacity`
+/// fades the text toward the bar color, from 1 (fully shown) to 0.
+pub fn render_title(text: &str, cols: u16, opacity: f32, buf: &mut Vec<u8>) {
+    buf.clear();
+    let line: String = format!(" {text}").chars().take(cols as usize).collect();
+    let pad = cols as usize - line.chars().count();
+    let level = (40.0 + 180.0 * opacity.clamp(0.0, 1.0)) as u8;
+    write_cursor_pos(buf, 1, 1);
+    write_bg(buf, 40, 40, 40);
+    write_fg(buf, level, level, level);
+    buf.extend_from_slice(line.as_bytes());
+    buf.resize(buf.len() + pad, b' ');
+    buf.extend_from_slice(b"\x1b[0m");
+}
+
+/// Nearest-neighbor resample of a `cols x rows` grid to `dst_cols x dst_rows`.
+pub fn scale_cells(cells: &[Cell], cols: u16, rows: u16, dst_cols: u16, dst_rows: u16, out: &mut Vec<Cell>) {
+    out.clear();
+    if cells.is_empty() {
+        return;
+    }
+    for dy in 0..dst_rows as usize {
+        let sy = dy * rows as usize / dst_rows as usize;
+        for dx in 0..dst_cols as usize {
+            let sx = dx * cols as usize / dst_cols as usize;
+            out.push(cells[sy * cols as usize + sx]);
+        }
+    }
+}
+
+/// The top-left `dst_cols x dst_rows` of a grid `cols` wide (no larger than it).
+pub fn crop_cells(cells: &[Cell], cols: u16, dst_cols: u16, dst_rows: u16, out: &mut Vec<Cell>) {
+    out.clear();
+    for row in cells.chunks_exact(cols as usize).take(dst_rows as usize) {
+        out.extend_from_slice(&row[..dst_cols as usize]);
+    }
+}
+
+/// Collect the cells of `current` that differ from `prev` (or all of them if `prev` is empty).
+pub fn diff_cells(prev: &[Cell], current: &[Cell], cols: u16, out: &mut Vec<DeltaCell>) {
+    out.clear();
+    for (i, cell) in current.iter().enumerate() {
+        if prev.get(i) != Some(cell) {
+            out.push(DeltaCell {
+                x: (i % cols as usize) as u32,
+                y: (i / cols as usize) as u32,
+                cell: *cell,
+            });
+        }
     }
 }
 
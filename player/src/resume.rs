@@ -0,0 +1,125 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Context;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::terminal;
+
+/// Only clips at least this long have their position remembered.
+pub const MIN_LENGTH: Duration = Duration::from_secs(60);
+/// Stopping closer than this to either end starts the next play from the top.
+const MARGIN: Duration = Duration::from_secs(5);
+/// Files remembered at once; the least recently played are forgotten first.
+const MAX_ENTRIES: usize = 200;
+
+/// Where playback positions are kept: `rsfx/positions` in the XDG state
+/// directory (`~/.local/state` unless `XDG_STATE_HOME` says otherwise).
+fn state_file() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")))?;
+    Some(dir.join("rsfx").join("positions"))
+}
+
+/// What a file is remembered by: its full path and size, so a file replaced by
+/// another of the same name doesn't jump to the old one's position.
+fn key(input: &Path) -> Option<String> {
+    let path = input.canonicalize().ok()?;
+    let size = std::fs::metadata(&path).ok()?.len();
+    Some(format!("{size} {}", path.display()))
+}
+
+/// One line per file, oldest first: the frame to resume at, a tab, and the key.
+fn parse(text: &str) -> Vec<(usize, String)> {
+    text.lines()
+        .filter_map(|line| {
+            let (frame, key) = line.split_once('\t')?;
+            Some((frame.parse().ok()?, key.to_string()))
+        })
+        .collect()
+}
+
+/// Record `frame` for `key` as the most recent entry, or forget the key with `None`.
+fn update(entries: &mut Vec<(usize, String)>, key: &str, frame: Option<usize>) {
+    entries.retain(|(_, k)| k != key);
+    if let Some(frame) = frame {
+        entries.push((frame, key.to_string()));
+    }
+    let excess = entries.len().saturating_sub(MAX_ENTRIES);
+    entries.drain(..excess);
+}
+
+/// The frame playback of `input` last stopped at, if it's remembered.
+pub fn saved_position(input: &Path) -> Option<usize> {
+    let key = key(input)?;
+    let text = std::fs::read_to_string(state_file()?).ok()?;
+    parse(&text).into_iter().find(|(_, k)| *k == key).map(|(frame, _)| frame)
+}
+
+/// Remember that playback of `input` (`frame_count` frames of `frame_secs`
+/// each) stopped at `frame`, or forget it when that's at the very start or end.
+pub fn save_position(input: &Path, frame: usize, frame_count: usize, frame_secs: f64) -> anyhow::Result<()> {
+    let (Some(key), Some(file)) = (key(input), state_file()) else { return Ok(()) };
+    let margin = (MARGIN.as_secs_f64() / frame_secs) as usize;
+    let keep = frame > margin && frame + margin < frame_count;
+
+    let text = std::fs::read_to_string(&file).unwrap_or_default();
+    let mut entries = parse(&text);
+    if !keep && !entries.iter().any(|(_, k)| *k == key) {
+        return Ok(());
+    }
+    update(&mut entries, &key, keep.then_some(frame));
+
+    if let Some(dir) = file.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    let text: String = entries.iter().map(|(frame, key)| format!("{frame}\t{key}\n")).collect();
+    std::fs::write(&file, text).with_context(|| format!("failed to write {}", file.display()))
+}
+
+/// Ask on the terminal whether to resume at `time`. Enter or `y` says yes; any
+/// other key, no.
+pub fn ask(time: &str) -> anyhow::Result<bool> {
+    let mut stderr = std::io::stderr();
+    write!(stderr, "Resume from {time}? [Y/n] ")?;
+    stderr.flush()?;
+    terminal::enable_raw_mode()?;
+    let answer = loop {
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                break Ok(matches!(key.code, KeyCode::Enter | KeyCode::Char('y' | 'Y')));
+            }
+            Ok(_) => {}
+            Err(e) => break Err(e),
+        }
+    };
+    terminal::disable_raw_mode()?;
+    let answer = answer?;
+    writeln!(stderr, "{}", if answer { "yes" } else { "no" })?;
+    Ok(answer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_update_and_roundtrip() {
+        let mut entries = parse("120\t100 /a.rsfx\nnot a line\n7\t5 /b c.rsfx\n");
+        assert_eq!(entries, [(120, "100 /a.rsfx".to_string()), (7, "5 /b c.rsfx".to_string())]);
+
+        // Updating moves the file to the end; None forgets it
+        update(&mut entries, "100 /a.rsfx", Some(300));
+        assert_eq!(entries, [(7, "5 /b c.rsfx".to_string()), (300, "100 /a.rsfx".to_string())]);
+        update(&mut entries, "5 /b c.rsfx", None);
+        assert_eq!(entries, [(300, "100 /a.rsfx".to_string())]);
+
+        for i in 0..MAX_ENTRIES {
+            update(&mut entries, &format!("1 /{i}.rsfx"), Some(i));
+        }
+        assert_eq!(entries.len(), MAX_ENTRIES);
+        assert_eq!(entries[0].1, "1 /0.rsfx");
+    }
+}
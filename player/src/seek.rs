@@ -0,0 +1,124 @@
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use rsfx_core::format::Cell;
+
+/// How far one arrow press moves the seek target.
+pub const SEEK_STEP: Duration = Duration::from_secs(5);
+/// How long the target has to stay put before its thumbnail is decoded, so
+/// holding an arrow down doesn't decode every frame it passes.
+const PREVIEW_DELAY: Duration = Duration::from_millis(150);
+/// With no arrow press for this long the seek is made; Enter makes it at once.
+const COMMIT_DELAY: Duration = Duration::from_millis(1200);
+
+/// A seek being picked with the arrow keys. The target is shown on the control
+/// bar, with a thumbnail of its frame above it, until playback jumps there.
+pub struct Scrub {
+    target: usize,
+    frame_count: usize,
+    /// Duration of one frame, in seconds
+    frame_secs: f64,
+    last_input: Instant,
+    /// The frame the thumbnail shows, and the thumbnail, already scaled down
+    preview: Option<(usize, Vec<Cell>)>,
+}
+
+impl Scrub {
+    /// Start from frame `from` of a file with `frame_count` frames, each
+    /// `frame_secs` long.
+    pub fn new(from: usize, frame_count: usize, frame_secs: f64) -> Self {
+        Self {
+            target: from,
+            frame_count,
+            frame_secs,
+            last_input: Instant::now(),
+            preview: None,
+        }
+    }
+
+    /// Move the target by `frames`, clamped to the file.
+    pub fn step(&mut self, frames: isize) {
+        self.target = self.target.saturating_add_signed(frames).min(self.frame_count.saturating_sub(1));
+        self.last_input = Instant::now();
+    }
+
+    /// Point the target at column `col` (from 0) of a `width`-wide seek bar,
+    /// clamped to the track.
+    pub fn point_at(&mut self, col: u16, width: u16) {
+        let (start, track) = self.track(width);
+        let pos = col.saturating_sub(start).min(track.saturating_sub(1)) as usize;
+        let span = track.saturating_sub(1).max(1) as usize;
+        self.target = pos * self.frame_count.saturating_sub(1) / span;
+        self.last_input = Instant::now();
+    }
+
+    pub fn target(&self) -> usize {
+        self.target
+    }
+
+    /// The frame to decode a thumbnail for: the target, once it has settled and
+    /// if the thumbnail doesn't show it already.
+    pub fn preview_due(&self) -> Option<usize> {
+        let shown = self.preview.as_ref().map(|(idx, _)| *idx);
+        (shown != Some(self.target) && self.last_input.elapsed() >= PREVIEW_DELAY).then_some(self.target)
+    }
+
+    pub fn set_preview(&mut self, frame_idx: usize, cells: Vec<Cell>) {
+        self.preview = Some((frame_idx, cells));
+    }
+
+    /// The latest thumbnail, which may lag behind the target while it moves.
+    pub fn preview(&self) -> Option<&[Cell]> {
+        self.preview.as_ref().map(|(_, cells)| cells.as_slice())
+    }
+
+    /// Whether the arrows have been left alone long enough to make the seek.
+    pub fn commit_due(&self) -> bool {
+        self.last_input.elapsed() >= COMMIT_DELAY
+    }
+
+    /// Column (from 0) of the target's marker on a `width`-wide seek bar.
+    pub fn marker_col(&self, width: u16) -> u16 {
+        let (start, track) = self.track(width);
+        let last = self.frame_count.saturating_sub(1).max(1);
+        start + (self.target.min(last) * track.saturating_sub(1) as usize / last) as u16
+    }
+
+    /// Where the track starts and how wide it is, between the two time labels.
+    fn track(&self, width: u16) -> (u16, u16) {
+        let label = self.time_label(self.frame_count).len() as u16 + 2;
+        (label, width.saturating_sub(label * 2))
+    }
+
+    /// The time at frame `frame_idx`, padded to the width of the total so the
+    /// track doesn't shift as the target moves.
+    fn time_label(&self, frame_idx: usize) -> String {
+        let total = format_time((self.frame_count as f64 * self.frame_secs) as u64);
+        let time = format_time((frame_idx as f64 * self.frame_secs) as u64);
+        format!("{time:>width$}", width = total.len())
+    }
+
+    /// Append the seek bar at the cursor, `width` columns wide: the target time,
+    /// a track with the target marked, and the total time.
+    pub fn render_bar(&self, width: u16, buf: &mut Vec<u8>) {
+        let (start, track) = self.track(width);
+        let before = (self.marker_col(width) - start) as usize;
+        let after = (track as usize).saturating_sub(before + 1);
+        let _ = write!(
+            buf,
+            "\x1b[48;2;0;0;0m\x1b[38;2;200;200;200m {} \x1b[38;2;100;160;255m{}\x1b[38;2;255;255;255m●\x1b[38;2;80;80;80m{}\x1b[38;2;200;200;200m {} \x1b[0m",
+            self.time_label(self.target),
+            "━".repeat(before),
+            "─".repeat(after),
+            self.time_label(self.frame_count),
+        );
+    }
+}
+
+/// `m:ss`, or `h:mm:ss` from an hour on.
+pub fn format_time(secs: u64) -> String {
+    match secs / 3600 {
+        0 => format!("{}:{:02}", secs / 60, secs % 60),
+        hours => format!("{hours}:{:02}:{:02}", secs / 60 % 60, secs % 60),
+    }
+}
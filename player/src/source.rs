@@ -0,0 +1,136 @@
+use std::io::{Read, Seek};
+
+use rsfx_core::decode::{DecodeStats, Frame, RsfxReader, SequentialReader};
+use rsfx_core::format::{Cell, RsfxHeader};
+use rsfx_core::subtitle::SubtitleEntry;
+
+/// Where the playback loop gets its frames from: a seekable file or a stream (stdin).
+pub trait FrameSource {
+    fn header(&self) -> &RsfxHeader;
+
+    /// Decode the next frame in order. Returns None after the last frame.
+    fn next_frame(&mut self) -> anyhow::Result<Option<Frame>>;
+
+    /// Take the PCM audio track, if the file has one.
+    fn take_audio(&mut self) -> anyhow::Result<Option<Vec<u8>>>;
+
+    /// Go back to the first frame, for looping. Returns false if the source can't
+    /// (streams are read once).
+    fn rewind(&mut self) -> bool {
+        false
+    }
+
+    /// Whether `seek` and `reconstruct_frame` work; streams can't go back.
+    fn can_seek(&self) -> bool {
+        false
+    }
+
+    /// Continue from the keyframe that frame `frame_idx` is decoded from,
+    /// returning that keyframe's index.
+    fn seek(&mut self, _frame_idx: usize) -> anyhow::Result<usize> {
+        anyhow::bail!("this source can't seek")
+    }
+
+    /// The full cell grid at frame `frame_idx`, without moving playback.
+    fn reconstruct_frame(&mut self, _frame_idx: usize) -> anyhow::Result<Vec<Cell>> {
+        anyhow::bail!("this source can't seek")
+    }
+
+    /// The subtitle track. Streams store it after the frames, so they have none.
+    fn subtitles(&mut self) -> anyhow::Result<Vec<SubtitleEntry>> {
+        Ok(Vec::new())
+    }
+
+    /// The decoder's running counters, for the stats overlay, where it keeps them.
+    fn decode_stats(&self) -> Option<DecodeStats> {
+        None
+    }
+
+    /// Total number of frames, if known up front (streams only know it at the end).
+    fn frame_count(&self) -> Option<usize> {
+        match self.header().frame_count {
+            0 => None,
+            n => Some(n as usize),
+        }
+    }
+}
+
+/// Plays a file through its frame index.
+pub struct IndexedSource<R: Read + Seek> {
+    reader: RsfxReader<R>,
+    next: usize,
+}
+
+impl<R: Read + Seek> IndexedSource<R> {
+    pub fn new(reader: RsfxReader<R>) -> Self {
+        Self { reader, next: 0 }
+    }
+}
+
+impl<R: Read + Seek> FrameSource for IndexedSource<R> {
+    fn header(&self) -> &RsfxHeader {
+        &self.reader.header
+    }
+
+    fn next_frame(&mut self) -> anyhow::Result<Option<Frame>> {
+        if self.next >= self.reader.len() {
+            return Ok(None);
+        }
+        let frame = self.reader.read_frame(self.next)?;
+        self.next += 1;
+        Ok(Some(frame))
+    }
+
+    fn take_audio(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+        if self.reader.header.audio_length == 0 {
+            return Ok(None);
+        }
+        self.reader.read_audio().map(Some)
+    }
+
+    fn rewind(&mut self) -> bool {
+        self.next = 0;
+        true
+    }
+
+    fn can_seek(&self) -> bool {
+        true
+    }
+
+    fn seek(&mut self, frame_idx: usize) -> anyhow::Result<usize> {
+        self.reader.frame_type(frame_idx)?;
+        self.next = self.reader.keyframe_before(frame_idx).unwrap_or(0);
+        Ok(self.next)
+    }
+
+    fn reconstruct_frame(&mut self, frame_idx: usize) -> anyhow::Result<Vec<Cell>> {
+        self.reader.reconstruct_frame(frame_idx)
+    }
+
+    fn subtitles(&mut self) -> anyhow::Result<Vec<SubtitleEntry>> {
+        self.reader.read_subtitles()
+    }
+
+    fn decode_stats(&self) -> Option<DecodeStats> {
+        Some(self.reader.stats())
+    }
+
+    /// Files know it up front, even when it is zero
+    fn frame_count(&self) -> Option<usize> {
+        Some(self.reader.len())
+    }
+}
+
+impl<R: Read> FrameSource for SequentialReader<R> {
+    fn header(&self) -> &RsfxHeader {
+        &self.header
+    }
+
+    fn next_frame(&mut self) -> anyhow::Result<Option<Frame>> {
+        SequentialReader::next_frame(self)
+    }
+
+    fn take_audio(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(SequentialReader::take_audio(self))
+    }
+}
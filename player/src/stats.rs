@@ -0,0 +1,68 @@
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use rsfx_core::decode::DecodeStats;
+
+/// Playback counters shown by the stats overlay.
+pub struct PlaybackStats {
+    skipped: u64,
+    /// Frames rendered since `window_start`, for the effective fps
+    window_frames: u32,
+    window_start: Instant,
+    fps: f64,
+    /// How far the clock is ahead of the last rendered frame, in seconds
+    av_delta: f64,
+    /// The decoder's counters at `window_start`, and the average decompression
+    /// time per frame over the last window, in milliseconds
+    window_decode: Option<DecodeStats>,
+    decode_ms: Option<f64>,
+}
+
+impl PlaybackStats {
+    pub fn new() -> Self {
+        Self {
+            skipped: 0,
+            window_frames: 0,
+            window_start: Instant::now(),
+            fps: 0.0,
+            av_delta: 0.0,
+            window_decode: None,
+            decode_ms: None,
+        }
+    }
+
+    pub fn frame_skipped(&mut self) {
+        self.skipped += 1;
+    }
+
+    /// Count a rendered frame; `decode` is the source's decoder counters, if it
+    /// keeps them.
+    pub fn frame_rendered(&mut self, av_delta: f64, decode: Option<DecodeStats>) {
+        self.av_delta = av_delta;
+        self.window_frames += 1;
+        let elapsed = self.window_start.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            self.fps = self.window_frames as f64 / elapsed.as_secs_f64();
+            self.window_frames = 0;
+            self.window_start = Instant::now();
+            if let (Some(now), Some(then)) = (decode, self.window_decode) {
+                let frames = now.frames_decoded - then.frames_decoded;
+                let time = now.decompress_time - then.decompress_time;
+                self.decode_ms = (frames > 0).then(|| time.as_secs_f64() * 1000.0 / frames as f64);
+            }
+            self.window_decode = decode;
+        } else if self.window_decode.is_none() {
+            self.window_decode = decode;
+        }
+    }
+
+    /// Append the stats line at the cursor, returning how many columns it took.
+    pub fn render(&self, buf: &mut Vec<u8>) -> u16 {
+        let mut text = format!(" skipped {} | {:.1} fps | A/V {:+.3}s ", self.skipped, self.fps, self.av_delta);
+        if let Some(ms) = self.decode_ms {
+            text += &format!("| decode {ms:.2} ms ");
+        }
+        let _ = write!(buf, "\x1b[48;2;0;0;0m\x1b[38;2;200;200;200m{text}\x1b[0m");
+        text.len() as u16
+    }
+}
@@ -0,0 +1,393 @@
+use std::io::{self, Write};
+use std::time::Duration;
+
+use clap::ValueEnum;
+
+/// How colors are sent to the terminal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ColorMode {
+    /// 24-bit RGB, exactly as stored
+    Truecolor,
+    /// Nearest of the xterm 256-color palette
+    #[value(name = "256")]
+    Palette256,
+    /// Nearest of the 16 basic ANSI colors
+    #[value(name = "16")]
+    Basic16,
+}
+
+/// What the terminal playback draws on can do, worked out once at startup.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    pub color: ColorMode,
+    /// Frames can be wrapped in synchronized-update markers (mode 2026)
+    pub sync_output: bool,
+    /// There's an alternate screen to play on (mode 1049); without one, the
+    /// screen is cleared on exit instead
+    pub alt_screen: bool,
+    /// Erased cells take the current background color (terminfo's `bce`), so
+    /// solid runs can be cleared with an erase instead of written out as spaces
+    pub back_color_erase: bool,
+}
+
+/// How long to wait for the terminal to answer the probe. Terminals answer
+/// DA1 in well under this; one that doesn't answer at all gets the defaults.
+const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Terminals that predate the alternate screen and draw at most 16 colors.
+const MINIMAL_TERMS: &[&str] = &["linux", "dumb", "ansi", "cons25", "vt52", "vt100", "vt102", "vt220"];
+
+/// What `$TERM`, `$COLORTERM` and `$TERM_PROGRAM` say about the terminal: its
+/// color mode when that's settled, and whether it has an alternate screen.
+fn from_env(term: &str, colorterm: &str, term_program: &str) -> (Option<ColorMode>, bool) {
+    let minimal = term.is_empty() || MINIMAL_TERMS.contains(&term);
+    let color = if matches!(colorterm, "truecolor" | "24bit") || term.ends_with("-direct") {
+        Some(ColorMode::Truecolor)
+    } else if term_program == "Apple_Terminal" {
+        // Sets TERM=xterm-256color and reads 24-bit colors as garbage
+        Some(ColorMode::Palette256)
+    } else if minimal {
+        Some(ColorMode::Basic16)
+    } else {
+        None
+    };
+    (color, !minimal)
+}
+
+/// Whether the terminal erases in the current background color. Most do; there's
+/// no asking, but screen and tmux sessions (unless set up for it) and the Windows
+/// console are known not to, and leave erased cells in the default background.
+fn back_color_erase(term: &str, multiplexed: bool) -> bool {
+    let known_without = multiplexed || term.starts_with("screen") || term.starts_with("tmux");
+    !(cfg!(windows) || known_without || term.is_empty() || term == "dumb")
+}
+
+/// The terminal's color mode as far as the environment tells, for output that
+/// can't wait for a probe's answer.
+pub fn env_color() -> Option<ColorMode> {
+    let var = |name| std::env::var(name).unwrap_or_default();
+    from_env(&var("TERM"), &var("COLORTERM"), &var("TERM_PROGRAM")).0
+}
+
+impl Capabilities {
+    /// Work out what the terminal on stdout can do: from the environment first,
+    /// then by asking the terminal what that leaves open (24-bit color through
+    /// DECRQSS, synchronized output through DECRQM, with DA1 after them to know
+    /// when it's done answering). `color` forces a color mode and `sync_output:
+    /// false` turns synchronized output off without asking. Anything that can't
+    /// be found out is assumed supported, as it is on most terminals in use.
+    ///
+    /// The terminal has to be in raw mode, so the answers aren't echoed. Keys
+    /// typed before it has answered are lost (see `probe`).
+    pub fn detect(color: Option<ColorMode>, sync_output: bool) -> Self {
+        let var = |name| std::env::var(name).unwrap_or_default();
+        let term = var("TERM");
+        let (env_color, alt_screen) = from_env(&term, &var("COLORTERM"), &var("TERM_PROGRAM"));
+        let color = color.or(env_color);
+
+        let ask_color = color.is_none();
+        let answers = if ask_color || sync_output { probe(ask_color) } else { None };
+        let color = color.unwrap_or(match answers.and_then(|a| a.truecolor) {
+            Some(false) if term.contains("256") => ColorMode::Palette256,
+            Some(false) => ColorMode::Basic16,
+            _ => ColorMode::Truecolor,
+        });
+        // A terminal that answered DA1 but not DECRQM doesn't know the mode
+        let sync_output = sync_output && answers.map(|a| a.sync_output == Some(true)).unwrap_or(true);
+        let multiplexed = !var("TMUX").is_empty() || !var("STY").is_empty();
+        let back_color_erase = back_color_erase(&term, multiplexed);
+        Self { color, sync_output, alt_screen, back_color_erase }
+    }
+}
+
+/// The terminal's answers to the probe.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Answers {
+    /// The DA1 answer, which comes after the others, has arrived
+    done: bool,
+    truecolor: Option<bool>,
+    sync_output: Option<bool>,
+}
+
+/// Sets a 24-bit background and asks which SGR is in effect: terminals that keep
+/// the color report `48:2:...` or `48;2;...` back.
+const ASK_TRUECOLOR: &[u8] = b"\x1b[48;2;1;2;3m\x1bP$qm\x1b\\\x1b[0m";
+const ASK_SYNC: &[u8] = b"\x1b[?2026$p";
+const ASK_DA1: &[u8] = b"\x1b[c";
+
+/// Read what's been answered so far out of `reply`.
+fn parse_answers(reply: &[u8]) -> Answers {
+    let mut answers = Answers::default();
+    for (i, _) in reply.iter().enumerate().filter(|&(_, &b)| b == 0x1b) {
+        let rest = &reply[i..];
+        if let Some(status) = rest.strip_prefix(b"\x1bP") {
+            // DECRQSS: `ESC P 1 $ r <sgr> m ESC \` when valid, `ESC P 0 $ r ...` when not
+            let end = status.windows(2).position(|w| w == b"\x1b\\").unwrap_or(status.len());
+            let status = &status[..end];
+            if let Some(sgr) = status.strip_prefix(b"1$r") {
+                answers.truecolor = Some(sgr.windows(4).any(|w| w == b"48:2" || w == b"48;2"));
+            } else if status.starts_with(b"0$r") {
+                answers.truecolor = Some(false);
+            }
+        } else if let Some(params) = rest.strip_prefix(b"\x1b[?") {
+            let len = params.iter().take_while(|b| b.is_ascii_digit() || **b == b';').count();
+            match &params[len..] {
+                // DECRPM: `ESC [ ? 2026 ; <state> $ y`, where 0 and 4 mean unsupported
+                [b'$', b'y', ..] => {
+                    if let Some(state) = params[..len].strip_prefix(b"2026;") {
+                        answers.sync_output = Some(!matches!(state, b"0" | b"4"));
+                    }
+                }
+                [b'c', ..] => answers.done = true,
+                _ => {}
+            }
+        }
+    }
+    answers
+}
+
+/// Ask the terminal about whatever `ask_color` and synchronized output leave
+/// open and wait for its answers. None when stdin or stdout isn't a terminal,
+/// or it doesn't answer in time.
+///
+/// Keys typed while waiting arrive mixed in with the answers and are dropped:
+/// crossterm's event reader has no way to take bytes back. The wait is one round
+/// trip to the terminal, or `PROBE_TIMEOUT` for one that never answers.
+#[cfg(unix)]
+fn probe(ask_color: bool) -> Option<Answers> {
+    use std::io::IsTerminal;
+    use std::time::Instant;
+
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return None;
+    }
+    let mut query = Vec::new();
+    if ask_color {
+        query.extend_from_slice(ASK_TRUECOLOR);
+    }
+    query.extend_from_slice(ASK_SYNC);
+    query.extend_from_slice(ASK_DA1);
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(&query).and_then(|()| stdout.flush()).ok()?;
+
+    // Read the fd directly: std's stdin would buffer keys typed meanwhile where
+    // the event reader never sees them
+    let deadline = Instant::now() + PROBE_TIMEOUT;
+    let mut reply = Vec::new();
+    loop {
+        let answers = parse_answers(&reply);
+        if answers.done {
+            return Some(answers);
+        }
+        let left = deadline.checked_duration_since(Instant::now())?;
+        let mut fd = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+        // SAFETY: `fd` is one valid, initialized pollfd, matching the count of 1
+        if unsafe { libc::poll(&mut fd, 1, left.as_millis() as libc::c_int) } <= 0 {
+            return None;
+        }
+        let mut buf = [0u8; 256];
+        // SAFETY: `buf` is valid for writes of `buf.len()` bytes and outlives the call
+        let n = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()) };
+        if n <= 0 {
+            return None;
+        }
+        reply.extend_from_slice(&buf[..n as usize]);
+    }
+}
+
+#[cfg(not(unix))]
+fn probe(_ask_color: bool) -> Option<Answers> {
+    None
+}
+
+/// Levels of each channel in the 256-color palette's 6x6x6 cube.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// xterm's default 16 colors.
+const BASIC_COLORS: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).unsigned_abs().pow(2);
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+/// The nearest color of the 256-color palette's cube (16-231) or gray ramp (232-255).
+fn palette_index(rgb: (u8, u8, u8)) -> u8 {
+    let level = |v: u8| (0..6).min_by_key(|&i| (CUBE_LEVELS[i] as i32 - v as i32).abs()).unwrap();
+    let (r, g, b) = (level(rgb.0), level(rgb.1), level(rgb.2));
+    let cube = (CUBE_LEVELS[r], CUBE_LEVELS[g], CUBE_LEVELS[b]);
+    // Grays run 8, 18, ... 238
+    let mean = (rgb.0 as u32 + rgb.1 as u32 + rgb.2 as u32) / 3;
+    let step = (mean.saturating_sub(3) / 10).min(23) as u8;
+    let gray = 8 + step * 10;
+    if distance(rgb, (gray, gray, gray)) < distance(rgb, cube) {
+        232 + step
+    } else {
+        16 + 36 * r as u8 + 6 * g as u8 + b as u8
+    }
+}
+
+/// The SGR parameters setting `rgb` as the background or foreground in `mode`.
+fn sgr(mode: ColorMode, background: bool, (r, g, b): (u8, u8, u8)) -> String {
+    let base = if background { 40 } else { 30 };
+    match mode {
+        ColorMode::Truecolor => format!("{};2;{r};{g};{b}", base + 8),
+        ColorMode::Palette256 => format!("{};5;{}", base + 8, palette_index((r, g, b))),
+        ColorMode::Basic16 => {
+            let idx = (0..16).min_by_key(|&i| distance((r, g, b), BASIC_COLORS[i])).unwrap();
+            // 30-37 / 40-47, then the bright ones at 90-97 / 100-107
+            if idx < 8 {
+                format!("{}", base + idx)
+            } else {
+                format!("{}", base + 60 + idx - 8)
+            }
+        }
+    }
+}
+
+/// Turns the 24-bit colors written through it (`ESC [ 38;2;r;g;b m` and
+/// `48;2`) into the nearest ones `mode` has, so every part of the player can
+/// write truecolor. Passes everything through untouched in truecolor mode.
+pub struct ColorWriter<W: Write> {
+    inner: W,
+    mode: ColorMode,
+    /// The start of a color sequence cut off at the end of the last write
+    pending: Vec<u8>,
+    out: Vec<u8>,
+}
+
+impl<W: Write> ColorWriter<W> {
+    pub fn new(inner: W, mode: ColorMode) -> Self {
+        Self { inner, mode, pending: Vec::new(), out: Vec::new() }
+    }
+
+    /// Translate the color sequences in `data`, appending the result to `out`
+    /// and keeping a cut-off sequence at the end in `pending`.
+    fn translate(&mut self, data: &[u8]) {
+        self.pending.extend_from_slice(data);
+        let input = std::mem::take(&mut self.pending);
+        let mut i = 0;
+        while i < input.len() {
+            let Some(esc) = input[i..].iter().position(|&b| b == 0x1b) else {
+                self.out.extend_from_slice(&input[i..]);
+                break;
+            };
+            self.out.extend_from_slice(&input[i..i + esc]);
+            i += esc;
+            match parse_color(&input[i..]) {
+                Parsed::Color { len, background, rgb } => {
+                    self.out.extend_from_slice(b"\x1b[");
+                    self.out.extend_from_slice(sgr(self.mode, background, rgb).as_bytes());
+                    self.out.push(b'm');
+                    i += len;
+                }
+                Parsed::Partial => {
+                    self.pending.extend_from_slice(&input[i..]);
+                    break;
+                }
+                Parsed::Other => {
+                    self.out.push(0x1b);
+                    i += 1;
+                }
+            }
+        }
+    }
+}
+
+enum Parsed {
+    Color { len: usize, background: bool, rgb: (u8, u8, u8) },
+    /// Could still become a color sequence with more bytes
+    Partial,
+    Other,
+}
+
+/// Whether `data`, which starts with ESC, opens with a 24-bit color sequence.
+fn parse_color(data: &[u8]) -> Parsed {
+    // Longest is `ESC [ 48;2;255;255;255m`
+    const MAX_LEN: usize = 21;
+    let end = data.iter().take(MAX_LEN).position(|&b| b == b'm');
+    let Some(end) = end else {
+        let complete = data.len() >= MAX_LEN || data[1..].iter().any(|b| !b"[0123456789;".contains(b));
+        return if complete { Parsed::Other } else { Parsed::Partial };
+    };
+    let Some(params) = data[..end].strip_prefix(b"\x1b[") else { return Parsed::Other };
+    let parts: Vec<&[u8]> = params.split(|&b| b == b';').collect();
+    let channel = |p: &[u8]| std::str::from_utf8(p).ok()?.parse::<u8>().ok();
+    match parts[..] {
+        [kind @ (b"38" | b"48"), b"2", r, g, b] => match (channel(r), channel(g), channel(b)) {
+            (Some(r), Some(g), Some(b)) => Parsed::Color { len: end + 1, background: kind == b"48", rgb: (r, g, b) },
+            _ => Parsed::Other,
+        },
+        _ => Parsed::Other,
+    }
+}
+
+impl<W: Write> Write for ColorWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.mode == ColorMode::Truecolor {
+            return self.inner.write(data);
+        }
+        self.translate(data);
+        self.inner.write_all(&self.out)?;
+        self.out.clear();
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_are_translated_across_writes() {
+        let mut out = ColorWriter::new(Vec::new(), ColorMode::Palette256);
+        out.write_all(b"a\x1b[48;2;255;0;0m\x1b[0mb\x1b[38;2;12").unwrap();
+        out.write_all(b"8;128;128m\x1b[?25h").unwrap();
+        assert_eq!(out.inner, b"a\x1b[48;5;196m\x1b[0mb\x1b[38;5;244m\x1b[?25h");
+
+        let mut out = ColorWriter::new(Vec::new(), ColorMode::Basic16);
+        out.write_all(b"\x1b[38;2;250;250;250m\x1b[48;2;0;0;10m\x1b[48;2;200;0;0m").unwrap();
+        assert_eq!(out.inner, b"\x1b[97m\x1b[40m\x1b[41m");
+    }
+
+    #[test]
+    fn capabilities_from_env_and_answers() {
+        assert_eq!(from_env("xterm-256color", "truecolor", ""), (Some(ColorMode::Truecolor), true));
+        assert_eq!(from_env("xterm-256color", "", "Apple_Terminal"), (Some(ColorMode::Palette256), true));
+        assert_eq!(from_env("linux", "", ""), (Some(ColorMode::Basic16), false));
+        assert_eq!(from_env("xterm-256color", "", ""), (None, true));
+        if !cfg!(windows) {
+            assert!(back_color_erase("xterm-256color", false));
+        }
+        assert!(!back_color_erase("xterm-256color", true));
+        assert!(!back_color_erase("screen-256color", false));
+        assert!(!back_color_erase("tmux-256color", false));
+
+        let reply = b"\x1bP1$r0;48:2::1:2:3m\x1b\\\x1b[?2026;2$y\x1b[?";
+        let answers = parse_answers(reply);
+        assert_eq!((answers.done, answers.truecolor, answers.sync_output), (false, Some(true), Some(true)));
+        let answers = parse_answers(b"\x1bP1$r0;48;5;16m\x1b\\\x1b[?62;22c");
+        assert_eq!((answers.done, answers.truecolor, answers.sync_output), (true, Some(false), None));
+    }
+}
@@ -0,0 +1,127 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Parser;
+use rsfx_core::decode::RsfxReader;
+use rsfx_core::encode::RsfxWriter;
+use rsfx_core::format::{
+    RsfxHeader, FLAG_ALPHA, FLAG_FRAMED_KEYFRAMES, FLAG_GLYPHS, FLAG_KEYFRAME_DELTAS, FLAG_LOOP, FLAG_REGION_DELTAS,
+    FLAG_WIDE_GRID,
+};
+use rsfx_core::subtitle::SubtitleEntry;
+
+#[derive(Parser)]
+#[command(name = "rsfx-remux", about = "Copy an .rsfx file with a new frame rate, without re-encoding frames")]
+struct Cli {
+    /// Input .rsfx file
+    input: PathBuf,
+
+    /// Output .rsfx file
+    output: PathBuf,
+
+    /// New frame rate, as `N` or `NUM/DEN` (e.g. 60, 24000/1001)
+    #[arg(long, value_parser = parse_fps)]
+    fps: (u16, u16),
+
+    /// Copy the audio track even though it will no longer line up with the video
+    #[arg(long)]
+    keep_audio: bool,
+}
+
+fn parse_fps(s: &str) -> Result<(u16, u16), String> {
+    let (num, den) = s.split_once('/').unwrap_or((s, "1"));
+    let num: u16 = num.trim().parse().map_err(|_| format!("invalid frame rate `{num}`"))?;
+    let den: u16 = den.trim().parse().map_err(|_| format!("invalid frame rate denominator `{den}`"))?;
+    if num == 0 || den == 0 {
+        return Err("frame rate must be nonzero".into());
+    }
+    Ok((num, den))
+}
+
+/// Subtitle times scaled by `stretch`, the ratio of the new duration to the old.
+fn retime(entries: Vec<SubtitleEntry>, stretch: f64) -> Vec<SubtitleEntry> {
+    let scale = |ms: u32| (ms as f64 * stretch).round() as u32;
+    entries
+        .into_iter()
+        .map(|e| SubtitleEntry { start_ms: scale(e.start_ms), end_ms: scale(e.end_ms), text: e.text })
+        .collect()
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let (fps_num, fps_den) = cli.fps;
+
+    let file = File::open(&cli.input)
+        .with_context(|| format!("failed to open {}", cli.input.display()))?;
+    let mut reader = RsfxReader::new(BufReader::new(file))?;
+    let header = reader.header.clone();
+
+    let out = File::create(&cli.output)
+        .with_context(|| format!("failed to create {}", cli.output.display()))?;
+    let mut out_header = RsfxHeader::new(header.cols, header.rows, fps_num, fps_den, header.keyframe_interval)?;
+    // Frames are copied as-is, so keep the flags that say how to read them (and
+    // framed keyframes keep their partial decoding)
+    out_header.flags |= header.flags
+        & (FLAG_ALPHA
+            | FLAG_GLYPHS
+            | FLAG_KEYFRAME_DELTAS
+            | FLAG_LOOP
+            | FLAG_REGION_DELTAS
+            | FLAG_WIDE_GRID
+            | FLAG_FRAMED_KEYFRAMES);
+    out_header.cell_aspect = header.cell_aspect;
+    let mut writer = RsfxWriter::with_header(BufWriter::new(out), out_header)?;
+
+    if header.audio_length > 0 {
+        if cli.keep_audio {
+            let pcm = reader.read_audio()?;
+            writer.write_audio(&pcm, header.audio_sample_rate, header.audio_channels)?;
+        } else {
+            eprintln!("Dropping audio track (it would no longer match the new frame rate; use --keep-audio to copy it)");
+        }
+    }
+
+    // Subtitles keep pace with the video; metadata describes the source and the
+    // conversion, so it's copied as is
+    let subtitles = reader.read_subtitles()?;
+    if !subtitles.is_empty() {
+        writer.write_subtitles(&retime(subtitles, reader.fps() * fps_den as f64 / fps_num as f64))?;
+    }
+    let metadata = reader.read_metadata()?;
+    if !metadata.is_empty() {
+        writer.write_metadata(&metadata)?;
+    }
+
+    for idx in 0..reader.len() {
+        let compressed = reader.read_frame_compressed(idx)?;
+        writer.write_frame_compressed(&compressed, reader.frame_type(idx)?)?;
+    }
+    writer.finish()?;
+
+    eprintln!(
+        "Wrote {} frames at {:.3} fps (was {:.3}) to {}",
+        reader.len(),
+        fps_num as f64 / fps_den as f64,
+        reader.fps(),
+        cli.output.display()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subtitles_keep_pace_with_the_frame_rate() {
+        let entries = vec![SubtitleEntry { start_ms: 1000, end_ms: 2500, text: "Hi".into() }];
+        // 30 fps played at 60 takes half the time
+        let faster = retime(entries.clone(), 30.0 / 60.0);
+        assert_eq!((faster[0].start_ms, faster[0].end_ms), (500, 1250));
+        let slower = retime(entries, 30.0 / (24000.0 / 1001.0));
+        assert_eq!((slower[0].start_ms, slower[0].end_ms, slower[0].text.as_str()), (1251, 3128, "Hi"));
+    }
+}
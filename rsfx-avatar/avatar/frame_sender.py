@@ -16,7 +16,11 @@ class FrameSender:
         self.sock.connect(self.socket_path)
 
     def send_frame(self, rgb: np.ndarray, timestamp_us: int):
-        """Send an RGB frame. rgb shape: (height, width, 3), dtype uint8."""
+        """Send an RGB frame. rgb shape: (height, width, 3), dtype uint8.
+
+        timestamp_us is the capture time in microseconds since the Unix epoch;
+        the renderer compares it against its own clock to measure latency.
+        """
         height, width = rgb.shape[:2]
         header = struct.pack("<HHQ", width, height, timestamp_us)
         self.sock.sendall(b"RF" + header + rgb.tobytes())
@@ -27,9 +31,14 @@ class FrameSender:
         self.sock.sendall(b"RA" + header + pcm_bytes)
 
     def send_control(self, cmd: int):
-        """Send control command. 0=stop, 1=start, 2=ready."""
+        """Send control command. 0=stop, 1=start, 2=ready, 3=heartbeat."""
         self.sock.sendall(b"RC" + struct.pack("<B", cmd))
 
+    def send_heartbeat(self):
+        """Tell the renderer we're alive while there's nothing to send, so its
+        --idle-timeout doesn't disconnect us. Send one every second or so."""
+        self.send_control(3)
+
     def close(self):
         if self.sock:
             try:
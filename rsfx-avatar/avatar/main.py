@@ -80,7 +80,8 @@ def main():
 
     try:
         for frame in worker.generate_frames(whisper_chunks, batch_size=args.batch_size):
-            timestamp_us = int((time.monotonic() - start_time) * 1_000_000)
+            # Wall-clock capture time, so the renderer can measure latency
+            timestamp_us = time.time_ns() // 1000
             sender.send_frame(frame, timestamp_us)
             frame_num += 1
 
@@ -4,27 +4,51 @@ use std::time::Duration;
 
 /// Streaming PCM audio source for rodio.
 ///
-/// Backed by a shared buffer of f32 samples. When empty, outputs silence (0.0)
-/// to keep the audio stream alive. Push PCM data from any thread via `push_pcm()`.
+/// Backed by a shared buffer of f32 samples. Nothing is played until `prebuffer`
+/// worth of samples is queued, and after running dry it waits for that much
+/// again, so late packets cost one longer gap instead of a string of clicks.
+/// While waiting it outputs silence (0.0) to keep the audio stream alive. Push
+/// PCM data from any thread via `push_pcm()`.
 pub struct StreamingSource {
-    buffer: Arc<Mutex<VecDeque<f32>>>,
+    shared: Arc<Mutex<Shared>>,
     sample_rate: u32,
     channels: u16,
+    /// Samples to queue before playing
+    prebuffer: usize,
+}
+
+struct Shared {
+    samples: VecDeque<f32>,
+    /// Playing queued samples, as opposed to waiting for the prebuffer to fill
+    playing: bool,
+    /// Converts pushed PCM to the output rate, if the producer's rate differs
+    resampler: Option<Resampler>,
 }
 
 impl StreamingSource {
-    pub fn new(sample_rate: u32, channels: u16) -> Self {
+    pub fn new(sample_rate: u32, channels: u16, prebuffer: Duration) -> Self {
+        let frames = (prebuffer.as_secs_f64() * sample_rate as f64) as usize;
         Self {
-            buffer: Arc::new(Mutex::new(VecDeque::with_capacity(sample_rate as usize))),
+            shared: Arc::new(Mutex::new(Shared {
+                samples: VecDeque::with_capacity(sample_rate as usize),
+                playing: false,
+                resampler: None,
+            })),
             sample_rate,
             channels,
+            prebuffer: frames * channels as usize,
         }
     }
 
-    /// Get a handle for pushing audio data from another thread.
-    pub fn handle(&self) -> AudioHandle {
+    /// Get a handle for pushing audio data from another thread. Data pushed at
+    /// `input_rate` is resampled to the source's rate.
+    pub fn handle(&self, input_rate: u32) -> AudioHandle {
+        if input_rate != self.sample_rate {
+            self.shared.lock().unwrap().resampler = Some(Resampler::new(input_rate, self.sample_rate));
+        }
         AudioHandle {
-            buffer: Arc::clone(&self.buffer),
+            shared: Arc::clone(&self.shared),
+            samples_per_sec: self.sample_rate as f64 * self.channels as f64,
         }
     }
 }
@@ -33,8 +57,18 @@ impl Iterator for StreamingSource {
     type Item = f32;
 
     fn next(&mut self) -> Option<f32> {
-        let mut buf = self.buffer.lock().unwrap();
-        Some(buf.pop_front().unwrap_or(0.0))
+        let mut shared = self.shared.lock().unwrap();
+        if !shared.playing && shared.samples.len() >= self.prebuffer.max(1) {
+            shared.playing = true;
+        }
+        if shared.playing {
+            if let Some(sample) = shared.samples.pop_front() {
+                return Some(sample);
+            }
+            // Ran dry: build the prebuffer back up before playing again
+            shared.playing = false;
+        }
+        Some(0.0)
     }
 }
 
@@ -58,16 +92,94 @@ impl rodio::Source for StreamingSource {
 
 /// Thread-safe handle for pushing PCM data into the streaming source.
 pub struct AudioHandle {
-    buffer: Arc<Mutex<VecDeque<f32>>>,
+    shared: Arc<Mutex<Shared>>,
+    samples_per_sec: f64,
 }
 
 impl AudioHandle {
     /// Convert raw s16le bytes to f32 samples and append to the buffer.
     pub fn push_pcm(&self, data: &[u8]) {
-        let mut buf = self.buffer.lock().unwrap();
-        for chunk in data.chunks_exact(2) {
-            let sample = i16::from_le_bytes([chunk[0], chunk[1]]);
-            buf.push_back(sample as f32 / 32768.0);
+        let samples = data
+            .chunks_exact(2)
+            .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]) as f32 / 32768.0);
+        let shared = &mut *self.shared.lock().unwrap();
+        match &mut shared.resampler {
+            Some(resampler) => resampler.process(&samples.collect::<Vec<_>>(), &mut shared.samples),
+            None => shared.samples.extend(samples),
         }
     }
+
+    /// Audio queued and not yet played.
+    pub fn buffered(&self) -> Duration {
+        let queued = self.shared.lock().unwrap().samples.len();
+        Duration::from_secs_f64(queued as f64 / self.samples_per_sec)
+    }
+}
+
+/// Linear-interpolation resampler for a mono stream that arrives in chunks.
+struct Resampler {
+    /// Input samples per output sample
+    step: f64,
+    /// Position of the next output sample, in input samples from the start of
+    /// the next chunk; -1 is the last sample of the previous one
+    pos: f64,
+    last: Option<f32>,
+}
+
+impl Resampler {
+    fn new(input_rate: u32, output_rate: u32) -> Self {
+        Self { step: input_rate as f64 / output_rate as f64, pos: 0.0, last: None }
+    }
+
+    /// Append `input` at the output rate to `out`. Output samples between this
+    /// chunk's last sample and the next chunk's first come with the next chunk.
+    fn process(&mut self, input: &[f32], out: &mut VecDeque<f32>) {
+        let at = |i: isize| if i < 0 { self.last.unwrap_or(input[0]) } else { input[i as usize] };
+        while self.pos < input.len() as f64 - 1.0 {
+            let i = self.pos.floor();
+            let (a, b) = (at(i as isize), at(i as isize + 1));
+            out.push_back(a + (b - a) * (self.pos - i) as f32);
+            self.pos += self.step;
+        }
+        if let Some(&last) = input.last() {
+            self.pos -= input.len() as f64;
+            self.last = Some(last);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(rate: u32, len: usize) -> Vec<f32> {
+        (0..len).map(|i| (i as f32 * 440.0 * std::f32::consts::TAU / rate as f32).sin()).collect()
+    }
+
+    #[test]
+    fn resampling_keeps_the_pitch() {
+        let input = sine(48000, 4800);
+        let mut resampler = Resampler::new(48000, 16000);
+        let mut out = VecDeque::new();
+        // Uneven chunks, like packets off the socket
+        for chunk in input.chunks(317) {
+            resampler.process(chunk, &mut out);
+        }
+
+        let expected = sine(16000, 1600);
+        assert!(out.len().abs_diff(expected.len()) <= 1, "{} samples", out.len());
+        for (i, (got, want)) in out.iter().zip(&expected).enumerate() {
+            assert!((got - want).abs() < 0.01, "sample {i}: {got} vs {want}");
+        }
+    }
+
+    #[test]
+    fn upsampling_interpolates_across_chunks() {
+        let mut resampler = Resampler::new(8000, 16000);
+        let mut out = VecDeque::new();
+        resampler.process(&[0.0, 0.5], &mut out);
+        resampler.process(&[1.0], &mut out);
+        resampler.process(&[0.0], &mut out);
+        assert_eq!(out, [0.0, 0.25, 0.5, 0.75, 1.0, 0.5]);
+    }
 }
@@ -1,4 +1,4 @@
-use crate::format::Cell;
+use rsfx_core::format::Cell;
 
 /// Convert RGB pixel data into a Cell grid using the half-block trick.
 /// Each cell = 2 vertical pixels: bg = top pixel, fg = bottom pixel.
@@ -19,14 +19,8 @@ pub fn pixels_to_cells(rgb: &[u8], width: u32, height: u32) -> Vec<Cell> {
             let top_off = top_y * stride + col * 3;
             let bot_off = bot_y * stride + col * 3;
 
-            cells.push(Cell {
-                bg_r: rgb[top_off],
-                bg_g: rgb[top_off + 1],
-                bg_b: rgb[top_off + 2],
-                fg_r: rgb[bot_off],
-                fg_g: rgb[bot_off + 1],
-                fg_b: rgb[bot_off + 2],
-            });
+            let px = |off: usize| [rgb[off], rgb[off + 1], rgb[off + 2]];
+            cells.push(Cell::from_pixels(px(top_off), px(bot_off)));
         }
     }
 
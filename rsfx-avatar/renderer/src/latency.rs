@@ -0,0 +1,62 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Weight of the newest sample in the rolling average.
+const SMOOTHING: f64 = 0.1;
+
+/// Timestamps further than this from our clock can't be Unix time.
+const MAX_CLOCK_SKEW_US: i64 = 24 * 60 * 60 * 1_000_000;
+
+/// Glass-to-glass latency from `Message::Frame` timestamps.
+///
+/// `timestamp_us` is the producer clock in microseconds, expected to be Unix time
+/// (same machine or synced clocks), so latency is `now - timestamp_us` at render
+/// time. If the producer uses some other epoch, the first frame anchors the two
+/// clocks and latency is measured relative to that frame instead.
+pub struct LatencyTracker {
+    /// Added to producer timestamps to put them on our clock
+    offset_us: Option<i64>,
+    last_us: i64,
+    average_us: Option<f64>,
+}
+
+impl LatencyTracker {
+    pub fn new() -> Self {
+        Self {
+            offset_us: None,
+            last_us: 0,
+            average_us: None,
+        }
+    }
+
+    /// Record a frame rendered now. Returns its latency in microseconds.
+    pub fn record(&mut self, timestamp_us: u64) -> i64 {
+        let now_us = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_micros() as i64);
+        let timestamp_us = timestamp_us as i64;
+        let offset_us = *self.offset_us.get_or_insert_with(|| {
+            if (now_us - timestamp_us).abs() < MAX_CLOCK_SKEW_US {
+                0
+            } else {
+                now_us - timestamp_us
+            }
+        });
+
+        self.last_us = now_us - (timestamp_us + offset_us);
+        self.average_us = Some(match self.average_us {
+            Some(avg) => avg + SMOOTHING * (self.last_us as f64 - avg),
+            None => self.last_us as f64,
+        });
+        self.last_us
+    }
+
+    /// Latency of the most recent frame, in milliseconds.
+    pub fn last_ms(&self) -> f64 {
+        self.last_us as f64 / 1000.0
+    }
+
+    /// Rolling average latency, in milliseconds.
+    pub fn average_ms(&self) -> f64 {
+        self.average_us.unwrap_or(0.0) / 1000.0
+    }
+}
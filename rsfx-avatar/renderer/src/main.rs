@@ -1,13 +1,15 @@
 mod audio;
-mod delta;
-mod format;
 mod halfblock;
+mod latency;
 mod protocol;
+mod record;
 mod render;
 
-use std::io::{self, Write};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::os::unix::net::UnixListener;
 use std::path::PathBuf;
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 
@@ -15,13 +17,15 @@ use anyhow::{Context, Result};
 use clap::Parser;
 use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
 use crossterm::terminal;
+use rsfx_core::delta::{compute_delta, FrameDiff};
+use rsfx_core::format::{Cell, HalfBlock};
 
-use crate::audio::StreamingSource;
-use crate::delta::{compute_delta, FrameDiff};
-use crate::format::Cell;
+use crate::audio::{AudioHandle, StreamingSource};
 use crate::halfblock::pixels_to_cells;
-use crate::protocol::{bind_listener, ControlCmd, Message, SocketReceiver};
-use crate::render::{render_delta, render_keyframe};
+use crate::latency::LatencyTracker;
+use crate::protocol::{bind_listener, is_idle_timeout, Closer, ControlCmd, Message, SocketReceiver};
+use crate::record::Recorder;
+use crate::render::{render_delta, render_keyframe, SYNC_BEGIN, SYNC_END};
 
 #[derive(Parser)]
 #[command(name = "rsfx-avatar", about = "Terminal avatar renderer")]
@@ -37,8 +41,62 @@ struct Cli {
     /// Display height in terminal rows (half the pixel height)
     #[arg(long, default_value_t = 40)]
     rows: u16,
+
+    /// Show a status line (fps, frame latency and queued audio) below the video.
+    /// With --headless it shows the frame count instead, so the output doesn't
+    /// depend on timing
+    #[arg(long)]
+    status: bool,
+
+    /// Don't send frames as synchronized updates (for terminals that mishandle them)
+    #[arg(long)]
+    no_sync_output: bool,
+
+    /// Half-block character to draw with; `upper` lines up better on fonts that
+    /// show thin gaps between rows with the default
+    #[arg(long, value_enum, default_value = "lower")]
+    half: HalfBlock,
+
+    /// Audio to queue before playback starts, and again after the buffer runs
+    /// dry, in milliseconds. Higher values ride out more network jitter but
+    /// delay the sound
+    #[arg(long, default_value_t = 100)]
+    audio_buffer_ms: u64,
+
+    /// Sample rate of the producer's audio (mono s16le); resampled for playback
+    /// if it isn't 16000
+    #[arg(long, default_value_t = OUTPUT_SAMPLE_RATE, value_parser = clap::value_parser!(u32).range(1..))]
+    audio_rate: u32,
+
+    /// Also save the incoming stream (video and audio) to this .rsfx file
+    #[arg(long)]
+    record: Option<PathBuf>,
+
+    /// Frame rate written to the --record file (should match the producer's rate)
+    #[arg(long, default_value_t = 25)]
+    record_fps: u16,
+
+    /// Write the ANSI output to this file instead of the terminal, without raw
+    /// mode, the alternate screen, keyboard input or audio playback (for tests
+    /// and capturing output)
+    #[arg(long)]
+    headless: Option<PathBuf>,
+
+    /// Disconnect once the producer has sent nothing (not even a heartbeat) for
+    /// this many seconds, instead of waiting on a dead connection forever
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    idle_timeout: Option<u64>,
+
+    /// Keep accepting connections while rendering: a producer that connects and
+    /// sends ready takes over from the current one, which is disconnected. The
+    /// renderer then outlives its producers and only ends on stop or a keypress
+    #[arg(long)]
+    takeover: bool,
 }
 
+/// Rate the audio output runs at.
+const OUTPUT_SAMPLE_RATE: u32 = 16000;
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
@@ -54,19 +112,126 @@ fn main() -> Result<()> {
     eprintln!("rsfx-avatar: connected");
 
     let mut receiver = SocketReceiver::new(stream);
+    receiver.set_idle_timeout(cli.idle_timeout.map(Duration::from_secs))?;
+    receiver.wait_for_ready()?;
+    eprintln!("rsfx-avatar: received ready, entering render mode");
 
-    // Wait for Ready control message
-    loop {
-        match receiver.recv()? {
-            Some(Message::Control(ControlCmd::Ready)) => break,
-            Some(_) => continue,
-            None => anyhow::bail!("connection closed before ready"),
+    // Spawn receiver thread; when it ends, the render loop sees the channel close
+    let (tx, rx) = mpsc::channel::<Message>();
+    let receiver_thread = if cli.takeover {
+        let active = Arc::new(Mutex::new(receiver.closer()?));
+        let (idle_timeout, log) = (cli.idle_timeout, log_disconnects(&cli));
+        spawn_receiver(receiver, tx.clone(), log);
+        thread::spawn(move || accept_takeovers(listener, idle_timeout, tx, active, log));
+        None
+    } else {
+        Some(thread::spawn(move || forward(receiver, tx)))
+    };
+
+    let mut recorder = cli.record.as_deref().map(|path| Recorder::new(path, cli.record_fps, cli.audio_rate));
+    let result = match &cli.headless {
+        Some(path) => {
+            let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+            let mut out = BufWriter::new(file);
+            let result = render_headless(&cli, &rx, &mut out, recorder.as_mut());
+            out.flush().context("flushing headless output")?;
+            result
+        }
+        None => run_terminal(&cli, &rx, recorder.as_mut()),
+    };
+
+    // Clean up socket
+    let _ = std::fs::remove_file(&cli.socket);
+
+    // Say why the connection ended, unless rendering stopped first and the
+    // receiver is still waiting on it
+    if let Some(receiver_thread) = receiver_thread.filter(|t| t.is_finished()) {
+        if let Ok(Err(err)) = receiver_thread.join() {
+            eprintln!("{}", disconnect_reason(&err, cli.idle_timeout));
         }
     }
-    eprintln!("rsfx-avatar: received ready, entering render mode");
 
+    // Finish the recording even if rendering failed, so what was captured is kept
+    if let Some(recorder) = recorder {
+        recorder.finish().context("finishing recording")?;
+    }
+
+    result
+}
+
+/// Pass a connection's messages to the render loop until it closes, fails or
+/// the render loop goes away.
+fn forward(mut receiver: SocketReceiver, tx: mpsc::Sender<Message>) -> Result<()> {
+    while let Some(msg) = receiver.recv()? {
+        if tx.send(msg).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// `forward` on a thread of its own, reporting why the connection ended if `log`.
+fn spawn_receiver(receiver: SocketReceiver, tx: mpsc::Sender<Message>, log: bool) {
+    thread::spawn(move || {
+        if let Err(err) = forward(receiver, tx) {
+            if log {
+                eprintln!("{}", disconnect_reason(&err, None));
+            }
+        }
+    });
+}
+
+fn disconnect_reason(err: &anyhow::Error, idle_timeout: Option<u64>) -> String {
+    match idle_timeout {
+        Some(secs) if is_idle_timeout(err) => {
+            format!("rsfx-avatar: nothing from the producer for {secs} s, disconnected")
+        }
+        _ if is_idle_timeout(err) => "rsfx-avatar: producer went silent, disconnected".to_string(),
+        _ => format!("rsfx-avatar: connection lost: {err:#}"),
+    }
+}
+
+/// With --takeover, disconnects are only reported when stderr isn't drawn over
+/// by the video.
+fn log_disconnects(cli: &Cli) -> bool {
+    cli.headless.is_some()
+}
+
+/// Accept producers for as long as the renderer runs (--takeover). Each one
+/// that sends ready becomes the active producer: the previous connection is
+/// closed and a `Ready` tells the renderer to start over with the new stream.
+fn accept_takeovers(
+    listener: UnixListener,
+    idle_timeout: Option<u64>,
+    tx: mpsc::Sender<Message>,
+    active: Arc<Mutex<Closer>>,
+    log: bool,
+) {
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else { continue };
+        let tx = tx.clone();
+        let active = Arc::clone(&active);
+        // The handshake runs on its own thread, so a slow producer doesn't hold up others
+        thread::spawn(move || -> Result<()> {
+            let mut receiver = SocketReceiver::new(stream);
+            receiver.set_idle_timeout(idle_timeout.map(Duration::from_secs))?;
+            receiver.wait_for_ready()?;
+            std::mem::replace(&mut *active.lock().unwrap(), receiver.closer()?).close();
+            if log {
+                eprintln!("rsfx-avatar: new producer took over");
+            }
+            if tx.send(Message::Control(ControlCmd::Ready)).is_ok() {
+                spawn_receiver(receiver, tx, log);
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Render to the terminal with audio, until the stream stops or the user quits.
+fn run_terminal(cli: &Cli, rx: &mpsc::Receiver<Message>, recorder: Option<&mut Recorder>) -> Result<()> {
     // Set up audio
-    let (_stream_handle, audio_handle) = setup_audio()?;
+    let (_stream_handle, audio_handle) = setup_audio(Duration::from_millis(cli.audio_buffer_ms), cli.audio_rate)?;
 
     // Enter alternate screen + raw mode
     let mut stdout = io::stdout();
@@ -90,24 +255,8 @@ fn main() -> Result<()> {
         orig_hook(info);
     }));
 
-    // Spawn receiver thread
-    let (tx, rx) = mpsc::channel::<Message>();
-    thread::spawn(move || {
-        loop {
-            match receiver.recv() {
-                Ok(Some(msg)) => {
-                    if tx.send(msg).is_err() {
-                        break;
-                    }
-                }
-                Ok(None) => break,
-                Err(_) => break,
-            }
-        }
-    });
-
     // Render loop
-    let result = render_loop(&cli, &rx, &audio_handle, &mut stdout);
+    let result = render_loop(cli, rx, &audio_handle, &mut stdout, recorder);
 
     // Restore terminal
     let _ = terminal::disable_raw_mode();
@@ -117,15 +266,12 @@ fn main() -> Result<()> {
         terminal::LeaveAlternateScreen
     );
 
-    // Clean up socket
-    let _ = std::fs::remove_file(&cli.socket);
-
     result
 }
 
-fn setup_audio() -> Result<(rodio::OutputStream, crate::audio::AudioHandle)> {
-    let source = StreamingSource::new(16000, 1);
-    let handle = source.handle();
+fn setup_audio(prebuffer: Duration, input_rate: u32) -> Result<(rodio::OutputStream, AudioHandle)> {
+    let source = StreamingSource::new(OUTPUT_SAMPLE_RATE, 1, prebuffer);
+    let handle = source.handle(input_rate);
     let (stream, stream_handle) =
         rodio::OutputStream::try_default().context("opening audio output")?;
     stream_handle
@@ -137,16 +283,12 @@ fn setup_audio() -> Result<(rodio::OutputStream, crate::audio::AudioHandle)> {
 fn render_loop(
     cli: &Cli,
     rx: &mpsc::Receiver<Message>,
-    audio_handle: &crate::audio::AudioHandle,
-    stdout: &mut io::Stdout,
+    audio_handle: &AudioHandle,
+    stdout: &mut impl Write,
+    recorder: Option<&mut Recorder>,
 ) -> Result<()> {
-    let cols = cli.cols;
-    let rows = cli.rows;
-
-    let mut prev_cells: Vec<Cell> = Vec::new();
-    let mut render_buf = Vec::with_capacity(cols as usize * rows as usize * 20);
-    let mut frame_count: u64 = 0;
-    let mut last_log = Instant::now();
+    let mut renderer = Renderer::new(cli, recorder);
+    renderer.audio_handle = Some(audio_handle);
 
     loop {
         // Poll keyboard (non-blocking)
@@ -167,53 +309,224 @@ fn render_loop(
         // Process all pending messages
         loop {
             match rx.try_recv() {
-                Ok(Message::Frame {
-                    width,
-                    height,
-                    rgb_data,
-                    ..
-                }) => {
-                    let cells = pixels_to_cells(&rgb_data, width as u32, height as u32);
-                    let cell_rows = (height / 2) as u16;
-
-                    let diff = compute_delta(&prev_cells, &cells, width, frame_count == 0);
-
-                    match diff {
-                        FrameDiff::Keyframe(ref k) => {
-                            render_keyframe(k, width, cell_rows, &mut render_buf);
-                        }
-                        FrameDiff::Delta(ref d) => {
-                            render_delta(d, &mut render_buf);
-                        }
+                Ok(Message::Audio(pcm_data)) => {
+                    audio_handle.push_pcm(&pcm_data);
+                    renderer.audio(&pcm_data);
+                }
+                Ok(msg) => {
+                    if renderer.handle(msg, stdout)? == Flow::Stop {
+                        return Ok(());
                     }
+                }
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => return Ok(()),
+            }
+        }
+    }
+
+    Ok(())
+}
 
-                    stdout.write_all(&render_buf)?;
-                    stdout.flush()?;
+/// Render every message to `out` until the stream stops. No terminal or audio
+/// device is touched, so the output is a deterministic function of the input.
+fn render_headless(
+    cli: &Cli,
+    rx: &mpsc::Receiver<Message>,
+    out: &mut impl Write,
+    recorder: Option<&mut Recorder>,
+) -> Result<()> {
+    let mut renderer = Renderer::new(cli, recorder);
+    renderer.headless = true;
+    // A closed channel means the producer disconnected
+    while let Ok(msg) = rx.recv() {
+        if renderer.handle(msg, out)? == Flow::Stop {
+            break;
+        }
+    }
+    Ok(())
+}
 
-                    prev_cells = cells;
-                    frame_count += 1;
+#[derive(PartialEq, Eq)]
+enum Flow {
+    Continue,
+    Stop,
+}
 
-                    // Log latency every 30 frames
-                    if frame_count % 30 == 0 {
-                        let elapsed = last_log.elapsed();
-                        let fps = 30.0 / elapsed.as_secs_f64();
-                        // Write to alternate screen bottom or just track internally
-                        let _ = fps; // avoid unused warning; can add status bar later
-                        last_log = Instant::now();
+/// Turns received frames into ANSI output; shared by terminal and headless modes.
+struct Renderer<'a> {
+    status: bool,
+    sync_output: bool,
+    half: HalfBlock,
+    prev_cells: Vec<Cell>,
+    render_buf: Vec<u8>,
+    frame_count: u64,
+    last_log: Instant,
+    latency: LatencyTracker,
+    /// Leave timings (fps, latency) out of the status line
+    headless: bool,
+    recorder: Option<&'a mut Recorder>,
+    /// For the queued audio in the status line; none when headless
+    audio_handle: Option<&'a AudioHandle>,
+}
+
+impl<'a> Renderer<'a> {
+    fn new(cli: &Cli, recorder: Option<&'a mut Recorder>) -> Self {
+        Self {
+            status: cli.status,
+            sync_output: !cli.no_sync_output,
+            half: cli.half,
+            prev_cells: Vec::new(),
+            render_buf: Vec::with_capacity(cli.cols as usize * cli.rows as usize * 20),
+            frame_count: 0,
+            last_log: Instant::now(),
+            latency: LatencyTracker::new(),
+            headless: false,
+            recorder,
+            audio_handle: None,
+        }
+    }
+
+    /// Pass received audio on to the recording, if any.
+    fn audio(&mut self, pcm_data: &[u8]) {
+        if let Some(recorder) = self.recorder.as_deref_mut() {
+            recorder.push_audio(pcm_data);
+        }
+    }
+
+    fn handle(&mut self, msg: Message, out: &mut impl Write) -> Result<Flow> {
+        match msg {
+            Message::Frame {
+                width,
+                height,
+                timestamp_us,
+                rgb_data,
+            } => {
+                let cells = pixels_to_cells(&rgb_data, width as u32, height as u32);
+                let cell_rows = height / 2;
+
+                // A new size (e.g. from a new producer) can't be diffed against the old grid
+                let keyframe = self.frame_count == 0 || self.prev_cells.len() != cells.len();
+                let diff = compute_delta(&self.prev_cells, &cells, width as u32, keyframe);
+
+                match diff {
+                    FrameDiff::Keyframe(ref k) => {
+                        render_keyframe(k, width, cell_rows, self.half, &mut self.render_buf);
+                    }
+                    FrameDiff::Delta(ref d) => {
+                        render_delta(d, self.half, &mut self.render_buf);
                     }
                 }
-                Ok(Message::Audio(pcm_data)) => {
-                    audio_handle.push_pcm(&pcm_data);
+
+                if self.sync_output {
+                    out.write_all(SYNC_BEGIN)?;
+                    out.write_all(&self.render_buf)?;
+                    out.write_all(SYNC_END)?;
+                } else {
+                    out.write_all(&self.render_buf)?;
                 }
-                Ok(Message::Control(ControlCmd::Stop)) => {
-                    return Ok(());
+                out.flush()?;
+                self.latency.record(timestamp_us);
+                if let Some(recorder) = self.recorder.as_deref_mut() {
+                    recorder.push_frame(&cells, width, cell_rows)?;
                 }
-                Ok(Message::Control(_)) => {}
-                Err(mpsc::TryRecvError::Empty) => break,
-                Err(mpsc::TryRecvError::Disconnected) => return Ok(()),
+
+                self.prev_cells = cells;
+                self.frame_count += 1;
+
+                // Report fps and latency every 30 frames
+                if self.frame_count % 30 == 0 {
+                    let elapsed = self.last_log.elapsed();
+                    let fps = 30.0 / elapsed.as_secs_f64();
+                    if self.status && self.headless {
+                        write!(out, "\x1b[{};1H\x1b[0m\x1b[2K{} frames", cell_rows + 1, self.frame_count)?;
+                        out.flush()?;
+                    } else if self.status {
+                        write!(
+                            out,
+                            "\x1b[{};1H\x1b[0m\x1b[2K{:.1} fps | latency {:.1} ms (avg {:.1} ms)",
+                            cell_rows + 1,
+                            fps,
+                            self.latency.last_ms(),
+                            self.latency.average_ms()
+                        )?;
+                        if let Some(audio) = self.audio_handle {
+                            write!(out, " | audio {} ms", audio.buffered().as_millis())?;
+                        }
+                        out.flush()?;
+                    }
+                    self.last_log = Instant::now();
+                }
+                Ok(Flow::Continue)
+            }
+            Message::Audio(pcm_data) => {
+                self.audio(&pcm_data);
+                Ok(Flow::Continue)
             }
+            Message::Control(ControlCmd::Stop) => Ok(Flow::Stop),
+            // Only sent by --takeover when a new producer replaces the old one:
+            // clear what's left of its picture and start again from a keyframe
+            Message::Control(ControlCmd::Ready) => {
+                out.write_all(b"\x1b[0m\x1b[2J")?;
+                self.prev_cells.clear();
+                self.frame_count = 0;
+                Ok(Flow::Continue)
+            }
+            Message::Control(_) => Ok(Flow::Continue),
         }
     }
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `width x 2` pixel frame: red over blue, except the cells in `green` have a
+    /// green bottom pixel.
+    fn frame(width: u16, green: &[usize]) -> Message {
+        let mut rgb_data = [255, 0, 0].repeat(width as usize);
+        for x in 0..width as usize {
+            rgb_data.extend(if green.contains(&x) { [0, 255, 0] } else { [0, 0, 255] });
+        }
+        Message::Frame { width, height: 2, timestamp_us: 0, rgb_data }
+    }
+
+    fn render(cli: &Cli, messages: Vec<Message>) -> Vec<u8> {
+        let (tx, rx) = mpsc::channel();
+        for msg in messages {
+            tx.send(msg).unwrap();
+        }
+        drop(tx);
+        let mut out = Vec::new();
+        render_headless(cli, &rx, &mut out, None).unwrap();
+        out
+    }
+
+    #[test]
+    fn headless_output_is_exact() {
+        let cli = Cli::parse_from(["rsfx-avatar", "--status"]);
+        let out = render(&cli, vec![frame(4, &[]), frame(4, &[3])]);
+        let expected = [
+            // A keyframe: every cell, colors set once
+            "\x1b[?2026h\x1b[H\x1b[48;2;255;0;0m\x1b[38;2;0;0;255m▄▄▄▄\x1b[0m\x1b[?2026l",
+            // Then just the changed cell
+            "\x1b[?2026h\x1b[1;4H\x1b[48;2;255;0;0m\x1b[38;2;0;255;0m▄\x1b[?2026l",
+        ]
+        .concat();
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+
+        let cli = Cli::parse_from(["rsfx-avatar", "--no-sync-output", "--half", "upper"]);
+        let out = render(&cli, vec![frame(4, &[]), frame(4, &[3])]);
+        let expected = "\x1b[H\x1b[48;2;0;0;255m\x1b[38;2;255;0;0m▀▀▀▀\x1b[0m\
+                        \x1b[1;4H\x1b[48;2;0;255;0m\x1b[38;2;255;0;0m▀";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn headless_status_line_has_no_timings() {
+        let cli = Cli::parse_from(["rsfx-avatar", "--status", "--no-sync-output"]);
+        let messages = (0..30).map(|i| frame(4, &[i % 4])).collect();
+        let out = String::from_utf8(render(&cli, messages)).unwrap();
+        assert!(out.ends_with("\x1b[2;1H\x1b[0m\x1b[2K30 frames"), "{out:?}");
+        assert_eq!(out.matches("\x1b[2K").count(), 1);
+    }
 }
@@ -0,0 +1,96 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use rsfx_core::encode::{RsfxBuilder, RsfxWriter};
+use rsfx_core::format::Cell;
+
+/// Frames between forced keyframes, so recordings stay seekable.
+const KEYFRAME_INTERVAL: u16 = 30;
+
+/// The producer sends mono audio (see `setup_audio`).
+const AUDIO_CHANNELS: u16 = 1;
+
+/// Writes the live stream to an `.rsfx` file alongside rendering.
+///
+/// The grid size is taken from the first frame; frames of any other size are
+/// dropped. Audio is buffered and written as the file's audio track on `finish`.
+pub struct Recorder {
+    path: PathBuf,
+    fps: u16,
+    writer: Option<RsfxWriter<BufWriter<File>>>,
+    cols: u16,
+    rows: u16,
+    frame_count: u32,
+    audio: Vec<u8>,
+    /// The producer's audio rate; recorded as received, without resampling
+    audio_rate: u32,
+    warned_size: bool,
+}
+
+impl Recorder {
+    pub fn new(path: &Path, fps: u16, audio_rate: u32) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            fps,
+            writer: None,
+            cols: 0,
+            rows: 0,
+            frame_count: 0,
+            audio: Vec::new(),
+            audio_rate,
+            warned_size: false,
+        }
+    }
+
+    /// Record one frame's cells (`cols` x `rows`, row-major).
+    pub fn push_frame(&mut self, cells: &[Cell], cols: u16, rows: u16) -> Result<()> {
+        if self.writer.is_none() {
+            let file = File::create(&self.path)
+                .with_context(|| format!("creating {}", self.path.display()))?;
+            let builder = RsfxBuilder::new(cols.into(), rows.into()).fps(self.fps, 1).keyframe_interval(KEYFRAME_INTERVAL);
+            self.writer = Some(builder.build(BufWriter::new(file))?);
+            self.cols = cols;
+            self.rows = rows;
+        }
+        if (cols, rows) != (self.cols, self.rows) {
+            if !self.warned_size {
+                eprintln!(
+                    "rsfx-avatar: frame size changed to {cols}x{rows}, not recording frames that differ from {}x{}",
+                    self.cols, self.rows
+                );
+                self.warned_size = true;
+            }
+            return Ok(());
+        }
+
+        let writer = self.writer.as_mut().expect("writer created above");
+        writer.write_frame(cells)?;
+        self.frame_count += 1;
+        Ok(())
+    }
+
+    /// Buffer received PCM (s16le) for the audio track.
+    pub fn push_audio(&mut self, pcm: &[u8]) {
+        self.audio.extend_from_slice(pcm);
+    }
+
+    /// Write the audio track and index. Does nothing if no frame was received.
+    pub fn finish(self) -> Result<()> {
+        let Some(mut writer) = self.writer else {
+            eprintln!("rsfx-avatar: no frames received, nothing recorded");
+            return Ok(());
+        };
+        if !self.audio.is_empty() {
+            writer.write_audio(&self.audio, self.audio_rate, AUDIO_CHANNELS)?;
+        }
+        writer.finish()?;
+        eprintln!(
+            "rsfx-avatar: recorded {} frames to {}",
+            self.frame_count,
+            self.path.display()
+        );
+        Ok(())
+    }
+}
@@ -1,10 +1,15 @@
-use crate::format::{Cell, DeltaCell};
+use rsfx_core::format::{Cell, DeltaCell, HalfBlock};
 
-const HALF_BLOCK: &str = "▄";
+/// Bracket a frame so the terminal shows it all at once (synchronized update,
+/// mode 2026). Harmless where unsupported.
+pub const SYNC_BEGIN: &[u8] = b"\x1b[?2026h";
+pub const SYNC_END: &[u8] = b"\x1b[?2026l";
 
 /// Render a full keyframe to an ANSI byte buffer.
 /// Writes every cell, row by row, with color optimization (skip escape if same as previous).
-pub fn render_keyframe(cells: &[Cell], cols: u16, rows: u16, buf: &mut Vec<u8>) {
+/// Solid cells (same color top and bottom) are drawn as spaces, and a solid run at the
+/// end of a row is cleared in one erase, which keeps letterboxed frames small.
+pub fn render_keyframe(cells: &[Cell], cols: u16, rows: u16, half: HalfBlock, buf: &mut Vec<u8>) {
     buf.clear();
 
     // Move cursor to top-left
@@ -12,28 +17,52 @@ pub fn render_keyframe(cells: &[Cell], cols: u16, rows: u16, buf: &mut Vec<u8>)
 
     let mut prev_bg = (255u8, 255u8, 255u8);
     let mut prev_fg = (255u8, 255u8, 255u8);
-    let mut first = true;
+    let mut first_bg = true;
+    let mut first_fg = true;
 
     for row in 0..rows as usize {
         if row > 0 {
             buf.extend_from_slice(b"\r\n");
         }
-        for col in 0..cols as usize {
-            let cell = &cells[row * cols as usize + col];
-            let bg = (cell.bg_r, cell.bg_g, cell.bg_b);
-            let fg = (cell.fg_r, cell.fg_g, cell.fg_b);
+        let row_cells = &cells[row * cols as usize..(row + 1) * cols as usize];
+        let trailing = trailing_solid_run(row_cells);
+        let end = if trailing >= MIN_ERASE_RUN { row_cells.len() - trailing } else { row_cells.len() };
+
+        for cell in &row_cells[..end] {
+            let (bg, fg) = half.colors(cell);
 
-            if first || bg != prev_bg {
+            if first_bg || bg != prev_bg {
                 write_bg(buf, bg.0, bg.1, bg.2);
                 prev_bg = bg;
+                first_bg = false;
             }
-            if first || fg != prev_fg {
+            if bg == fg {
+                // A space shows just the background, so the fg color can stay as is
+                buf.push(b' ');
+                continue;
+            }
+            if first_fg || fg != prev_fg {
                 write_fg(buf, fg.0, fg.1, fg.2);
                 prev_fg = fg;
+                first_fg = false;
             }
-            first = false;
 
-            buf.extend_from_slice(HALF_BLOCK.as_bytes());
+            buf.extend_from_slice(half.glyph().as_bytes());
+        }
+
+        if end < row_cells.len() {
+            // Erase Character fills with the current background without touching
+            // anything past the video's last column
+            let cell = &row_cells[end];
+            let bg = (cell.bg_r, cell.bg_g, cell.bg_b);
+            if first_bg || bg != prev_bg {
+                write_bg(buf, bg.0, bg.1, bg.2);
+                prev_bg = bg;
+                first_bg = false;
+            }
+            buf.extend_from_slice(b"\x1b[");
+            write_u16(buf, trailing as u16);
+            buf.push(b'X');
         }
     }
 
@@ -41,16 +70,29 @@ pub fn render_keyframe(cells: &[Cell], cols: u16, rows: u16, buf: &mut Vec<u8>)
     buf.extend_from_slice(b"\x1b[0m");
 }
 
+/// Shortest trailing run worth an erase sequence instead of spaces.
+const MIN_ERASE_RUN: usize = 8;
+
+/// Number of identical solid cells at the end of a row.
+fn trailing_solid_run(row: &[Cell]) -> usize {
+    let Some(last) = row.last() else { return 0 };
+    if (last.bg_r, last.bg_g, last.bg_b) != (last.fg_r, last.fg_g, last.fg_b) {
+        return 0;
+    }
+    row.iter().rev().take_while(|c| c == &last).count()
+}
+
 /// Render a delta frame: only update changed cells.
-pub fn render_delta(deltas: &[DeltaCell], buf: &mut Vec<u8>) {
+pub fn render_delta(deltas: &[DeltaCell], half: HalfBlock, buf: &mut Vec<u8>) {
     buf.clear();
 
     for d in deltas {
         // Move cursor to position (1-indexed)
-        write_cursor_pos(buf, d.y + 1, d.x + 1);
-        write_bg(buf, d.cell.bg_r, d.cell.bg_g, d.cell.bg_b);
-        write_fg(buf, d.cell.fg_r, d.cell.fg_g, d.cell.fg_b);
-        buf.extend_from_slice(HALF_BLOCK.as_bytes());
+        write_cursor_pos(buf, (d.y + 1) as u16, (d.x + 1) as u16);
+        let (bg, fg) = half.colors(&d.cell);
+        write_bg(buf, bg.0, bg.1, bg.2);
+        write_fg(buf, fg.0, fg.1, fg.2);
+        buf.extend_from_slice(half.glyph().as_bytes());
     }
 }
 
@@ -14,7 +14,7 @@ FPS = 30
 
 
 def send_control(sock, cmd):
-    """Send RC message. cmd: 0=stop, 1=start, 2=ready"""
+    """Send RC message. cmd: 0=stop, 1=start, 2=ready, 3=heartbeat"""
     sock.sendall(b"RC" + struct.pack("<B", cmd))
 
 
@@ -62,7 +62,8 @@ def main():
     try:
         while True:
             t = time.monotonic() - start
-            timestamp_us = int(t * 1_000_000)
+            # Wall-clock send time, so the renderer can measure latency
+            timestamp_us = time.time_ns() // 1000
 
             rgb = make_gradient_frame(width, height, t)
             send_frame(sock, width, height, rgb, timestamp_us)
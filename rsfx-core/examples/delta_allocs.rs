@@ -0,0 +1,86 @@
+//! Allocations and time per frame of `compute_delta_with`, which returns a new
+//! vector each frame, against `compute_delta_into` with one reused buffer, on a
+//! synthetic 160x45 clip where a small block moves across a static picture.
+//!
+//! `cargo run --release -p rsfx-core --example delta_allocs`
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use rsfx_core::delta::{compute_delta_into, compute_delta_with, DeltaOptions, FrameDiff};
+use rsfx_core::format::Cell;
+
+/// The system allocator, counting allocations.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const COLS: u32 = 160;
+const ROWS: u32 = 45;
+const FRAMES: usize = 2000;
+
+/// A static gradient with an 8x4 block at a position that moves each frame.
+fn clip() -> Vec<Vec<Cell>> {
+    (0..FRAMES)
+        .map(|frame| {
+            let (bx, by) = ((frame * 3) as u32 % (COLS - 8), (frame / 4) as u32 % (ROWS - 4));
+            (0..COLS * ROWS)
+                .map(|i| {
+                    let (x, y) = (i % COLS, i / COLS);
+                    let inside = (bx..bx + 8).contains(&x) && (by..by + 4).contains(&y);
+                    let v = if inside { 255 } else { (x + y) as u8 };
+                    Cell { bg_r: v, bg_g: v, bg_b: v, fg_r: v, fg_g: v, fg_b: v, bg_a: 255, fg_a: 255, glyph: None }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Run `diff` over every pair of consecutive frames, returning allocations and
+/// microseconds per frame, and how many frames came out as deltas.
+fn measure(frames: &[Vec<Cell>], mut diff: impl FnMut(&[Cell], &[Cell]) -> bool) -> (f64, f64, usize) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let deltas = frames.windows(2).filter(|pair| diff(&pair[0], &pair[1])).count();
+    let micros = start.elapsed().as_secs_f64() * 1e6;
+    let pairs = (frames.len() - 1) as f64;
+    ((ALLOCATIONS.load(Ordering::Relaxed) - allocations) as f64 / pairs, micros / pairs, deltas)
+}
+
+fn main() {
+    let frames = clip();
+    let options = DeltaOptions::default();
+
+    let before = measure(&frames, |prev, current| {
+        matches!(compute_delta_with(prev, current, COLS, false, &options), FrameDiff::Delta(_))
+    });
+    let mut buffer = Vec::new();
+    let after = measure(&frames, |prev, current| {
+        !compute_delta_into(prev, current, COLS, false, &options, &mut buffer)
+    });
+    assert_eq!(before.2, after.2, "both should pick the same frame types");
+
+    println!("{} frames of {COLS}x{ROWS}, {} deltas", FRAMES, before.2);
+    println!("compute_delta_with: {:>6.2} allocations/frame {:>8.2} us/frame", before.0, before.1);
+    println!("compute_delta_into: {:>6.2} allocations/frame {:>8.2} us/frame", after.0, after.1);
+}
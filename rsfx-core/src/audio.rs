@@ -0,0 +1,43 @@
+use anyhow::Context;
+
+/// Bytes before the samples in what `wrap_pcm_as_wav` returns.
+pub const WAV_HEADER_SIZE: usize = 44;
+
+/// Wrap raw little-endian PCM (`RsfxReader::read_audio`, with the header's
+/// `audio_sample_rate` and `audio_channels`; .rsfx audio is 16-bit) in a
+/// minimal WAV header, giving a file any audio tool can play.
+/// A trailing partial sample frame is dropped so channels stay aligned.
+/// Fails when the format or the amount of audio doesn't fit WAV's header fields.
+pub fn wrap_pcm_as_wav(pcm: &[u8], sample_rate: u32, channels: u16, bits_per_sample: u16) -> anyhow::Result<Vec<u8>> {
+    debug_assert!(bits_per_sample.is_multiple_of(8), "{bits_per_sample} bits per sample isn't whole bytes");
+    let block_align = channels
+        .checked_mul(bits_per_sample / 8)
+        .with_context(|| format!("{channels} channels of {bits_per_sample}-bit audio don't fit a WAV header"))?;
+    let byte_rate = sample_rate
+        .checked_mul(block_align as u32)
+        .with_context(|| format!("{sample_rate} Hz with {channels} channels doesn't fit a WAV header"))?;
+    let pcm = &pcm[..pcm.len() - pcm.len() % block_align.max(1) as usize];
+    // The RIFF chunk size counts the 36 header bytes after it as well
+    let data_len = u32::try_from(pcm.len())
+        .ok()
+        .filter(|&len| len <= u32::MAX - 36)
+        .with_context(|| format!("{} bytes of audio is more than a WAV file holds", pcm.len()))?;
+    let file_size = 36 + data_len;
+
+    let mut wav = Vec::with_capacity(WAV_HEADER_SIZE + pcm.len());
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&file_size.to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes()); // chunk size
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM format
+    wav.extend_from_slice(&channels.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&byte_rate.to_le_bytes());
+    wav.extend_from_slice(&block_align.to_le_bytes());
+    wav.extend_from_slice(&bits_per_sample.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    wav.extend_from_slice(pcm);
+    Ok(wav)
+}
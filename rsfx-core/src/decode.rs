@@ -1,66 +1,323 @@
 use std::io::{Read, Seek, SeekFrom};
+use std::time::{Duration, Instant};
 
-use crate::compress;
+use anyhow::Context;
+
+use crate::compress::{self, EncodedFrame};
+use crate::metadata;
 use crate::format::*;
+use crate::subtitle::{self, SubtitleEntry};
+
+/// A decoded frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Frame {
+    Keyframe(Vec<Cell>),
+    Delta(Vec<DeltaCell>),
+}
+
+impl Frame {
+    fn from_raw(frame_type: FrameType, raw: &[u8], header: &RsfxHeader) -> Self {
+        match frame_type {
+            FrameType::Keyframe => Frame::Keyframe(cells_from_raw(raw, header)),
+            FrameType::Delta => Frame::Delta(deltas_from_raw(raw, header)),
+        }
+    }
+}
+
+/// Error for a file whose frame 0 is a delta, which would have nothing to apply to.
+const LEADING_DELTA: &str =
+    "frame 0 is a delta frame, not a keyframe (the file is damaged or was cut without a leading keyframe)";
+
+/// Running totals of the work an `RsfxReader` has done, for profiling and
+/// stats overlays. See `RsfxReader::stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DecodeStats {
+    /// Frames decompressed (or taken as-is, for frames stored raw)
+    pub frames_decoded: u64,
+    /// Frame bytes read from the file, as stored
+    pub bytes_read: u64,
+    /// Raw frame bytes produced (for frames stored raw, the bytes as read)
+    pub bytes_decompressed: u64,
+    /// Time spent in decompression alone, not reading
+    pub decompress_time: Duration,
+}
 
 /// Reads .rsfx files.
 pub struct RsfxReader<R: Read + Seek> {
     reader: R,
     pub header: RsfxHeader,
     pub index: Vec<FrameIndexEntry>,
+    /// Reused decompression buffer for the typed read methods
+    scratch: Vec<u8>,
+    /// Reused buffer for frames as read from the file, before decompression
+    stored: Vec<u8>,
+    stats: DecodeStats,
 }
 
 impl<R: Read + Seek> RsfxReader<R> {
     /// Open and parse header + index.
-    pub fn new(mut reader: R) -> anyhow::Result<Self> {
+    pub fn new(reader: R) -> anyhow::Result<Self> {
+        let mut this = Self::header_only(reader)?;
+        this.load_index()?;
+        Ok(this)
+    }
+
+    /// Open and parse just the header (plus the footer of streamed files, which
+    /// holds the final counts). The index is left empty, so frames can't be read
+    /// until `load_index` is called; this is for cheap metadata lookups.
+    pub fn header_only(mut reader: R) -> anyhow::Result<Self> {
         // Read header
         let mut header_buf = [0u8; HEADER_SIZE];
         reader.read_exact(&mut header_buf)?;
-        let header = RsfxHeader::from_bytes(&header_buf)?;
+        let mut header = RsfxHeader::from_bytes(&header_buf)?;
 
-        // Read frame index
-        reader.seek(SeekFrom::Start(header.index_offset))?;
-        let mut index = Vec::with_capacity(header.frame_count as usize);
-        for _ in 0..header.frame_count {
-            let mut entry_buf = [0u8; FrameIndexEntry::SIZE];
-            reader.read_exact(&mut entry_buf)?;
-            index.push(FrameIndexEntry::from_bytes(&entry_buf));
+        // Streamed files keep the final counts and offsets in a footer
+        if header.flags & FLAG_FOOTER != 0 {
+            reader.seek(SeekFrom::End(-(FOOTER_SIZE as i64)))?;
+            let mut footer_buf = [0u8; FOOTER_SIZE];
+            reader.read_exact(&mut footer_buf)?;
+            RsfxFooter::from_bytes(&footer_buf)?.apply(&mut header);
         }
 
-        Ok(Self { reader, header, index })
+        Ok(Self {
+            reader,
+            header,
+            index: Vec::new(),
+            scratch: Vec::new(),
+            stored: Vec::new(),
+            stats: DecodeStats::default(),
+        })
+    }
+
+    /// Read the frame index, falling back to the inline records if it's damaged
+    /// or missing (a checkpointed file that was never finished; see
+    /// `RsfxWriter::with_checkpoints`). A no-op when the index is already loaded.
+    pub fn load_index(&mut self) -> anyhow::Result<()> {
+        if !self.index.is_empty() || self.header.frame_count == 0 {
+            return Ok(());
+        }
+        self.index = match read_index(&mut self.reader, &self.header) {
+            Ok(index) => index,
+            Err(e) if self.header.flags & FLAG_INLINE_FRAMES != 0 => {
+                let index = scan_records(&mut self.reader, &mut self.header)
+                    .with_context(|| format!("frame index unreadable ({e}) and record scan failed"))?;
+                self.header.frame_count = index.len() as u32;
+                index
+            }
+            Err(e) => return Err(e),
+        };
+        if self.index.first().is_some_and(|entry| entry.frame_type != FrameType::Keyframe) {
+            anyhow::bail!("{LEADING_DELTA}");
+        }
+        Ok(())
     }
 
     /// Read and decompress a single frame by index. Returns raw bytes.
     pub fn read_frame_raw(&mut self, frame_idx: usize) -> anyhow::Result<Vec<u8>> {
-        let entry = self.index[frame_idx];
-        self.reader.seek(SeekFrom::Start(entry.offset))?;
-        let mut compressed = vec![0u8; entry.compressed_size as usize];
-        self.reader.read_exact(&mut compressed)?;
-        compress::decompress(&compressed)
+        let mut raw = Vec::new();
+        self.read_frame_raw_into(frame_idx, &mut raw)?;
+        Ok(raw)
+    }
+
+    /// Decompress a frame into `out`, sized from the index when it records the raw size.
+    fn read_frame_raw_into(&mut self, frame_idx: usize, out: &mut Vec<u8>) -> anyhow::Result<()> {
+        let entry = self.entry(frame_idx)?;
+        let mut stored = std::mem::take(&mut self.stored);
+        self.read_frame_stored_into(frame_idx, &mut stored)?;
+        let started = Instant::now();
+        let result = if entry.stored_raw {
+            std::mem::swap(out, &mut stored);
+            Ok(())
+        } else if self.is_framed(entry) {
+            compress::decompress_framed(&stored).map(|raw| *out = raw)
+        } else {
+            let raw_size = match entry.raw_size {
+                0 => None,
+                size => Some(size as usize),
+            };
+            compress::decompress_into(&stored, raw_size, out)
+        };
+        if result.is_ok() {
+            self.count_decoded(out.len(), started);
+        }
+        self.stored = stored;
+        result
+    }
+
+    /// The reader's work so far: frames decoded, bytes read and decompressed,
+    /// and time spent decompressing. Counting costs a clock read per frame.
+    pub fn stats(&self) -> DecodeStats {
+        self.stats
+    }
+
+    /// Count a frame of `raw_len` bytes whose decompression began at `started`.
+    fn count_decoded(&mut self, raw_len: usize, started: Instant) {
+        self.stats.frames_decoded += 1;
+        self.stats.bytes_decompressed += raw_len as u64;
+        self.stats.decompress_time += started.elapsed();
+    }
+
+    /// Read a frame as stored, for copying frames between files with
+    /// `RsfxWriter::write_frame_compressed`: its LZ4 data (framed, for keyframes
+    /// with `FLAG_FRAMED_KEYFRAMES`), or the frame itself for frames kept raw
+    /// (`FRAME_STORED_RAW`).
+    pub fn read_frame_compressed(&mut self, frame_idx: usize) -> anyhow::Result<EncodedFrame> {
+        let entry = self.entry(frame_idx)?;
+        let block_size = if self.is_framed(entry) { self.header.keyframe_block_size() } else { None };
+        let data = self.read_frame_stored(frame_idx)?;
+        Ok(EncodedFrame { data, stored_raw: entry.stored_raw, block_size })
+    }
+
+    /// Whether the frame's stored bytes are in `compress_framed`'s format.
+    fn is_framed(&self, entry: FrameIndexEntry) -> bool {
+        entry.frame_type == FrameType::Keyframe && !entry.stored_raw && self.header.keyframe_block_size().is_some()
+    }
+
+    /// The frame's bytes exactly as stored in the file.
+    fn read_frame_stored(&mut self, frame_idx: usize) -> anyhow::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.read_frame_stored_into(frame_idx, &mut data)?;
+        Ok(data)
+    }
+
+    /// `read_frame_stored` into `out`, replacing its contents.
+    fn read_frame_stored_into(&mut self, frame_idx: usize, out: &mut Vec<u8>) -> anyhow::Result<()> {
+        let entry = self.entry(frame_idx)?;
+        out.clear();
+        // Deltas with no changes (static stretches) have no bytes; skipping the
+        // seek also keeps a BufReader's buffer
+        if entry.compressed_size == 0 {
+            return Ok(());
+        }
+        let mut offset = entry.offset;
+        if self.header.flags & FLAG_INLINE_FRAMES != 0 {
+            offset += FRAME_PREFIX_SIZE as u64;
+        }
+        self.reader.seek(SeekFrom::Start(offset))?;
+        out.resize(entry.compressed_size as usize, 0);
+        self.reader.read_exact(out)?;
+        self.stats.bytes_read += out.len() as u64;
+        Ok(())
     }
 
     /// Read a keyframe as a Cell grid.
     pub fn read_keyframe(&mut self, frame_idx: usize) -> anyhow::Result<Vec<Cell>> {
-        let raw = self.read_frame_raw(frame_idx)?;
-        let cell_count = raw.len() / Cell::SIZE;
-        let mut cells = Vec::with_capacity(cell_count);
-        for i in 0..cell_count {
-            cells.push(Cell::from_bytes(&raw[i * Cell::SIZE..(i + 1) * Cell::SIZE]));
-        }
+        let mut raw = std::mem::take(&mut self.scratch);
+        self.read_frame_raw_into(frame_idx, &mut raw)?;
+        let cells = cells_from_raw(&raw, &self.header);
+        self.scratch = raw;
         Ok(cells)
     }
 
+    /// Read just the cells of keyframe `frame_idx` inside `rect` (clamped to the
+    /// grid; see `CellRect::clamped` for the size), row by row. In files with
+    /// `FLAG_FRAMED_KEYFRAMES` only the rows the rectangle covers are
+    /// decompressed, which makes small previews of large grids cheap; other
+    /// files decode the whole keyframe and crop it.
+    pub fn read_keyframe_region(&mut self, frame_idx: usize, rect: CellRect) -> anyhow::Result<Vec<Cell>> {
+        let entry = self.entry(frame_idx)?;
+        if entry.frame_type != FrameType::Keyframe {
+            anyhow::bail!("frame {frame_idx} is a delta frame, not a keyframe");
+        }
+        let cols = self.header.cols as usize;
+        let rect = rect.clamped(self.header.cols, self.header.rows);
+        if rect.is_empty() {
+            return Ok(Vec::new());
+        }
+        let layout = CellLayout::from_flags(self.header.flags);
+        let size = layout.cell_size();
+        // Bytes from the rectangle's first cell to its last
+        let (x, y, width) = (rect.x as usize, rect.y as usize, rect.width as usize);
+        let last_row = y + rect.height as usize - 1;
+        let span = (y * cols + x) * size..(last_row * cols + x + width) * size;
+
+        // Either just the span, or the whole frame with the span inside it
+        let mut raw = std::mem::take(&mut self.scratch);
+        let result = if self.is_framed(entry) {
+            let mut stored = std::mem::take(&mut self.stored);
+            let result = self.read_frame_stored_into(frame_idx, &mut stored).and_then(|()| {
+                let started = Instant::now();
+                let bytes = compress::decompress_framed_range(&stored, span.clone())?;
+                self.count_decoded(bytes.len(), started);
+                Ok(bytes)
+            });
+            self.stored = stored;
+            result.map(|bytes| {
+                raw = bytes;
+                0
+            })
+        } else {
+            self.read_frame_raw_into(frame_idx, &mut raw).map(|()| span.start)
+        };
+        let cells = result.and_then(|start| {
+            if raw.len() < start + span.len() {
+                anyhow::bail!("keyframe {frame_idx} is smaller than the grid");
+            }
+            Ok((0..rect.height as usize)
+                .flat_map(|row| raw[start + row * cols * size..][..width * size].chunks_exact(size))
+                .map(|b| layout.read_cell(b))
+                .collect())
+        });
+        self.scratch = raw;
+        cells
+    }
+
     /// Read a delta frame as a list of DeltaCells.
     pub fn read_delta(&mut self, frame_idx: usize) -> anyhow::Result<Vec<DeltaCell>> {
-        let raw = self.read_frame_raw(frame_idx)?;
-        let count = raw.len() / DeltaCell::SIZE;
-        let mut deltas = Vec::with_capacity(count);
-        for i in 0..count {
-            deltas.push(DeltaCell::from_bytes(&raw[i * DeltaCell::SIZE..(i + 1) * DeltaCell::SIZE]));
-        }
+        let mut raw = std::mem::take(&mut self.scratch);
+        self.read_frame_raw_into(frame_idx, &mut raw)?;
+        let deltas = deltas_from_raw(&raw, &self.header);
+        self.scratch = raw;
         Ok(deltas)
     }
 
+    /// Read a frame of either type.
+    pub fn read_frame(&mut self, frame_idx: usize) -> anyhow::Result<Frame> {
+        let frame_type = self.frame_type(frame_idx)?;
+        let mut raw = std::mem::take(&mut self.scratch);
+        self.read_frame_raw_into(frame_idx, &mut raw)?;
+        let frame = Frame::from_raw(frame_type, &raw, &self.header);
+        self.scratch = raw;
+        Ok(frame)
+    }
+
+    /// The full cell grid at frame `frame_idx`: its keyframe (or the last one
+    /// before it) with the deltas since applied, or just the last of them when
+    /// deltas are relative to the keyframe. For previews and stills; playback
+    /// applies frames as it goes instead.
+    pub fn reconstruct_frame(&mut self, frame_idx: usize) -> anyhow::Result<Vec<Cell>> {
+        self.entry(frame_idx)?;
+        let start = self.keyframe_before(frame_idx).unwrap_or(0);
+        let keyframe_deltas = self.header.keyframe_deltas();
+        let cols = self.header.cols as usize;
+        let mut cells = Vec::new();
+        let mut keyframe = Vec::new();
+        for idx in start..=frame_idx {
+            if keyframe_deltas && idx != start && idx != frame_idx {
+                continue;
+            }
+            match self.read_frame(idx)? {
+                Frame::Keyframe(frame) => {
+                    if keyframe_deltas {
+                        keyframe.clone_from(&frame);
+                    }
+                    cells = frame;
+                }
+                Frame::Delta(deltas) => {
+                    if keyframe_deltas {
+                        cells.clone_from(&keyframe);
+                    }
+                    for d in deltas {
+                        if let Some(cell) = cells.get_mut(d.y as usize * cols + d.x as usize) {
+                            *cell = d.cell;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(cells)
+    }
+
     /// Read audio PCM data.
     pub fn read_audio(&mut self) -> anyhow::Result<Vec<u8>> {
         if self.header.audio_length == 0 {
@@ -72,11 +329,377 @@ impl<R: Read + Seek> RsfxReader<R> {
         Ok(buf)
     }
 
-    pub fn frame_type(&self, frame_idx: usize) -> FrameType {
-        self.index[frame_idx].frame_type
+    /// Whether the file asks to be played in a loop.
+    pub fn loop_hint(&self) -> bool {
+        self.header.loop_hint()
+    }
+
+    /// Read the subtitle track. Empty if the file has none.
+    pub fn read_subtitles(&mut self) -> anyhow::Result<Vec<SubtitleEntry>> {
+        match self.read_section(SECTION_SUBTITLES)? {
+            Some(payload) => subtitle::from_bytes(&payload),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Read the key/value metadata, in the order it was written. Empty if the file has none.
+    pub fn read_metadata(&mut self) -> anyhow::Result<Vec<(String, String)>> {
+        match self.read_section(SECTION_METADATA)? {
+            Some(payload) => metadata::from_bytes(&payload),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Find a section of `kind` and return its payload.
+    fn read_section(&mut self, kind: u8) -> anyhow::Result<Option<Vec<u8>>> {
+        if self.header.sections_offset == 0 {
+            return Ok(None);
+        }
+        // Lengths come from the file, so check them against its size before allocating
+        let file_len = self.reader.seek(SeekFrom::End(0))?;
+        let mut offset = self.reader.seek(SeekFrom::Start(self.header.sections_offset))?;
+        loop {
+            let mut prefix = [0u8; SECTION_PREFIX_SIZE];
+            self.reader.read_exact(&mut prefix).context("reading section header")?;
+            if prefix[0] != RECORD_SECTION {
+                return Ok(None);
+            }
+            let len = u32::from_le_bytes(prefix[2..6].try_into().unwrap()) as u64;
+            offset += SECTION_PREFIX_SIZE as u64;
+            if offset + len > file_len {
+                anyhow::bail!("section of {len} bytes at offset {offset} runs past the end of the file");
+            }
+            if prefix[1] == kind {
+                let mut payload = vec![0u8; len as usize];
+                self.reader.read_exact(&mut payload).context("reading section")?;
+                return Ok(Some(payload));
+            }
+            offset = self.reader.seek(SeekFrom::Current(len as i64))?;
+        }
+    }
+
+    /// Frame count from the header (or footer), known even before `load_index`.
+    pub fn frame_count(&self) -> usize {
+        self.header.frame_count as usize
+    }
+
+    /// Number of frames in the loaded index; 0 until `load_index` for readers
+    /// opened with `header_only`.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// The type of frame `frame_idx`, or `None` if there's no such frame.
+    pub fn get(&self, frame_idx: usize) -> Option<FrameType> {
+        self.index.get(frame_idx).map(|entry| entry.frame_type)
+    }
+
+    /// The type of frame `frame_idx`; an error if there's no such frame (see
+    /// `get` for an `Option`).
+    pub fn frame_type(&self, frame_idx: usize) -> anyhow::Result<FrameType> {
+        Ok(self.entry(frame_idx)?.frame_type)
+    }
+
+    /// The index entry of frame `frame_idx`, or an out-of-range error.
+    fn entry(&self, frame_idx: usize) -> anyhow::Result<FrameIndexEntry> {
+        match self.index.get(frame_idx) {
+            Some(entry) => Ok(*entry),
+            None => anyhow::bail!("frame index {frame_idx} out of range (count {})", self.index.len()),
+        }
+    }
+
+    /// The nearest keyframe at or before `frame_idx`, where decoding has to start
+    /// to reconstruct that frame. Scans back at most one keyframe interval.
+    pub fn keyframe_before(&self, frame_idx: usize) -> Option<usize> {
+        let end = frame_idx.saturating_add(1).min(self.index.len());
+        self.index[..end].iter().rposition(|e| e.frame_type == FrameType::Keyframe)
+    }
+
+    /// The nearest keyframe at or after `frame_idx`, for seeking forward.
+    pub fn keyframe_after(&self, frame_idx: usize) -> Option<usize> {
+        let start = frame_idx.min(self.index.len());
+        self.index[start..]
+            .iter()
+            .position(|e| e.frame_type == FrameType::Keyframe)
+            .map(|offset| start + offset)
     }
 
     pub fn fps(&self) -> f64 {
         self.header.fps_num as f64 / self.header.fps_den as f64
     }
+
+    /// When frame `frame_idx` is shown, in seconds from the start. Frames are
+    /// evenly spaced at the header's frame rate.
+    pub fn frame_time(&self, frame_idx: usize) -> f64 {
+        frame_idx as f64 * self.header.fps_den as f64 / self.header.fps_num as f64
+    }
+
+    /// The frame on screen at `secs`: the last one shown at or before it, clamped
+    /// to the file's frames (0 if it has none). A binary search over `frame_time`,
+    /// so a seek bar can map a position to a frame the same way whatever the
+    /// spacing; decoding it starts at `keyframe_before`.
+    pub fn frame_at_time(&self, secs: f64) -> usize {
+        // Absorbs rounding when `secs` was computed from a frame's own time
+        const EPSILON: f64 = 1e-6;
+        let (mut lo, mut hi) = (0, self.index.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.frame_time(mid) <= secs + EPSILON {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        lo.saturating_sub(1)
+    }
+}
+
+/// Decodes a file front to back from a non-seekable source (pipe, socket).
+///
+/// Requires inline records (`FLAG_INLINE_FRAMES`), which every writer
+/// produces. Frame count and audio info may only be known once the stream ends.
+pub struct SequentialReader<R: Read> {
+    reader: R,
+    pub header: RsfxHeader,
+    audio: Option<Vec<u8>>,
+    /// Record tag read ahead while looking for leading audio
+    pending_tag: Option<u8>,
+    /// Whether a frame has been returned yet, to check that the first is a keyframe
+    started: bool,
+    done: bool,
+    /// Reused buffers for a frame as stored and decompressed
+    stored: Vec<u8>,
+    raw: Vec<u8>,
+}
+
+impl<R: Read> SequentialReader<R> {
+    /// Parse the header, plus the audio record if it comes before the frames.
+    pub fn new(mut reader: R) -> anyhow::Result<Self> {
+        let mut header_buf = [0u8; HEADER_SIZE];
+        reader.read_exact(&mut header_buf)?;
+        let header = RsfxHeader::from_bytes(&header_buf)?;
+        if header.flags & FLAG_INLINE_FRAMES == 0 {
+            anyhow::bail!("file has no inline frame records; sequential decode needs a streamed .rsfx");
+        }
+
+        let mut seq = Self {
+            reader,
+            header,
+            audio: None,
+            pending_tag: None,
+            started: false,
+            done: false,
+            stored: Vec::new(),
+            raw: Vec::new(),
+        };
+        match seq.read_tag()? {
+            Some(RECORD_AUDIO) => seq.read_audio_record()?,
+            Some(tag) => seq.pending_tag = Some(tag),
+            None => seq.done = true,
+        }
+        Ok(seq)
+    }
+
+    /// Take the audio PCM, if the audio record has been read so far.
+    pub fn take_audio(&mut self) -> Option<Vec<u8>> {
+        self.audio.take()
+    }
+
+    /// Read the next frame, or `None` once the frames are exhausted.
+    pub fn next_frame(&mut self) -> anyhow::Result<Option<Frame>> {
+        while !self.done {
+            let tag = match self.pending_tag.take() {
+                Some(tag) => Some(tag),
+                None => self.read_tag()?,
+            };
+            match tag {
+                // A stream cut off between records just ends early
+                None | Some(RECORD_END) => self.done = true,
+                Some(RECORD_AUDIO) => self.read_audio_record()?,
+                // Sections come after the last frame, too late to use while streaming
+                Some(RECORD_SECTION) => self.skip_section_record()?,
+                Some(tag) => {
+                    let frame_type = FrameType::try_from_u8(tag & !FRAME_STORED_RAW)
+                        .ok_or_else(|| anyhow::anyhow!("unknown record tag: {tag:#04x}"))?;
+                    if !self.started && frame_type != FrameType::Keyframe {
+                        anyhow::bail!("{LEADING_DELTA}");
+                    }
+                    self.started = true;
+                    let mut size_buf = [0u8; 4];
+                    self.reader.read_exact(&mut size_buf)?;
+                    read_body(&mut self.reader, u32::from_le_bytes(size_buf) as u64, &mut self.stored)?;
+                    let framed = frame_type == FrameType::Keyframe && self.header.keyframe_block_size().is_some();
+                    let raw = if tag & FRAME_STORED_RAW == 0 {
+                        if framed {
+                            self.raw = compress::decompress_framed(&self.stored)?;
+                        } else {
+                            compress::decompress_into(&self.stored, None, &mut self.raw)?;
+                        }
+                        &self.raw
+                    } else {
+                        &self.stored
+                    };
+                    return Ok(Some(Frame::from_raw(frame_type, raw, &self.header)));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn fps(&self) -> f64 {
+        self.header.fps_num as f64 / self.header.fps_den as f64
+    }
+
+    /// Read one record tag byte, or `None` at EOF.
+    fn read_tag(&mut self) -> anyhow::Result<Option<u8>> {
+        let mut tag = [0u8; 1];
+        match self.reader.read_exact(&mut tag) {
+            Ok(()) => Ok(Some(tag[0])),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Skip over a section record (tag already consumed).
+    fn skip_section_record(&mut self) -> anyhow::Result<()> {
+        let mut prefix = [0u8; SECTION_PREFIX_SIZE - 1];
+        self.reader.read_exact(&mut prefix)?;
+        let len = u32::from_le_bytes(prefix[1..5].try_into().unwrap()) as u64;
+        std::io::copy(&mut (&mut self.reader).take(len), &mut std::io::sink())?;
+        Ok(())
+    }
+
+    /// Read the body of an audio record (tag already consumed).
+    fn read_audio_record(&mut self) -> anyhow::Result<()> {
+        let mut prefix = [0u8; AUDIO_PREFIX_SIZE - 1];
+        self.reader.read_exact(&mut prefix)?;
+        self.header.audio_sample_rate = u32::from_le_bytes(prefix[0..4].try_into().unwrap());
+        self.header.audio_channels = u16::from_le_bytes([prefix[4], prefix[5]]);
+        self.header.audio_length = u64::from_le_bytes(prefix[6..14].try_into().unwrap());
+
+        let mut pcm = Vec::new();
+        read_body(&mut self.reader, self.header.audio_length, &mut pcm)?;
+        self.audio = Some(pcm);
+        Ok(())
+    }
+}
+
+/// Read a record body of `len` bytes into `out`, replacing its contents. `out`
+/// grows as the bytes arrive instead of being sized from `len` up front, so a
+/// damaged length in a stream is an error rather than a huge allocation.
+fn read_body<R: Read>(reader: &mut R, len: u64, out: &mut Vec<u8>) -> anyhow::Result<()> {
+    out.clear();
+    reader.take(len).read_to_end(out)?;
+    if (out.len() as u64) < len {
+        anyhow::bail!("record of {len} bytes ends after {} (the stream is truncated or damaged)", out.len());
+    }
+    Ok(())
+}
+
+/// Read the stored frame index and sanity-check it against the header.
+fn read_index<R: Read + Seek>(reader: &mut R, header: &RsfxHeader) -> anyhow::Result<Vec<FrameIndexEntry>> {
+    if header.index_offset == 0 {
+        anyhow::bail!("the file has no frame index (it was never finished)");
+    }
+    reader.seek(SeekFrom::Start(header.index_offset))?;
+    let mut index = Vec::with_capacity(header.frame_count as usize);
+    for _ in 0..header.frame_count {
+        let mut entry_buf = [0u8; FrameIndexEntry::SIZE];
+        reader.read_exact(&mut entry_buf)?;
+        let entry = FrameIndexEntry::from_bytes(&entry_buf);
+        // Frames always sit between the header and the index
+        if entry.offset < HEADER_SIZE as u64 || entry.offset + entry.compressed_size as u64 > header.index_offset {
+            anyhow::bail!("frame index entry points outside the frame data");
+        }
+        index.push(entry);
+    }
+    Ok(index)
+}
+
+/// Rebuild the frame index by walking the inline records from the top of the file.
+/// Also recovers the audio and section locations. Stops at the end record, EOF, or a
+/// truncated record.
+fn scan_records<R: Read + Seek>(reader: &mut R, header: &mut RsfxHeader) -> anyhow::Result<Vec<FrameIndexEntry>> {
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    let mut index = Vec::new();
+    let mut offset = HEADER_SIZE as u64;
+
+    while offset < file_len {
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut tag = [0u8; 1];
+        reader.read_exact(&mut tag)?;
+        match tag[0] {
+            RECORD_END => break,
+            RECORD_AUDIO => {
+                let mut prefix = [0u8; AUDIO_PREFIX_SIZE - 1];
+                reader.read_exact(&mut prefix)?;
+                let length = u64::from_le_bytes(prefix[6..14].try_into().unwrap());
+                let data_offset = offset + AUDIO_PREFIX_SIZE as u64;
+                if data_offset + length > file_len {
+                    break;
+                }
+                header.audio_sample_rate = u32::from_le_bytes(prefix[0..4].try_into().unwrap());
+                header.audio_channels = u16::from_le_bytes([prefix[4], prefix[5]]);
+                header.audio_offset = data_offset;
+                header.audio_length = length;
+                offset = data_offset + length;
+            }
+            RECORD_SECTION => {
+                let mut prefix = [0u8; SECTION_PREFIX_SIZE - 1];
+                reader.read_exact(&mut prefix)?;
+                let end = offset + SECTION_PREFIX_SIZE as u64 + u32::from_le_bytes(prefix[1..5].try_into().unwrap()) as u64;
+                if end > file_len {
+                    break;
+                }
+                if header.sections_offset == 0 {
+                    header.sections_offset = offset;
+                }
+                offset = end;
+            }
+            tag => {
+                let Some(frame_type) = FrameType::try_from_u8(tag & !FRAME_STORED_RAW) else {
+                    break;
+                };
+                let mut size_buf = [0u8; 4];
+                reader.read_exact(&mut size_buf)?;
+                let compressed_size = u32::from_le_bytes(size_buf);
+                let end = offset + FRAME_PREFIX_SIZE as u64 + compressed_size as u64;
+                if end > file_len {
+                    break;
+                }
+                index.push(FrameIndexEntry {
+                    offset,
+                    compressed_size,
+                    frame_type,
+                    raw_size: 0,
+                    stored_raw: tag & FRAME_STORED_RAW != 0,
+                });
+                offset = end;
+            }
+        }
+    }
+
+    Ok(index)
+}
+
+fn cells_from_raw(raw: &[u8], header: &RsfxHeader) -> Vec<Cell> {
+    let layout = CellLayout::from_flags(header.flags);
+    raw.chunks_exact(layout.cell_size()).map(|b| layout.read_cell(b)).collect()
+}
+
+/// Region deltas (`FLAG_REGION_DELTAS`) come back as their cells, row by row.
+fn deltas_from_raw(raw: &[u8], header: &RsfxHeader) -> Vec<DeltaCell> {
+    let layout = CellLayout::from_flags(header.flags);
+    if header.flags & FLAG_REGION_DELTAS != 0 {
+        let mut deltas = Vec::new();
+        layout.read_regions(raw).iter().for_each(|r| r.to_deltas(&mut deltas));
+        // Regions several rows high interleave with the ones beside them
+        deltas.sort_by_key(|d| (d.y, d.x));
+        return deltas;
+    }
+    raw.chunks_exact(layout.delta_size()).map(|b| layout.read_delta(b)).collect()
 }
@@ -0,0 +1,158 @@
+use crate::format::{Cell, DeltaCell, Region};
+
+/// Result of comparing two frames.
+pub enum FrameDiff {
+    /// Use this as a keyframe (too many changes, or no previous frame).
+    Keyframe(Vec<Cell>),
+    /// Delta: only the changed cells.
+    Delta(Vec<DeltaCell>),
+}
+
+/// Tuning for keyframe promotion in `compute_delta_with`.
+#[derive(Clone, Copy, Debug)]
+pub struct DeltaOptions {
+    /// Promote to a keyframe once the delta's raw size exceeds this fraction of
+    /// the keyframe's. 1.0 picks whichever is smaller; lower values favor
+    /// keyframes, which usually compress better than scattered deltas.
+    pub max_delta_ratio: f32,
+    /// Promote once more than this percentage of cells changed, instead of
+    /// comparing sizes. 0 makes any change a keyframe, 100 never promotes.
+    pub keyframe_threshold: Option<f32>,
+    /// Size deltas as the rectangles `find_regions` makes of them, for files
+    /// with `FLAG_REGION_DELTAS`.
+    pub regions: bool,
+}
+
+impl Default for DeltaOptions {
+    fn default() -> Self {
+        Self { max_delta_ratio: 1.0, keyframe_threshold: None, regions: false }
+    }
+}
+
+/// Compare current frame cells against previous, producing either a delta or promoting to keyframe.
+/// `cols` is needed to compute x,y positions from the flat cell array.
+pub fn compute_delta(
+    prev: &[Cell],
+    current: &[Cell],
+    cols: u32,
+    force_keyframe: bool,
+) -> FrameDiff {
+    compute_delta_with(prev, current, cols, force_keyframe, &DeltaOptions::default())
+}
+
+/// `compute_delta` with explicit promotion settings.
+pub fn compute_delta_with(
+    prev: &[Cell],
+    current: &[Cell],
+    cols: u32,
+    force_keyframe: bool,
+    options: &DeltaOptions,
+) -> FrameDiff {
+    let mut deltas = Vec::new();
+    if compute_delta_into(prev, current, cols, force_keyframe, options, &mut deltas) {
+        FrameDiff::Keyframe(current.to_vec())
+    } else {
+        FrameDiff::Delta(deltas)
+    }
+}
+
+/// `compute_delta_with` into a buffer the caller keeps from frame to frame, so
+/// the delta path doesn't allocate once the buffer has grown. `deltas` is
+/// cleared and filled with the changed cells; returns true if the frame should
+/// be a keyframe instead (`current` as it is), and then `deltas` means nothing.
+pub fn compute_delta_into(
+    prev: &[Cell],
+    current: &[Cell],
+    cols: u32,
+    force_keyframe: bool,
+    options: &DeltaOptions,
+    deltas: &mut Vec<DeltaCell>,
+) -> bool {
+    deltas.clear();
+    if force_keyframe || prev.is_empty() {
+        return true;
+    }
+
+    let total = current.len();
+    for i in 0..total {
+        if current[i] != prev[i] {
+            let x = (i % cols as usize) as u32;
+            let y = (i / cols as usize) as u32;
+            deltas.push(DeltaCell {
+                x,
+                y,
+                cell: current[i],
+            });
+        }
+    }
+
+    match options.keyframe_threshold {
+        Some(pct) => deltas.len() as f64 * 100.0 > total as f64 * pct as f64,
+        // Send a keyframe if the delta would be bigger (at 1.0, more than 60% of cells changed)
+        None => {
+            let delta_bytes = if options.regions {
+                let regions = find_regions(deltas, current, cols);
+                regions.iter().map(|r| Region::HEADER_SIZE + r.cells.len() * Cell::SIZE).sum()
+            } else {
+                deltas.len() * DeltaCell::SIZE
+            } as f64;
+            let keyframe_bytes = (total * Cell::SIZE) as f64;
+            delta_bytes > keyframe_bytes * options.max_delta_ratio as f64
+        }
+    }
+}
+
+/// Unchanged cells a run may bridge: one costs less than a new region's header.
+const MAX_GAP: u32 = 1;
+
+/// Group changed cells into rectangles taken from `current`. Rows with changes
+/// are split into runs (bridging gaps of up to `MAX_GAP` cells), and each band
+/// of consecutive changed rows becomes one bounding rectangle if at least half
+/// of it changed, or else one region per run.
+///
+/// `deltas` must be in row-major order, as `compute_delta` produces them.
+pub fn find_regions(deltas: &[DeltaCell], current: &[Cell], cols: u32) -> Vec<Region> {
+    // (y, x0, x1 inclusive, changed cells) per run
+    let mut runs: Vec<(u32, u32, u32, usize)> = Vec::new();
+    for d in deltas {
+        match runs.last_mut() {
+            Some((y, _, x1, n)) if *y == d.y && d.x - *x1 <= MAX_GAP + 1 => {
+                *x1 = d.x;
+                *n += 1;
+            }
+            _ => runs.push((d.y, d.x, d.x, 1)),
+        }
+    }
+
+    let region = |x0: u32, x1: u32, y0: u32, y1: u32| {
+        let mut cells = Vec::with_capacity((x1 - x0 + 1) as usize * (y1 - y0 + 1) as usize);
+        for y in y0..=y1 {
+            let start = y as usize * cols as usize;
+            cells.extend_from_slice(&current[start + x0 as usize..=start + x1 as usize]);
+        }
+        Region { x: x0, y: y0, width: x1 - x0 + 1, height: y1 - y0 + 1, cells }
+    };
+
+    let mut regions = Vec::new();
+    let mut band_start = 0;
+    while band_start < runs.len() {
+        // Runs on consecutive rows form a band
+        let mut band_end = band_start + 1;
+        while band_end < runs.len() && runs[band_end].0 <= runs[band_end - 1].0 + 1 {
+            band_end += 1;
+        }
+        let band = &runs[band_start..band_end];
+        let (y0, y1) = (band[0].0, band[band.len() - 1].0);
+        let x0 = band.iter().map(|r| r.1).min().unwrap_or(0);
+        let x1 = band.iter().map(|r| r.2).max().unwrap_or(0);
+        let changed: usize = band.iter().map(|r| r.3).sum();
+        let area = (x1 - x0 + 1) as usize * (y1 - y0 + 1) as usize;
+        if area <= changed * 2 {
+            regions.push(region(x0, x1, y0, y1));
+        } else {
+            regions.extend(band.iter().map(|&(y, x0, x1, _)| region(x0, x1, y, y)));
+        }
+        band_start = band_end;
+    }
+    regions
+}
@@ -1,32 +1,140 @@
+use std::collections::{BTreeMap, VecDeque};
 use std::io::{Seek, SeekFrom, Write};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
 
-use crate::compress;
+use crate::compress::{self, EncodedFrame};
+use crate::delta::{compute_delta_into, find_regions, DeltaOptions};
+use crate::metadata;
 use crate::format::*;
+use crate::subtitle::{self, SubtitleEntry};
+
+/// `Seek::seek` of a writer's output, kept by writers that checkpoint so the
+/// frame-writing code, which only needs `Write`, can go back to the header.
+type SeekFn<W> = fn(&mut W, SeekFrom) -> std::io::Result<u64>;
 
 /// Writes .rsfx files incrementally.
-pub struct RsfxWriter<W: Write + Seek> {
+pub struct RsfxWriter<W: Write> {
     writer: W,
     header: RsfxHeader,
     index: Vec<FrameIndexEntry>,
     frame_count: u32,
+    /// Bytes written so far. Tracked by hand so non-seekable outputs work.
+    position: u64,
+    /// Background compression workers (see `with_threads`)
+    pool: Option<CompressPool>,
+    /// Reused compression buffer for frames compressed inline
+    scratch: Vec<u8>,
+    /// What `write_frame` diffs against: the previous frame, or the last
+    /// keyframe with `FLAG_KEYFRAME_DELTAS`
+    reference: Vec<Cell>,
+    delta_options: DeltaOptions,
+    /// Reused by `write_frame` for each frame's changed cells
+    deltas: Vec<DeltaCell>,
+    /// Set by `request_keyframe` for the next `write_frame`
+    keyframe_requested: bool,
+    /// Sections (kind, payload) to write after the last frame
+    sections: Vec<(u8, Vec<u8>)>,
+    /// Frames between checkpoints, and how to seek back to the header for one
+    /// (see `with_checkpoints`)
+    checkpoints: Option<(u32, SeekFn<W>)>,
 }
 
 impl<W: Write + Seek> RsfxWriter<W> {
-    /// Create a new writer. Writes a placeholder header immediately.
-    pub fn new(mut writer: W, cols: u16, rows: u16, fps: u16, keyframe_interval: u16) -> anyhow::Result<Self> {
-        let header = RsfxHeader {
-            cols,
-            rows,
-            fps_num: fps,
-            fps_den: 1,
-            frame_count: 0,
-            keyframe_interval,
-            audio_sample_rate: 0,
-            audio_channels: 0,
-            audio_offset: 0,
-            audio_length: 0,
-            index_offset: 0,
-        };
+    /// Create a new writer for a grid of up to `MAX_DIMENSION` cells a side.
+    /// Writes a placeholder header immediately.
+    pub fn new(writer: W, cols: u32, rows: u32, fps: u16, keyframe_interval: u16) -> anyhow::Result<Self> {
+        Self::new_with_fps(writer, cols, rows, fps, 1, keyframe_interval)
+    }
+
+    /// Create a new writer with a fractional frame rate of `fps_num / fps_den`
+    /// (e.g. 24000/1001 for 23.976 fps).
+    pub fn new_with_fps(
+        writer: W,
+        cols: u32,
+        rows: u32,
+        fps_num: u16,
+        fps_den: u16,
+        keyframe_interval: u16,
+    ) -> anyhow::Result<Self> {
+        let header = RsfxHeader::new(cols, rows, fps_num, fps_den, keyframe_interval)?;
+        Self::with_header(writer, header)
+    }
+
+    /// Checkpoint the file every `every` frames (0 turns it off), so that a
+    /// writer that never gets to `finish` (the process is killed, say) leaves a
+    /// readable file behind.
+    ///
+    /// A checkpoint flushes what's been written and patches the header with the
+    /// frame count and audio so far and an index offset of 0, which tells
+    /// `RsfxReader` to rebuild the index from the inline records. That recovers
+    /// every frame written in full, at least those up to the last checkpoint,
+    /// and drops a frame cut off partway. Subtitles and metadata only go out at
+    /// `finish`. Streamed files (`FLAG_FOOTER`) aren't checkpointed.
+    ///
+    /// (An index isn't written at checkpoints: the frames that follow would
+    /// overwrite it in part, leaving stale bytes that read as records.)
+    pub fn with_checkpoints(mut self, every: u32) -> Self {
+        self.checkpoints = (every > 0 && self.header.flags & FLAG_FOOTER == 0).then_some((every, W::seek));
+        self
+    }
+
+    /// Finalize: write frame index, update header, flush.
+    pub fn finish(mut self) -> anyhow::Result<W> {
+        if self.header.flags & FLAG_FOOTER != 0 {
+            return self.finish_streaming();
+        }
+        self.flush_pool()?;
+        self.write_sections()?;
+
+        // Write frame index
+        self.write_end_record()?;
+        let index_offset = self.position;
+        self.write_index()?;
+
+        // Update header
+        self.header.frame_count = self.frame_count;
+        self.header.index_offset = index_offset;
+
+        // Seek back and rewrite header
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&self.header.to_bytes())?;
+
+        // Seek to end
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> RsfxWriter<W> {
+    /// Create a writer for non-seekable output (pipes, stdout).
+    ///
+    /// The header is written up front with `FLAG_FOOTER` set; frame count, index
+    /// offset and audio info go into a footer at EOF instead of being patched in.
+    pub fn new_streaming(
+        writer: W,
+        cols: u32,
+        rows: u32,
+        fps_num: u16,
+        fps_den: u16,
+        keyframe_interval: u16,
+    ) -> anyhow::Result<Self> {
+        let mut header = RsfxHeader::new(cols, rows, fps_num, fps_den, keyframe_interval)?;
+        header.flags |= FLAG_FOOTER;
+        Self::with_header(writer, header)
+    }
+
+    /// Create a writer from a prepared header, e.g. one with `FLAG_ALPHA` or `FLAG_GLYPHS` set.
+    /// Frame count, offsets and audio fields are filled in as the file is written.
+    pub fn with_header(mut writer: W, mut header: RsfxHeader) -> anyhow::Result<Self> {
+        header.check_dimensions()?;
+        // Every frame carries an inline type tag + size, so files can be read
+        // sequentially and survive a damaged index
+        header.flags |= FLAG_INLINE_FRAMES | FLAG_RAW_FRAMES;
+
         // Write placeholder header
         writer.write_all(&header.to_bytes())?;
         Ok(Self {
@@ -34,51 +142,258 @@ impl<W: Write + Seek> RsfxWriter<W> {
             header,
             index: Vec::new(),
             frame_count: 0,
+            position: HEADER_SIZE as u64,
+            pool: None,
+            scratch: Vec::new(),
+            reference: Vec::new(),
+            delta_options: DeltaOptions::default(),
+            deltas: Vec::new(),
+            keyframe_requested: false,
+            sections: Vec::new(),
+            checkpoints: None,
         })
     }
 
+    /// Formerly opted in to inline records (`FLAG_INLINE_FRAMES`); every writer
+    /// writes them now, so this leaves the writer as it is.
+    #[deprecated(note = "inline records are always written")]
+    pub fn with_inline_frames(self) -> anyhow::Result<Self> {
+        Ok(self)
+    }
+
+    /// Compress frames on `threads` background threads instead of inline.
+    ///
+    /// Frames are still written in the order they were submitted, so offsets and
+    /// the index are identical to single-threaded output. `threads <= 1` turns the
+    /// pool off. Any frames already queued on a previous pool are written first.
+    pub fn with_threads(mut self, threads: usize) -> anyhow::Result<Self> {
+        self.flush_pool()?;
+        self.pool = (threads > 1).then(|| CompressPool::new(threads));
+        Ok(self)
+    }
+
+    /// Keyframe promotion settings for `write_frame`. `regions` is ignored; it
+    /// follows the header's `FLAG_REGION_DELTAS`.
+    pub fn set_delta_options(&mut self, options: DeltaOptions) {
+        self.delta_options = options;
+    }
+
+    /// Make the next `write_frame` a keyframe, e.g. at a scene cut.
+    pub fn request_keyframe(&mut self) {
+        self.keyframe_requested = true;
+    }
+
+    /// Write the next frame (full cell grid, row-major), choosing between a
+    /// keyframe and a delta.
+    ///
+    /// Keyframes go out every `keyframe_interval` frames, after
+    /// `request_keyframe`, and whenever the delta isn't worth it (see
+    /// `set_delta_options`). Deltas are against the previous frame, or the last
+    /// keyframe when the header has `FLAG_KEYFRAME_DELTAS`, and are written as
+    /// regions when it has `FLAG_REGION_DELTAS`.
+    ///
+    /// The previous frame is only tracked here, so don't mix this with
+    /// `write_keyframe`/`write_delta` in the same file.
+    pub fn write_frame(&mut self, cells: &[Cell]) -> anyhow::Result<FrameType> {
+        let (cols, rows) = (self.header.cols, self.header.rows);
+        if cells.len() != cols as usize * rows as usize {
+            anyhow::bail!("frame has {} cells, expected {cols}x{rows}", cells.len());
+        }
+        let interval = self.header.keyframe_interval.max(1) as usize;
+        let force_keyframe = std::mem::take(&mut self.keyframe_requested) || self.submitted() % interval == 0;
+        let regions = self.header.flags & FLAG_REGION_DELTAS != 0;
+        let options = DeltaOptions { regions, ..self.delta_options };
+
+        let mut deltas = std::mem::take(&mut self.deltas);
+        let keyframe = compute_delta_into(&self.reference, cells, cols, force_keyframe, &options, &mut deltas);
+        let result = if keyframe {
+            self.write_keyframe(cells)
+        } else if regions {
+            self.write_regions(&find_regions(&deltas, cells, cols))
+        } else {
+            self.write_delta(&deltas)
+        };
+        self.deltas = deltas;
+        result?;
+
+        if keyframe || !self.header.keyframe_deltas() {
+            self.reference.clear();
+            self.reference.extend_from_slice(cells);
+        }
+        Ok(if keyframe { FrameType::Keyframe } else { FrameType::Delta })
+    }
+
     /// Write a keyframe (full cell grid, row-major).
     pub fn write_keyframe(&mut self, cells: &[Cell]) -> anyhow::Result<()> {
-        let mut raw = Vec::with_capacity(cells.len() * Cell::SIZE);
-        for c in cells {
-            raw.extend_from_slice(&c.to_bytes());
+        let layout = CellLayout::from_flags(self.header.flags);
+        let mut raw = Vec::with_capacity(cells.len() * layout.cell_size());
+        cells.iter().for_each(|c| layout.write_cell(c, &mut raw));
+        self.write_frame_data(raw, FrameType::Keyframe)
+    }
+
+    /// Write a delta frame (list of changed cells).
+    ///
+    /// A delta with no changes is stored raw with no data, so a static stretch
+    /// costs only the record prefix and index entry per frame.
+    ///
+    /// Files with `FLAG_REGION_DELTAS` store the cells as the regions
+    /// `Region::from_deltas` makes of them.
+    pub fn write_delta(&mut self, deltas: &[DeltaCell]) -> anyhow::Result<()> {
+        if self.header.flags & FLAG_REGION_DELTAS != 0 {
+            return self.write_regions(&Region::from_deltas(deltas));
         }
-        let compressed = compress::compress(&raw);
-        let offset = self.writer.stream_position()?;
-        self.writer.write_all(&compressed)?;
+        let layout = CellLayout::from_flags(self.header.flags);
+        let mut raw = Vec::with_capacity(deltas.len() * layout.delta_size());
+        deltas.iter().for_each(|d| layout.write_delta(d, &mut raw));
+        self.write_frame_data(raw, FrameType::Delta)
+    }
 
-        self.index.push(FrameIndexEntry {
-            offset,
-            compressed_size: compressed.len() as u32,
-            frame_type: FrameType::Keyframe,
-        });
-        self.frame_count += 1;
+    /// Write a delta frame as changed rectangles. Needs `FLAG_REGION_DELTAS`.
+    pub fn write_regions(&mut self, regions: &[Region]) -> anyhow::Result<()> {
+        if self.header.flags & FLAG_REGION_DELTAS == 0 {
+            anyhow::bail!("region deltas need a header with FLAG_REGION_DELTAS");
+        }
+        let layout = CellLayout::from_flags(self.header.flags);
+        let mut raw = Vec::with_capacity(regions.iter().map(|r| layout.region_size(r)).sum());
+        regions.iter().for_each(|r| layout.write_region(r, &mut raw));
+        self.write_frame_data(raw, FrameType::Delta)
+    }
+
+    /// Write a frame that's already encoded (e.g. from
+    /// `RsfxReader::read_frame_compressed`) verbatim, LZ4 or raw as it was
+    /// stored, skipping the re-encode.
+    pub fn write_frame_compressed(&mut self, frame: &EncodedFrame, frame_type: FrameType) -> anyhow::Result<()> {
+        // LZ4 data laid out for another file (framed in other blocks, or not
+        // framed at all) is re-encoded to suit this one
+        let block_size = match frame_type {
+            FrameType::Keyframe => self.header.keyframe_block_size(),
+            FrameType::Delta => None,
+        };
+        if !frame.stored_raw && frame.block_size != block_size {
+            let raw = match frame.block_size {
+                Some(_) => compress::decompress_framed(&frame.data)?,
+                None => compress::decompress(&frame.data)?,
+            };
+            return self.write_frame_data(raw, frame_type);
+        }
+        self.check_frame_order(frame_type)?;
+        self.flush_pool()?;
+        self.commit_frame(&frame.data, frame.stored_raw, frame_type)
+    }
+
+    fn write_frame_data(&mut self, raw: Vec<u8>, frame_type: FrameType) -> anyhow::Result<()> {
+        self.check_frame_order(frame_type)?;
+        let block_size = match frame_type {
+            FrameType::Keyframe => self.header.keyframe_block_size(),
+            FrameType::Delta => None,
+        };
+        match self.pool.as_mut() {
+            Some(pool) => {
+                pool.submit(raw, frame_type, block_size)?;
+                self.commit_finished()
+            }
+            None => {
+                let encoded = match block_size {
+                    Some(block_size) => compress::encode_frame_framed(raw, block_size),
+                    None => compress::encode_frame_with(raw, &mut self.scratch),
+                };
+                self.commit_frame(&encoded.data, encoded.stored_raw, frame_type)
+            }
+        }
+    }
+
+    /// Deltas apply to the previous frame, so playback can only start from a keyframe.
+    fn check_frame_order(&self, frame_type: FrameType) -> anyhow::Result<()> {
+        if self.submitted() == 0 && frame_type != FrameType::Keyframe {
+            anyhow::bail!("the first frame must be a keyframe");
+        }
         Ok(())
     }
 
-    /// Write a delta frame (list of changed cells).
-    pub fn write_delta(&mut self, deltas: &[DeltaCell]) -> anyhow::Result<()> {
-        let mut raw = Vec::with_capacity(deltas.len() * DeltaCell::SIZE);
-        for d in deltas {
-            raw.extend_from_slice(&d.to_bytes());
+    /// Frames written so far, including those still being compressed.
+    fn submitted(&self) -> usize {
+        self.frame_count as usize + self.pool.as_ref().map_or(0, |pool| pool.in_flight.len())
+    }
+
+    /// Write an encoded frame (LZ4, or raw with `stored_raw`) and add its index entry.
+    fn commit_frame(&mut self, data: &[u8], stored_raw: bool, frame_type: FrameType) -> anyhow::Result<()> {
+        let offset = self.position;
+        if self.header.flags & FLAG_INLINE_FRAMES != 0 {
+            let mut prefix = [0u8; FRAME_PREFIX_SIZE];
+            prefix[0] = frame_type as u8 | if stored_raw { FRAME_STORED_RAW } else { 0 };
+            prefix[1..5].copy_from_slice(&(data.len() as u32).to_le_bytes());
+            self.write_bytes(&prefix)?;
         }
-        let compressed = compress::compress(&raw);
-        let offset = self.writer.stream_position()?;
-        self.writer.write_all(&compressed)?;
+        self.write_bytes(data)?;
 
+        let raw_size = if stored_raw { Some(data.len()) } else { compress::raw_size(data) };
         self.index.push(FrameIndexEntry {
             offset,
-            compressed_size: compressed.len() as u32,
-            frame_type: FrameType::Delta,
+            compressed_size: data.len() as u32,
+            frame_type,
+            raw_size: raw_size.map_or(0, |size| size as u32),
+            stored_raw,
         });
         self.frame_count += 1;
+        if let Some((every, seek)) = self.checkpoints {
+            if self.frame_count % every == 0 {
+                self.checkpoint(seek)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Make the file readable as it stands (see `with_checkpoints`).
+    fn checkpoint(&mut self, seek: SeekFn<W>) -> anyhow::Result<()> {
+        let mut header = self.header.clone();
+        header.frame_count = self.frame_count;
+        header.index_offset = 0;
+        // Seeking flushes a buffered writer, so the frames are out before the header
+        seek(&mut self.writer, SeekFrom::Start(0))?;
+        self.writer.write_all(&header.to_bytes())?;
+        seek(&mut self.writer, SeekFrom::Start(self.position))?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Write whatever the pool has finished in order, blocking only if too many
+    /// frames are queued.
+    fn commit_finished(&mut self) -> anyhow::Result<()> {
+        while let Some(pool) = self.pool.as_mut() {
+            let wait = pool.in_flight.len() > pool.max_in_flight;
+            match pool.next_done(wait)? {
+                Some((frame_type, encoded)) => self.commit_frame(&encoded.data, encoded.stored_raw, frame_type)?,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait for every queued frame and write it out.
+    fn flush_pool(&mut self) -> anyhow::Result<()> {
+        while let Some(pool) = self.pool.as_mut() {
+            match pool.next_done(true)? {
+                Some((frame_type, encoded)) => self.commit_frame(&encoded.data, encoded.stored_raw, frame_type)?,
+                None => break,
+            }
+        }
         Ok(())
     }
 
-    /// Write raw PCM audio data. Call after all frames.
+    /// Write raw PCM audio data. May come before or after the frames; streamed
+    /// output should write it first so sequential readers get it before playback.
     pub fn write_audio(&mut self, pcm_data: &[u8], sample_rate: u32, channels: u16) -> anyhow::Result<()> {
-        let offset = self.writer.stream_position()?;
-        self.writer.write_all(pcm_data)?;
+        self.flush_pool()?;
+        if self.header.flags & FLAG_INLINE_FRAMES != 0 {
+            let mut prefix = [0u8; AUDIO_PREFIX_SIZE];
+            prefix[0] = RECORD_AUDIO;
+            prefix[1..5].copy_from_slice(&sample_rate.to_le_bytes());
+            prefix[5..7].copy_from_slice(&channels.to_le_bytes());
+            prefix[7..15].copy_from_slice(&(pcm_data.len() as u64).to_le_bytes());
+            self.write_bytes(&prefix)?;
+        }
+        let offset = self.position;
+        self.write_bytes(pcm_data)?;
         self.header.audio_offset = offset;
         self.header.audio_length = pcm_data.len() as u64;
         self.header.audio_sample_rate = sample_rate;
@@ -86,26 +401,362 @@ impl<W: Write + Seek> RsfxWriter<W> {
         Ok(())
     }
 
-    /// Finalize: write frame index, update header, flush.
-    pub fn finish(mut self) -> anyhow::Result<W> {
-        // Write frame index
-        let index_offset = self.writer.stream_position()?;
-        for entry in &self.index {
-            self.writer.write_all(&entry.to_bytes())?;
+    /// Set or clear the loop hint (`FLAG_LOOP`). Streamed output has already sent
+    /// its header, so for those set the flag on the header given to `with_header`;
+    /// changing it here afterwards is an error.
+    pub fn set_loop(&mut self, looping: bool) -> anyhow::Result<()> {
+        if self.header.loop_hint() == looping {
+            return Ok(());
+        }
+        if self.header.flags & FLAG_FOOTER != 0 {
+            anyhow::bail!("the loop hint of a streamed file must be set before its header is written");
         }
+        self.header.flags ^= FLAG_LOOP;
+        Ok(())
+    }
 
-        // Update header
-        self.header.frame_count = self.frame_count;
-        self.header.index_offset = index_offset;
+    /// Store a subtitle track, written after the last frame when the file is
+    /// finished. Replaces any earlier subtitles.
+    pub fn write_subtitles(&mut self, entries: &[SubtitleEntry]) -> anyhow::Result<()> {
+        self.set_section(SECTION_SUBTITLES, subtitle::to_bytes(entries))
+    }
 
-        // Seek back and rewrite header
-        self.writer.seek(SeekFrom::Start(0))?;
-        self.writer.write_all(&self.header.to_bytes())?;
+    /// Store key/value metadata (e.g. how the file was made), written after the
+    /// last frame when the file is finished. Replaces any earlier metadata.
+    pub fn write_metadata(&mut self, pairs: &[(String, String)]) -> anyhow::Result<()> {
+        self.set_section(SECTION_METADATA, metadata::to_bytes(pairs))
+    }
 
-        // Seek to end
-        self.writer.seek(SeekFrom::End(0))?;
+    fn set_section(&mut self, kind: u8, payload: Vec<u8>) -> anyhow::Result<()> {
+        if payload.len() > u32::MAX as usize {
+            anyhow::bail!("section too large: {} bytes", payload.len());
+        }
+        self.sections.retain(|(k, _)| *k != kind);
+        self.sections.push((kind, payload));
+        Ok(())
+    }
+
+    /// Write the pending sections as inline records and point the header at them.
+    fn write_sections(&mut self) -> anyhow::Result<()> {
+        if self.sections.is_empty() || self.header.flags & FLAG_INLINE_FRAMES == 0 {
+            return Ok(());
+        }
+        self.header.sections_offset = self.position;
+        for (kind, payload) in std::mem::take(&mut self.sections) {
+            let mut prefix = [0u8; SECTION_PREFIX_SIZE];
+            prefix[0] = RECORD_SECTION;
+            prefix[1] = kind;
+            prefix[2..6].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+            self.write_bytes(&prefix)?;
+            self.write_bytes(&payload)?;
+        }
+        Ok(())
+    }
+
+    /// Finalize a streaming writer: write frame index and footer, flush.
+    pub fn finish_streaming(mut self) -> anyhow::Result<W> {
+        if self.header.flags & FLAG_FOOTER == 0 {
+            anyhow::bail!("finish_streaming requires a writer created with new_streaming");
+        }
+        self.flush_pool()?;
+        self.write_sections()?;
+
+        self.write_end_record()?;
+        self.header.frame_count = self.frame_count;
+        self.header.index_offset = self.position;
+        self.write_index()?;
+
+        let footer = RsfxFooter::from_header(&self.header);
+        self.write_bytes(&footer.to_bytes())?;
         self.writer.flush()?;
 
         Ok(self.writer)
     }
+
+    /// Mark the end of the inline records, if the file has them.
+    fn write_end_record(&mut self) -> anyhow::Result<()> {
+        if self.header.flags & FLAG_INLINE_FRAMES == 0 {
+            return Ok(());
+        }
+        let mut record = [0u8; FRAME_PREFIX_SIZE];
+        record[0] = RECORD_END;
+        self.write_bytes(&record)
+    }
+
+    fn write_index(&mut self) -> anyhow::Result<()> {
+        let mut buf = Vec::with_capacity(self.index.len() * FrameIndexEntry::SIZE);
+        for entry in &self.index {
+            buf.extend_from_slice(&entry.to_bytes());
+        }
+        self.write_bytes(&buf)
+    }
+
+    fn write_bytes(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        self.writer.write_all(data)?;
+        self.position += data.len() as u64;
+        Ok(())
+    }
+}
+
+/// Collects a file's settings and creates a configured `RsfxWriter`, so new
+/// options don't have to change a constructor signature, e.g.
+/// `RsfxBuilder::new(cols, rows).fps(24000, 1001).threads(4).build(file)?`.
+#[derive(Clone, Debug)]
+pub struct RsfxBuilder {
+    cols: u32,
+    rows: u32,
+    fps_num: u16,
+    fps_den: u16,
+    keyframe_interval: u16,
+    flags: u16,
+    cell_aspect: u16,
+    metadata: Vec<(String, String)>,
+    threads: usize,
+    delta_options: DeltaOptions,
+    checkpoint_interval: u32,
+}
+
+impl RsfxBuilder {
+    /// A `cols x rows` file at 30 fps with a keyframe every 30 frames. `build`
+    /// fails for grids over `MAX_DIMENSION` cells a side.
+    pub fn new(cols: u32, rows: u32) -> Self {
+        Self {
+            cols,
+            rows,
+            fps_num: 30,
+            fps_den: 1,
+            keyframe_interval: 30,
+            flags: 0,
+            cell_aspect: 0,
+            metadata: Vec::new(),
+            threads: 1,
+            delta_options: DeltaOptions::default(),
+            checkpoint_interval: 0,
+        }
+    }
+
+    /// Frame rate as `num / den` (e.g. 24000/1001 for 23.976 fps).
+    pub fn fps(mut self, num: u16, den: u16) -> Self {
+        self.fps_num = num;
+        self.fps_den = den;
+        self
+    }
+
+    /// Frames between forced keyframes in `write_frame`.
+    pub fn keyframe_interval(mut self, interval: u16) -> Self {
+        self.keyframe_interval = interval;
+        self
+    }
+
+    /// Store cell alpha (`FLAG_ALPHA`).
+    pub fn alpha(self, on: bool) -> Self {
+        self.flag(FLAG_ALPHA, on)
+    }
+
+    /// Store a glyph per cell (`FLAG_GLYPHS`).
+    pub fn glyphs(self, on: bool) -> Self {
+        self.flag(FLAG_GLYPHS, on)
+    }
+
+    /// Ask players to loop the file (`FLAG_LOOP`).
+    pub fn looping(self, on: bool) -> Self {
+        self.flag(FLAG_LOOP, on)
+    }
+
+    /// Make deltas relative to the last keyframe (`FLAG_KEYFRAME_DELTAS`).
+    pub fn keyframe_deltas(self, on: bool) -> Self {
+        self.flag(FLAG_KEYFRAME_DELTAS, on)
+    }
+
+    /// Store deltas as changed rectangles (`FLAG_REGION_DELTAS`).
+    pub fn region_deltas(self, on: bool) -> Self {
+        self.flag(FLAG_REGION_DELTAS, on)
+    }
+
+    /// Compress keyframes in blocks of rows (`FLAG_FRAMED_KEYFRAMES`).
+    pub fn framed_keyframes(self, on: bool) -> Self {
+        self.flag(FLAG_FRAMED_KEYFRAMES, on)
+    }
+
+    /// Record the height-to-width ratio of the cells the file was made for
+    /// (`RsfxHeader::cell_aspect`), e.g. 2.0.
+    pub fn cell_aspect(mut self, ratio: f32) -> Self {
+        self.cell_aspect = (ratio * 100.0).round().clamp(0.0, u16::MAX as f32) as u16;
+        self
+    }
+
+    /// Key/value metadata written with the file (see `RsfxWriter::write_metadata`).
+    pub fn metadata(mut self, pairs: Vec<(String, String)>) -> Self {
+        self.metadata = pairs;
+        self
+    }
+
+    /// Background compression threads (see `RsfxWriter::with_threads`).
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Keyframe promotion settings (see `RsfxWriter::set_delta_options`).
+    pub fn delta_options(mut self, options: DeltaOptions) -> Self {
+        self.delta_options = options;
+        self
+    }
+
+    /// Frames between checkpoints of a seekable file, 0 for none (see
+    /// `RsfxWriter::with_checkpoints`).
+    pub fn checkpoint_interval(mut self, frames: u32) -> Self {
+        self.checkpoint_interval = frames;
+        self
+    }
+
+    fn flag(mut self, flag: u16, on: bool) -> Self {
+        if on {
+            self.flags |= flag;
+        } else {
+            self.flags &= !flag;
+        }
+        self
+    }
+
+    /// The header the file will start with. Fails on an invalid frame rate.
+    pub fn header(&self) -> anyhow::Result<RsfxHeader> {
+        let mut header = RsfxHeader::new(self.cols, self.rows, self.fps_num, self.fps_den, self.keyframe_interval)?;
+        header.flags |= self.flags;
+        header.cell_aspect = self.cell_aspect;
+        Ok(header)
+    }
+
+    /// Create a writer for a seekable output; the header is patched in by `finish`.
+    pub fn build<W: Write + Seek>(self, writer: W) -> anyhow::Result<RsfxWriter<W>> {
+        let header = self.header()?;
+        let every = self.checkpoint_interval;
+        Ok(self.configure(RsfxWriter::with_header(writer, header)?)?.with_checkpoints(every))
+    }
+
+    /// Create a writer for non-seekable output, finished with `finish_streaming`
+    /// (see `RsfxWriter::new_streaming`).
+    pub fn build_streaming<W: Write>(self, writer: W) -> anyhow::Result<RsfxWriter<W>> {
+        let mut header = self.header()?;
+        header.flags |= FLAG_FOOTER;
+        self.configure(RsfxWriter::with_header(writer, header)?)
+    }
+
+    fn configure<W: Write>(self, writer: RsfxWriter<W>) -> anyhow::Result<RsfxWriter<W>> {
+        let mut writer = writer.with_threads(self.threads)?;
+        writer.set_delta_options(self.delta_options);
+        if !self.metadata.is_empty() {
+            writer.write_metadata(&self.metadata)?;
+        }
+        Ok(writer)
+    }
+}
+
+/// A frame for `CompressPool`: its number, raw bytes and, for a framed
+/// keyframe, the block size.
+type Job = (u64, Vec<u8>, Option<usize>);
+
+/// Worker threads that compress frames off the caller's thread.
+///
+/// Jobs are numbered as they're submitted; results can come back in any order and
+/// are held in `ready` until every earlier frame has been handed out.
+struct CompressPool {
+    jobs: Option<Sender<Job>>,
+    results: Receiver<(u64, EncodedFrame)>,
+    workers: Vec<JoinHandle<()>>,
+    /// Frame types of submitted frames not yet handed back, oldest first
+    in_flight: VecDeque<FrameType>,
+    /// Encoded frames that finished ahead of their turn
+    ready: BTreeMap<u64, EncodedFrame>,
+    next_submit: u64,
+    next_done: u64,
+    /// Bounds memory when compression can't keep up with the caller
+    max_in_flight: usize,
+}
+
+impl CompressPool {
+    fn new(threads: usize) -> Self {
+        let (job_tx, job_rx) = mpsc::channel::<Job>();
+        let (result_tx, result_rx) = mpsc::channel();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+
+        let workers = (0..threads)
+            .map(|_| {
+                let job_rx = Arc::clone(&job_rx);
+                let result_tx = result_tx.clone();
+                thread::spawn(move || {
+                    let mut scratch = Vec::new();
+                    loop {
+                        let job = match job_rx.lock() {
+                            Ok(rx) => rx.recv(),
+                            Err(_) => break,
+                        };
+                        let Ok((seq, raw, block_size)) = job else { break };
+                        let encoded = match block_size {
+                            Some(block_size) => compress::encode_frame_framed(raw, block_size),
+                            None => compress::encode_frame_with(raw, &mut scratch),
+                        };
+                        if result_tx.send((seq, encoded)).is_err() {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            jobs: Some(job_tx),
+            results: result_rx,
+            workers,
+            in_flight: VecDeque::new(),
+            ready: BTreeMap::new(),
+            next_submit: 0,
+            next_done: 0,
+            max_in_flight: threads * 2,
+        }
+    }
+
+    fn submit(&mut self, raw: Vec<u8>, frame_type: FrameType, block_size: Option<usize>) -> anyhow::Result<()> {
+        let jobs = self.jobs.as_ref().expect("jobs sender lives until drop");
+        jobs.send((self.next_submit, raw, block_size))
+            .map_err(|_| anyhow::anyhow!("compression workers exited"))?;
+        self.next_submit += 1;
+        self.in_flight.push_back(frame_type);
+        Ok(())
+    }
+
+    /// The next frame in submit order, if it's compressed. With `wait`, blocks
+    /// until it is. Returns `None` when nothing is queued.
+    fn next_done(&mut self, wait: bool) -> anyhow::Result<Option<(FrameType, EncodedFrame)>> {
+        if self.in_flight.is_empty() {
+            return Ok(None);
+        }
+        loop {
+            if let Some(encoded) = self.ready.remove(&self.next_done) {
+                self.next_done += 1;
+                let frame_type = self.in_flight.pop_front().expect("in-flight frame");
+                return Ok(Some((frame_type, encoded)));
+            }
+            let (seq, encoded) = if wait {
+                self.results.recv().map_err(|_| anyhow::anyhow!("compression workers exited"))?
+            } else {
+                match self.results.try_recv() {
+                    Ok(result) => result,
+                    Err(TryRecvError::Empty) => return Ok(None),
+                    Err(TryRecvError::Disconnected) => anyhow::bail!("compression workers exited"),
+                }
+            };
+            self.ready.insert(seq, encoded);
+        }
+    }
+}
+
+impl Drop for CompressPool {
+    fn drop(&mut self) {
+        // Closing the job channel lets idle workers exit
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
 }
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 /// A single terminal cell: background color (top pixel) + foreground color (bottom pixel).
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Cell {
@@ -9,10 +11,21 @@ pub struct Cell {
     pub fg_r: u8,
     pub fg_g: u8,
     pub fg_b: u8,
+    /// Per-pixel opacity. Only stored in files with `FLAG_ALPHA`; always
+    /// `OPAQUE` otherwise.
+    pub bg_a: u8,
+    pub fg_a: u8,
+    /// Character drawn in the fg color over the bg color instead of the half-block
+    /// (for captions and text modes). Should be a single-column character. Only
+    /// stored in files with `FLAG_GLYPHS`; always `None` otherwise.
+    pub glyph: Option<char>,
 }
 
 impl Cell {
     pub const SIZE: usize = 6;
+    /// Serialized size in files with `FLAG_ALPHA`
+    pub const SIZE_ALPHA: usize = 8;
+    pub const OPAQUE: u8 = 255;
 
     pub fn to_bytes(&self) -> [u8; 6] {
         [self.bg_r, self.bg_g, self.bg_b, self.fg_r, self.fg_g, self.fg_b]
@@ -26,6 +39,110 @@ impl Cell {
             fg_r: b[3],
             fg_g: b[4],
             fg_b: b[5],
+            bg_a: Self::OPAQUE,
+            fg_a: Self::OPAQUE,
+            glyph: None,
+        }
+    }
+
+    pub fn to_bytes_alpha(&self) -> [u8; 8] {
+        [self.bg_r, self.bg_g, self.bg_b, self.fg_r, self.fg_g, self.fg_b, self.bg_a, self.fg_a]
+    }
+
+    pub fn from_bytes_alpha(b: &[u8]) -> Self {
+        Self {
+            bg_a: b[6],
+            fg_a: b[7],
+            ..Self::from_bytes(b)
+        }
+    }
+
+    /// An opaque cell showing `top` in its upper half (bg) and `bottom` in its
+    /// lower half (fg), as RGB.
+    pub fn from_pixels(top: [u8; 3], bottom: [u8; 3]) -> Self {
+        Self::from_rgba_pixels([top[0], top[1], top[2], Self::OPAQUE], [bottom[0], bottom[1], bottom[2], Self::OPAQUE])
+    }
+
+    /// The upper (bg) and lower (fg) pixels, as RGB.
+    pub fn to_pixels(&self) -> ([u8; 3], [u8; 3]) {
+        ([self.bg_r, self.bg_g, self.bg_b], [self.fg_r, self.fg_g, self.fg_b])
+    }
+
+    /// Like `from_pixels`, with each half's alpha.
+    pub fn from_rgba_pixels(top: [u8; 4], bottom: [u8; 4]) -> Self {
+        Self {
+            bg_r: top[0],
+            bg_g: top[1],
+            bg_b: top[2],
+            fg_r: bottom[0],
+            fg_g: bottom[1],
+            fg_b: bottom[2],
+            bg_a: top[3],
+            fg_a: bottom[3],
+            glyph: None,
+        }
+    }
+
+    /// Like `to_pixels`, with each half's alpha.
+    pub fn to_rgba_pixels(&self) -> ([u8; 4], [u8; 4]) {
+        (
+            [self.bg_r, self.bg_g, self.bg_b, self.bg_a],
+            [self.fg_r, self.fg_g, self.fg_b, self.fg_a],
+        )
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        self.bg_a == Self::OPAQUE && self.fg_a == Self::OPAQUE
+    }
+
+    /// Blend both halves over a solid `(r, g, b)` background. The result is opaque.
+    pub fn composite(&self, bg: (u8, u8, u8)) -> Cell {
+        if self.is_opaque() {
+            return *self;
+        }
+        let blend = |c: u8, under: u8, a: u8| ((c as u16 * a as u16 + under as u16 * (255 - a as u16) + 127) / 255) as u8;
+        Cell {
+            bg_r: blend(self.bg_r, bg.0, self.bg_a),
+            bg_g: blend(self.bg_g, bg.1, self.bg_a),
+            bg_b: blend(self.bg_b, bg.2, self.bg_a),
+            fg_r: blend(self.fg_r, bg.0, self.fg_a),
+            fg_g: blend(self.fg_g, bg.1, self.fg_a),
+            fg_b: blend(self.fg_b, bg.2, self.fg_a),
+            bg_a: Self::OPAQUE,
+            fg_a: Self::OPAQUE,
+            glyph: self.glyph,
+        }
+    }
+}
+
+/// Which half-block character draws a cell's two pixels in a terminal. Cells
+/// always store the top pixel as bg and the bottom one as fg; `Upper` swaps them
+/// when drawing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum HalfBlock {
+    /// `▄`: the bottom pixel in the fg color
+    #[default]
+    Lower,
+    /// `▀`: the top pixel in the fg color, for fonts that leave a gap under `▄`
+    Upper,
+}
+
+impl HalfBlock {
+    pub fn glyph(self) -> &'static str {
+        match self {
+            HalfBlock::Lower => "▄",
+            HalfBlock::Upper => "▀",
+        }
+    }
+
+    /// Terminal (bg, fg) colors for a cell. Cells with a glyph keep their colors as is.
+    pub fn colors(self, cell: &Cell) -> ((u8, u8, u8), (u8, u8, u8)) {
+        let top = (cell.bg_r, cell.bg_g, cell.bg_b);
+        let bottom = (cell.fg_r, cell.fg_g, cell.fg_b);
+        match self {
+            HalfBlock::Upper if cell.glyph.is_none() => (bottom, top),
+            _ => (top, bottom),
         }
     }
 }
@@ -33,27 +150,291 @@ impl Cell {
 /// A changed cell in a delta frame: position + new cell data.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct DeltaCell {
-    pub x: u16,
-    pub y: u16,
+    pub x: u32,
+    pub y: u32,
     pub cell: Cell,
 }
 
+/// `to_bytes`/`from_bytes` and the sizes are the 16-bit coordinate encoding;
+/// `CellLayout` also handles files with `FLAG_WIDE_GRID`.
 impl DeltaCell {
     pub const SIZE: usize = 10; // 2 + 2 + 6
+    /// Serialized size in files with `FLAG_ALPHA`
+    pub const SIZE_ALPHA: usize = 12; // 2 + 2 + 8
 
     pub fn to_bytes(&self) -> [u8; 10] {
-        let xb = self.x.to_le_bytes();
-        let yb = self.y.to_le_bytes();
+        let xb = narrow_coord(self.x).to_le_bytes();
+        let yb = narrow_coord(self.y).to_le_bytes();
         let cb = self.cell.to_bytes();
         [xb[0], xb[1], yb[0], yb[1], cb[0], cb[1], cb[2], cb[3], cb[4], cb[5]]
     }
 
     pub fn from_bytes(b: &[u8]) -> Self {
-        let x = u16::from_le_bytes([b[0], b[1]]);
-        let y = u16::from_le_bytes([b[2], b[3]]);
+        let x = u16::from_le_bytes([b[0], b[1]]) as u32;
+        let y = u16::from_le_bytes([b[2], b[3]]) as u32;
         let cell = Cell::from_bytes(&b[4..10]);
         Self { x, y, cell }
     }
+
+    pub fn to_bytes_alpha(&self) -> [u8; 12] {
+        let mut b = [0u8; 12];
+        b[0..2].copy_from_slice(&narrow_coord(self.x).to_le_bytes());
+        b[2..4].copy_from_slice(&narrow_coord(self.y).to_le_bytes());
+        b[4..12].copy_from_slice(&self.cell.to_bytes_alpha());
+        b
+    }
+
+    pub fn from_bytes_alpha(b: &[u8]) -> Self {
+        let x = u16::from_le_bytes([b[0], b[1]]) as u32;
+        let y = u16::from_le_bytes([b[2], b[3]]) as u32;
+        let cell = Cell::from_bytes_alpha(&b[4..12]);
+        Self { x, y, cell }
+    }
+}
+
+/// `v` as a 16-bit coordinate, for grids without `FLAG_WIDE_GRID`. Larger values
+/// mean the caller has the layout wrong.
+fn narrow_coord(v: u32) -> u16 {
+    debug_assert!(v <= u16::MAX as u32, "coordinate {v} needs FLAG_WIDE_GRID");
+    v as u16
+}
+
+/// A rectangle of cells, in cell coordinates from the top left of the grid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CellRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CellRect {
+    /// The part of the rectangle inside a `cols x rows` grid; empty if none is.
+    pub fn clamped(self, cols: u32, rows: u32) -> Self {
+        let x = self.x.min(cols);
+        let y = self.y.min(rows);
+        Self {
+            x,
+            y,
+            width: self.width.min(cols - x),
+            height: self.height.min(rows - y),
+        }
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+}
+
+/// A changed rectangle in a delta frame (`FLAG_REGION_DELTAS`): its position,
+/// size and every cell inside it, row by row, changed or not.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub cells: Vec<Cell>,
+}
+
+impl Region {
+    /// Serialized size of the position and size before the cells.
+    pub const HEADER_SIZE: usize = 8;
+    /// Serialized size of the position and size in files with `FLAG_WIDE_GRID`
+    pub const HEADER_SIZE_WIDE: usize = 16;
+
+    /// Regions covering exactly `deltas`: horizontal runs of adjacent cells, with
+    /// runs over the same columns on consecutive rows stacked into one region,
+    /// which saves a region header per row. Deltas must be in row-major order, as
+    /// `compute_delta` produces them.
+    pub fn from_deltas(deltas: &[DeltaCell]) -> Vec<Region> {
+        let mut runs: Vec<Region> = Vec::new();
+        for d in deltas {
+            match runs.last_mut() {
+                Some(r) if r.y == d.y && r.x + r.width == d.x => {
+                    r.width += 1;
+                    r.cells.push(d.cell);
+                }
+                _ => runs.push(Region { x: d.x, y: d.y, width: 1, height: 1, cells: vec![d.cell] }),
+            }
+        }
+
+        let mut regions: Vec<Region> = Vec::new();
+        // Regions ending on the row before the run's and on its row, by (x, width)
+        let mut above: HashMap<(u32, u32), usize> = HashMap::new();
+        let mut current: HashMap<(u32, u32), usize> = HashMap::new();
+        let mut row = None;
+        for run in runs {
+            if row != Some(run.y) {
+                above = std::mem::take(&mut current);
+                if row.is_none_or(|y| y + 1 != run.y) {
+                    above.clear();
+                }
+                row = Some(run.y);
+            }
+            let key = (run.x, run.width);
+            match above.remove(&key) {
+                Some(idx) => {
+                    regions[idx].height += 1;
+                    regions[idx].cells.extend_from_slice(&run.cells);
+                    current.insert(key, idx);
+                }
+                None => {
+                    current.insert(key, regions.len());
+                    regions.push(run);
+                }
+            }
+        }
+        regions
+    }
+
+    /// The region's cells as positioned delta cells, row by row.
+    pub fn to_deltas(&self, out: &mut Vec<DeltaCell>) {
+        for (i, cell) in self.cells.iter().enumerate() {
+            out.push(DeltaCell {
+                x: self.x + (i % self.width as usize) as u32,
+                y: self.y + (i / self.width as usize) as u32,
+                cell: *cell,
+            });
+        }
+    }
+}
+
+/// How cells are serialized in a file, following its `FLAG_ALPHA` and
+/// `FLAG_GLYPHS` header flags: the 6 color bytes, then 2 alpha bytes if present,
+/// then the glyph as a little-endian u32 codepoint (0 = none) if present.
+/// Delta and region coordinates are u16, or u32 with `FLAG_WIDE_GRID`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CellLayout {
+    pub alpha: bool,
+    pub glyph: bool,
+    pub wide: bool,
+}
+
+impl CellLayout {
+    pub fn from_flags(flags: u16) -> Self {
+        Self {
+            alpha: flags & FLAG_ALPHA != 0,
+            glyph: flags & FLAG_GLYPHS != 0,
+            wide: flags & FLAG_WIDE_GRID != 0,
+        }
+    }
+
+    /// Serialized size of one coordinate.
+    fn coord_size(self) -> usize {
+        if self.wide { 4 } else { 2 }
+    }
+
+    fn write_coord(self, v: u32, out: &mut Vec<u8>) {
+        if self.wide {
+            out.extend_from_slice(&v.to_le_bytes());
+        } else {
+            out.extend_from_slice(&narrow_coord(v).to_le_bytes());
+        }
+    }
+
+    /// Coordinate number `i` of the ones at the start of `b`.
+    fn read_coord(self, b: &[u8], i: usize) -> u32 {
+        if self.wide {
+            u32::from_le_bytes(b[i * 4..i * 4 + 4].try_into().unwrap())
+        } else {
+            u16::from_le_bytes([b[i * 2], b[i * 2 + 1]]) as u32
+        }
+    }
+
+    /// Serialized size of a keyframe cell.
+    pub fn cell_size(self) -> usize {
+        let mut size = Cell::SIZE;
+        if self.alpha {
+            size += Cell::SIZE_ALPHA - Cell::SIZE;
+        }
+        if self.glyph {
+            size += 4;
+        }
+        size
+    }
+
+    /// Serialized size of a delta cell (position + cell).
+    pub fn delta_size(self) -> usize {
+        2 * self.coord_size() + self.cell_size()
+    }
+
+    pub fn write_cell(self, cell: &Cell, out: &mut Vec<u8>) {
+        if self.alpha {
+            out.extend_from_slice(&cell.to_bytes_alpha());
+        } else {
+            out.extend_from_slice(&cell.to_bytes());
+        }
+        if self.glyph {
+            // Control characters are written as no glyph, as `read_cell` reads them
+            let glyph = cell.glyph.filter(|c| !c.is_control());
+            out.extend_from_slice(&glyph.map_or(0, u32::from).to_le_bytes());
+        }
+    }
+
+    /// Parse one cell from `b`, which must hold at least `cell_size()` bytes.
+    /// Invalid codepoints and control characters read as no glyph: players
+    /// write glyphs straight to the terminal, which would act on ESC and the like.
+    pub fn read_cell(self, b: &[u8]) -> Cell {
+        let mut cell = if self.alpha { Cell::from_bytes_alpha(b) } else { Cell::from_bytes(b) };
+        if self.glyph {
+            let at = self.cell_size() - 4;
+            let code = u32::from_le_bytes(b[at..at + 4].try_into().unwrap());
+            cell.glyph = char::from_u32(code).filter(|c| !c.is_control());
+        }
+        cell
+    }
+
+    pub fn write_delta(self, delta: &DeltaCell, out: &mut Vec<u8>) {
+        self.write_coord(delta.x, out);
+        self.write_coord(delta.y, out);
+        self.write_cell(&delta.cell, out);
+    }
+
+    pub fn read_delta(self, b: &[u8]) -> DeltaCell {
+        DeltaCell {
+            x: self.read_coord(b, 0),
+            y: self.read_coord(b, 1),
+            cell: self.read_cell(&b[2 * self.coord_size()..]),
+        }
+    }
+
+    /// Serialized size of a region's position and size.
+    pub fn region_header_size(self) -> usize {
+        if self.wide { Region::HEADER_SIZE_WIDE } else { Region::HEADER_SIZE }
+    }
+
+    /// Serialized size of a region: position and size, then its cells.
+    pub fn region_size(self, region: &Region) -> usize {
+        self.region_header_size() + region.cells.len() * self.cell_size()
+    }
+
+    pub fn write_region(self, region: &Region, out: &mut Vec<u8>) {
+        for v in [region.x, region.y, region.width, region.height] {
+            self.write_coord(v, out);
+        }
+        region.cells.iter().for_each(|c| self.write_cell(c, out));
+    }
+
+    /// Parse the regions of a delta frame. A region cut off by the end of the
+    /// data ends the list.
+    pub fn read_regions(self, mut b: &[u8]) -> Vec<Region> {
+        let header_size = self.region_header_size();
+        let mut regions = Vec::new();
+        while b.len() >= header_size {
+            let field = |i: usize| self.read_coord(b, i);
+            let (x, y, width, height) = (field(0), field(1), field(2), field(3));
+            let Some(len) = (width as usize).checked_mul(height as usize).and_then(|n| n.checked_mul(self.cell_size()))
+            else {
+                break;
+            };
+            let Some(data) = b[header_size..].get(..len) else { break };
+            let cells = data.chunks_exact(self.cell_size()).map(|c| self.read_cell(c)).collect();
+            regions.push(Region { x, y, width, height, cells });
+            b = &b[header_size + len..];
+        }
+        regions
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -69,19 +450,114 @@ impl FrameType {
             _ => FrameType::Delta,
         }
     }
+
+    /// Strict variant of `from_u8` for inline record tags.
+    pub fn try_from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(FrameType::Keyframe),
+            1 => Some(FrameType::Delta),
+            _ => None,
+        }
+    }
 }
 
 pub const MAGIC: &[u8; 4] = b"RSFX";
-pub const VERSION: u16 = 1;
+/// Format version written to the header. Version 2 added the header flags (bytes
+/// 50..52, reserved in version 1), which change how frames are laid out, so
+/// version 1 readers must not read these files. Readers still take version 1
+/// files, which have no flags.
+pub const VERSION: u16 = 2;
 pub const HEADER_SIZE: usize = 64;
+/// Largest `cols` or `rows` the header can hold (24 bits, with `FLAG_WIDE_GRID`);
+/// writers reject larger grids.
+pub const MAX_DIMENSION: u32 = 0xFF_FFFF;
+
+/// Header flag: frame count, index offset and audio info live in a footer at EOF
+/// (written by streaming writers that can't seek back to patch the header).
+pub const FLAG_FOOTER: u16 = 1 << 0;
+
+/// Header flag: frames and audio are wrapped in inline records (tag + size prefix),
+/// so the file can be decoded front to back without the index. Set by all writers;
+/// files without it predate inline records.
+pub const FLAG_INLINE_FRAMES: u16 = 1 << 1;
+
+/// Header flag: cells carry per-pixel alpha (`Cell::SIZE_ALPHA` bytes each) for
+/// compositing over a background.
+pub const FLAG_ALPHA: u16 = 1 << 2;
+
+/// Header flag: cells carry an optional glyph (4 more bytes each) that is drawn
+/// instead of the half-block, for text mixed with video. See `CellLayout`.
+pub const FLAG_GLYPHS: u16 = 1 << 3;
+
+/// Header flag: the content is meant to repeat, so players loop it by default.
+pub const FLAG_LOOP: u16 = 1 << 4;
+
+/// Header flag: frames may be stored uncompressed (`FRAME_STORED_RAW`). Set by all
+/// writers; readers without raw-frame support must not read these files.
+pub const FLAG_RAW_FRAMES: u16 = 1 << 5;
+
+/// Header flag: each delta lists the changes from the last keyframe rather than
+/// from the previous frame, so any frame decodes from its keyframe plus itself.
+/// Readers that ignore it would let changes pile up over a keyframe interval.
+pub const FLAG_KEYFRAME_DELTAS: u16 = 1 << 6;
+
+/// Header flag: delta frames hold rectangles of cells (`Region`) instead of a
+/// list of single cells, which suits changes that cluster, like a moving subject.
+pub const FLAG_REGION_DELTAS: u16 = 1 << 7;
+
+/// Header flag: the grid may be wider or taller than 65535 cells. The header
+/// holds the high byte of `cols` and `rows` in bytes 54 and 55, and delta and
+/// region coordinates are u32 instead of u16. Set by writers when it's needed.
+pub const FLAG_WIDE_GRID: u16 = 1 << 8;
+
+/// Header flag: compressed keyframes are stored as independent LZ4 blocks of
+/// whole rows (`compress::compress_framed`) instead of one block, so part of a
+/// keyframe can be decoded on its own (`RsfxReader::read_keyframe_region`).
+pub const FLAG_FRAMED_KEYFRAMES: u16 = 1 << 9;
+
+/// All header flags this version understands.
+pub const KNOWN_FLAGS: u16 = FLAG_FOOTER
+    | FLAG_INLINE_FRAMES
+    | FLAG_ALPHA
+    | FLAG_GLYPHS
+    | FLAG_LOOP
+    | FLAG_RAW_FRAMES
+    | FLAG_KEYFRAME_DELTAS
+    | FLAG_REGION_DELTAS
+    | FLAG_WIDE_GRID
+    | FLAG_FRAMED_KEYFRAMES;
+
+/// Set on a frame's type byte (index entry and inline tag) when its data is the
+/// raw frame instead of LZ4, because compressing wouldn't have paid off.
+pub const FRAME_STORED_RAW: u8 = 0x80;
+
+/// Inline record tags (`FLAG_INLINE_FRAMES`). Frame records are tagged with their `FrameType`.
+pub const RECORD_AUDIO: u8 = 0x10;
+pub const RECORD_END: u8 = 0xFF;
+/// Extra data section (subtitles, metadata): tag (1) + kind (1) + length (4), then the
+/// payload. Sections follow the last frame and `RsfxHeader::sections_offset`
+/// points at the first one.
+pub const RECORD_SECTION: u8 = 0x20;
+
+/// Section kinds.
+pub const SECTION_SUBTITLES: u8 = 1;
+pub const SECTION_METADATA: u8 = 2;
+
+/// Prefix of an inline frame record: tag (1) + compressed size (4).
+pub const FRAME_PREFIX_SIZE: usize = 5;
+/// Prefix of an inline audio record: tag (1) + sample rate (4) + channels (2) + length (8).
+pub const AUDIO_PREFIX_SIZE: usize = 15;
+/// Prefix of a section record: tag (1) + kind (1) + length (4).
+pub const SECTION_PREFIX_SIZE: usize = 6;
 
 /// File header — fixed 64 bytes at the start of a .rsfx file.
 #[derive(Clone, Debug)]
 pub struct RsfxHeader {
     // magic: [u8; 4] = "RSFX"
     // version: u16
-    pub cols: u16,
-    pub rows: u16,
+    /// Grid size in cells; above 65535 needs `FLAG_WIDE_GRID`
+    pub cols: u32,
+    pub rows: u32,
     pub fps_num: u16,
     pub fps_den: u16,
     pub frame_count: u32,
@@ -91,15 +567,94 @@ pub struct RsfxHeader {
     pub audio_offset: u64,
     pub audio_length: u64,
     pub index_offset: u64,
+    pub flags: u16,
+    /// Offset of the first section record, 0 if the file has none
+    pub sections_offset: u64,
+    /// Height-to-width ratio of the terminal cells the file was made for, in
+    /// hundredths (200: twice as tall as wide); 0 if not recorded
+    pub cell_aspect: u16,
 }
 
 impl RsfxHeader {
+    /// A header with no frames or audio yet. Sets `FLAG_WIDE_GRID` if the grid needs
+    /// it; grids over `MAX_DIMENSION` cells a side are an error.
+    pub fn new(cols: u32, rows: u32, fps_num: u16, fps_den: u16, keyframe_interval: u16) -> anyhow::Result<Self> {
+        if fps_num == 0 || fps_den == 0 {
+            anyhow::bail!("invalid frame rate: {fps_num}/{fps_den}");
+        }
+        let mut header = Self {
+            cols,
+            rows,
+            fps_num,
+            fps_den,
+            frame_count: 0,
+            keyframe_interval,
+            audio_sample_rate: 0,
+            audio_channels: 0,
+            audio_offset: 0,
+            audio_length: 0,
+            index_offset: 0,
+            flags: 0,
+            sections_offset: 0,
+            cell_aspect: 0,
+        };
+        header.check_dimensions()?;
+        Ok(header)
+    }
+
+    /// Fail if the grid is too large for the header, and set `FLAG_WIDE_GRID`
+    /// if it's too large for 16 bits.
+    pub fn check_dimensions(&mut self) -> anyhow::Result<()> {
+        if self.cols > MAX_DIMENSION || self.rows > MAX_DIMENSION {
+            anyhow::bail!(
+                "{}x{} grid is too large: .rsfx holds at most {MAX_DIMENSION} cells a side (24 bits)",
+                self.cols,
+                self.rows
+            );
+        }
+        if self.cols > u16::MAX as u32 || self.rows > u16::MAX as u32 {
+            self.flags |= FLAG_WIDE_GRID;
+        }
+        Ok(())
+    }
+
+    /// Whether the file asks to be played in a loop (`FLAG_LOOP`).
+    pub fn loop_hint(&self) -> bool {
+        self.flags & FLAG_LOOP != 0
+    }
+
+    /// Height-to-width ratio of one half-block pixel on the cells the file was
+    /// made for: 1 for square pixels, which is also assumed when `cell_aspect`
+    /// isn't recorded.
+    pub fn pixel_aspect(&self) -> f64 {
+        match self.cell_aspect {
+            0 => 1.0,
+            hundredths => hundredths as f64 / 200.0,
+        }
+    }
+
+    /// Raw bytes per block of a framed keyframe (`FLAG_FRAMED_KEYFRAMES`): as many
+    /// whole rows as fit in `compress::DEFAULT_BLOCK_SIZE`, and at least one.
+    /// `None` when keyframes aren't framed.
+    pub fn keyframe_block_size(&self) -> Option<usize> {
+        if self.flags & FLAG_FRAMED_KEYFRAMES == 0 {
+            return None;
+        }
+        let row = (self.cols as usize * CellLayout::from_flags(self.flags).cell_size()).max(1);
+        Some(row * (crate::compress::DEFAULT_BLOCK_SIZE / row).max(1))
+    }
+
+    /// Whether deltas are relative to the last keyframe (`FLAG_KEYFRAME_DELTAS`).
+    pub fn keyframe_deltas(&self) -> bool {
+        self.flags & FLAG_KEYFRAME_DELTAS != 0
+    }
+
     pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
         let mut buf = [0u8; HEADER_SIZE];
         buf[0..4].copy_from_slice(MAGIC);
         buf[4..6].copy_from_slice(&VERSION.to_le_bytes());
-        buf[6..8].copy_from_slice(&self.cols.to_le_bytes());
-        buf[8..10].copy_from_slice(&self.rows.to_le_bytes());
+        buf[6..8].copy_from_slice(&(self.cols as u16).to_le_bytes());
+        buf[8..10].copy_from_slice(&(self.rows as u16).to_le_bytes());
         buf[10..12].copy_from_slice(&self.fps_num.to_le_bytes());
         buf[12..14].copy_from_slice(&self.fps_den.to_le_bytes());
         buf[14..18].copy_from_slice(&self.frame_count.to_le_bytes());
@@ -109,7 +664,13 @@ impl RsfxHeader {
         buf[26..34].copy_from_slice(&self.audio_offset.to_le_bytes());
         buf[34..42].copy_from_slice(&self.audio_length.to_le_bytes());
         buf[42..50].copy_from_slice(&self.index_offset.to_le_bytes());
-        // bytes 50..64 reserved
+        buf[50..52].copy_from_slice(&self.flags.to_le_bytes());
+        buf[52..54].copy_from_slice(&self.cell_aspect.to_le_bytes());
+        if self.flags & FLAG_WIDE_GRID != 0 {
+            buf[54] = (self.cols >> 16) as u8;
+            buf[55] = (self.rows >> 16) as u8;
+        }
+        buf[56..64].copy_from_slice(&self.sections_offset.to_le_bytes());
         buf
     }
 
@@ -118,12 +679,20 @@ impl RsfxHeader {
             anyhow::bail!("invalid magic: expected RSFX");
         }
         let version = u16::from_le_bytes([buf[4], buf[5]]);
-        if version != VERSION {
+        if version == 0 || version > VERSION {
             anyhow::bail!("unsupported version: {version}");
         }
+        let flags = u16::from_le_bytes([buf[50], buf[51]]);
+        if flags & !KNOWN_FLAGS != 0 {
+            anyhow::bail!("unsupported header flags: {flags:#06x}");
+        }
+        if version == 1 && flags != 0 {
+            anyhow::bail!("version 1 header with flags set ({flags:#06x}); the file is damaged");
+        }
+        let (cols_high, rows_high) = if flags & FLAG_WIDE_GRID != 0 { (buf[54], buf[55]) } else { (0, 0) };
         Ok(Self {
-            cols: u16::from_le_bytes([buf[6], buf[7]]),
-            rows: u16::from_le_bytes([buf[8], buf[9]]),
+            cols: u16::from_le_bytes([buf[6], buf[7]]) as u32 | (cols_high as u32) << 16,
+            rows: u16::from_le_bytes([buf[8], buf[9]]) as u32 | (rows_high as u32) << 16,
             fps_num: u16::from_le_bytes([buf[10], buf[11]]),
             fps_den: u16::from_le_bytes([buf[12], buf[13]]),
             frame_count: u32::from_le_bytes([buf[14], buf[15], buf[16], buf[17]]),
@@ -133,6 +702,79 @@ impl RsfxHeader {
             audio_offset: u64::from_le_bytes(buf[26..34].try_into().unwrap()),
             audio_length: u64::from_le_bytes(buf[34..42].try_into().unwrap()),
             index_offset: u64::from_le_bytes(buf[42..50].try_into().unwrap()),
+            flags,
+            sections_offset: u64::from_le_bytes(buf[56..64].try_into().unwrap()),
+            cell_aspect: u16::from_le_bytes([buf[52], buf[53]]),
+        })
+    }
+}
+
+pub const FOOTER_MAGIC: &[u8; 4] = b"RSFE";
+pub const FOOTER_SIZE: usize = 48;
+
+/// Footer of a streamed file (`FLAG_FOOTER`) — the header fields that are only
+/// known once writing is done, fixed 48 bytes at the very end of the file.
+#[derive(Clone, Debug)]
+pub struct RsfxFooter {
+    pub frame_count: u32,
+    pub index_offset: u64,
+    pub audio_sample_rate: u32,
+    pub audio_channels: u16,
+    pub audio_offset: u64,
+    pub audio_length: u64,
+    pub sections_offset: u64,
+}
+
+impl RsfxFooter {
+    pub fn from_header(header: &RsfxHeader) -> Self {
+        Self {
+            frame_count: header.frame_count,
+            index_offset: header.index_offset,
+            audio_sample_rate: header.audio_sample_rate,
+            audio_channels: header.audio_channels,
+            audio_offset: header.audio_offset,
+            audio_length: header.audio_length,
+            sections_offset: header.sections_offset,
+        }
+    }
+
+    /// Copy the footer's fields into `header`.
+    pub fn apply(&self, header: &mut RsfxHeader) {
+        header.frame_count = self.frame_count;
+        header.index_offset = self.index_offset;
+        header.audio_sample_rate = self.audio_sample_rate;
+        header.audio_channels = self.audio_channels;
+        header.audio_offset = self.audio_offset;
+        header.audio_length = self.audio_length;
+        header.sections_offset = self.sections_offset;
+    }
+
+    pub fn to_bytes(&self) -> [u8; FOOTER_SIZE] {
+        let mut buf = [0u8; FOOTER_SIZE];
+        buf[0..4].copy_from_slice(&self.frame_count.to_le_bytes());
+        buf[4..12].copy_from_slice(&self.index_offset.to_le_bytes());
+        buf[12..16].copy_from_slice(&self.audio_sample_rate.to_le_bytes());
+        buf[16..18].copy_from_slice(&self.audio_channels.to_le_bytes());
+        buf[18..26].copy_from_slice(&self.audio_offset.to_le_bytes());
+        buf[26..34].copy_from_slice(&self.audio_length.to_le_bytes());
+        buf[34..42].copy_from_slice(&self.sections_offset.to_le_bytes());
+        // bytes 42..44 reserved
+        buf[44..48].copy_from_slice(FOOTER_MAGIC);
+        buf
+    }
+
+    pub fn from_bytes(buf: &[u8; FOOTER_SIZE]) -> anyhow::Result<Self> {
+        if &buf[44..48] != FOOTER_MAGIC {
+            anyhow::bail!("invalid footer magic: expected RSFE");
+        }
+        Ok(Self {
+            frame_count: u32::from_le_bytes(buf[0..4].try_into().unwrap()),
+            index_offset: u64::from_le_bytes(buf[4..12].try_into().unwrap()),
+            audio_sample_rate: u32::from_le_bytes(buf[12..16].try_into().unwrap()),
+            audio_channels: u16::from_le_bytes([buf[16], buf[17]]),
+            audio_offset: u64::from_le_bytes(buf[18..26].try_into().unwrap()),
+            audio_length: u64::from_le_bytes(buf[26..34].try_into().unwrap()),
+            sections_offset: u64::from_le_bytes(buf[34..42].try_into().unwrap()),
         })
     }
 }
@@ -143,17 +785,25 @@ pub struct FrameIndexEntry {
     pub offset: u64,
     pub compressed_size: u32,
     pub frame_type: FrameType,
+    /// Decompressed size, so readers can allocate exactly. Stored as 24 bits;
+    /// 0 means unknown (older files, or frames over `MAX_RAW_SIZE`).
+    pub raw_size: u32,
+    /// The data is stored uncompressed (`FRAME_STORED_RAW`)
+    pub stored_raw: bool,
 }
 
 impl FrameIndexEntry {
     pub const SIZE: usize = 16;
+    /// Largest `raw_size` the index can record
+    pub const MAX_RAW_SIZE: u32 = 0xFF_FFFF;
 
     pub fn to_bytes(&self) -> [u8; 16] {
         let mut buf = [0u8; 16];
         buf[0..8].copy_from_slice(&self.offset.to_le_bytes());
         buf[8..12].copy_from_slice(&self.compressed_size.to_le_bytes());
-        buf[12] = self.frame_type as u8;
-        // bytes 13..16 reserved
+        buf[12] = self.frame_type as u8 | if self.stored_raw { FRAME_STORED_RAW } else { 0 };
+        let raw_size = if self.raw_size > Self::MAX_RAW_SIZE { 0 } else { self.raw_size };
+        buf[13..16].copy_from_slice(&raw_size.to_le_bytes()[0..3]);
         buf
     }
 
@@ -161,7 +811,9 @@ impl FrameIndexEntry {
         Self {
             offset: u64::from_le_bytes(buf[0..8].try_into().unwrap()),
             compressed_size: u32::from_le_bytes(buf[8..12].try_into().unwrap()),
-            frame_type: FrameType::from_u8(buf[12]),
+            frame_type: FrameType::from_u8(buf[12] & !FRAME_STORED_RAW),
+            raw_size: u32::from_le_bytes([buf[13], buf[14], buf[15], 0]),
+            stored_raw: buf[12] & FRAME_STORED_RAW != 0,
         }
     }
 }
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use anyhow::{bail, Context};
+
+/// Bytes fetched per request, at least: reads that run into several chunks not
+/// yet fetched get them all in one request. The first one holds the header.
+const CHUNK_SIZE: u64 = 64 * 1024;
+/// Most fetched bytes kept at once; the chunks read least recently go first.
+const CACHE_LIMIT: u64 = 64 * 1024 * 1024;
+
+/// `Read + Seek` over a file on an HTTP server that answers Range requests, so
+/// `RsfxReader::new` can open a URL without downloading the whole file: it
+/// fetches the header, then the index at the end, and frames only as they're
+/// read. Fetched chunks are cached, so going back to a keyframe after a seek
+/// doesn't fetch it again.
+pub struct HttpReader {
+    agent: ureq::Agent,
+    url: String,
+    len: u64,
+    pos: u64,
+    /// Fetched chunks by index, with the `reads` count when each was last read
+    chunks: HashMap<u64, (Vec<u8>, u64)>,
+    cached: u64,
+    reads: u64,
+}
+
+impl HttpReader {
+    /// Open the file at `url`, fetching its first chunk (and from the response,
+    /// its length).
+    pub fn open(url: &str) -> anyhow::Result<Self> {
+        let mut reader = Self {
+            agent: ureq::Agent::new(),
+            url: url.to_string(),
+            len: 0,
+            pos: 0,
+            chunks: HashMap::new(),
+            cached: 0,
+            reads: 0,
+        };
+        let (first, len) = reader.fetch(0, CHUNK_SIZE)?;
+        reader.len = len;
+        reader.insert(0, first);
+        Ok(reader)
+    }
+
+    /// GET bytes `start..end` (cut short at the end of the file), returning them
+    /// and the file's total length.
+    fn fetch(&self, start: u64, end: u64) -> anyhow::Result<(Vec<u8>, u64)> {
+        let response = self
+            .agent
+            .get(&self.url)
+            .set("Range", &format!("bytes={start}-{}", end - 1))
+            .call()
+            .with_context(|| format!("failed to fetch {}", self.url))?;
+        if response.status() != 206 {
+            bail!("{} doesn't support range requests (status {})", self.url, response.status());
+        }
+        // `Content-Range: bytes 0-65535/1234567`
+        let len = response
+            .header("Content-Range")
+            .and_then(|range| range.rsplit_once('/'))
+            .and_then(|(_, len)| len.parse::<u64>().ok())
+            .with_context(|| format!("{} sent no file length with its range", self.url))?;
+        let mut data = Vec::new();
+        response
+            .into_reader()
+            .take(end.min(len).saturating_sub(start))
+            .read_to_end(&mut data)
+            .with_context(|| format!("failed to read from {}", self.url))?;
+        Ok((data, len))
+    }
+
+    /// Make sure the chunks covering bytes `start..end` are cached, fetching the
+    /// missing ones (and any between them) in one request.
+    fn load(&mut self, start: u64, end: u64) -> anyhow::Result<()> {
+        let missing: Vec<u64> = (start / CHUNK_SIZE..end.div_ceil(CHUNK_SIZE))
+            .filter(|idx| !self.chunks.contains_key(idx))
+            .collect();
+        let (Some(&first), Some(&last)) = (missing.first(), missing.last()) else {
+            return Ok(());
+        };
+        let (from, to) = (first * CHUNK_SIZE, ((last + 1) * CHUNK_SIZE).min(self.len));
+        let (data, _) = self.fetch(from, to)?;
+        if data.len() as u64 != to - from {
+            bail!("{} sent {} bytes for a range of {}", self.url, data.len(), to - from);
+        }
+        for (i, chunk) in data.chunks(CHUNK_SIZE as usize).enumerate() {
+            self.insert(first + i as u64, chunk.to_vec());
+        }
+        Ok(())
+    }
+
+    /// Cache chunk `idx`, dropping the least recently read ones over the limit.
+    fn insert(&mut self, idx: u64, data: Vec<u8>) {
+        self.cached += data.len() as u64;
+        if let Some((old, _)) = self.chunks.insert(idx, (data, self.reads)) {
+            self.cached -= old.len() as u64;
+        }
+        while self.cached > CACHE_LIMIT {
+            let Some(oldest) = self.chunks.iter().min_by_key(|(_, (_, read))| *read).map(|(&idx, _)| idx) else {
+                break;
+            };
+            let (old, _) = self.chunks.remove(&oldest).unwrap();
+            self.cached -= old.len() as u64;
+        }
+    }
+}
+
+impl Read for HttpReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Reads are capped well under the cache limit, so none of their own
+        // chunks are dropped before they're copied out
+        let want = (buf.len() as u64).min(self.len.saturating_sub(self.pos)).min(CACHE_LIMIT / 2);
+        if want == 0 {
+            return Ok(0);
+        }
+        self.load(self.pos, self.pos + want).map_err(io::Error::other)?;
+        self.reads += 1;
+        let mut done = 0;
+        while done < want {
+            let at = self.pos + done;
+            let (chunk, read) = self.chunks.get_mut(&(at / CHUNK_SIZE)).expect("chunk was just loaded");
+            *read = self.reads;
+            let offset = (at % CHUNK_SIZE) as usize;
+            let n = (chunk.len() - offset).min((want - done) as usize);
+            buf[done as usize..][..n].copy_from_slice(&chunk[offset..][..n]);
+            done += n as u64;
+        }
+        self.pos += want;
+        Ok(want as usize)
+    }
+}
+
+impl Seek for HttpReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start of the file"))?;
+        Ok(self.pos)
+    }
+}
@@ -1,35 +1,53 @@
 pub mod format;
 pub mod compress;
+pub mod delta;
 pub mod encode;
 pub mod decode;
+pub mod subtitle;
+pub mod protocol;
+pub mod audio;
+#[cfg(feature = "http")]
+pub mod http;
+mod metadata;
 
 #[cfg(test)]
 mod tests {
     use std::io::Cursor;
+    use crate::compress;
     use crate::format::*;
     use crate::encode::RsfxWriter;
-    use crate::decode::RsfxReader;
+    use crate::decode::{DecodeStats, Frame, RsfxReader, SequentialReader};
+    use crate::subtitle::{self, SubtitleEntry};
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    /// An opaque cell with no glyph: `top` as the bg color over `bottom` as the fg.
+    fn cell(top: [u8; 3], bottom: [u8; 3]) -> Cell {
+        Cell::from_pixels(top, bottom)
+    }
+
+    /// `len` copies of the cell most tests fill their frames with.
+    fn solid_frame(len: usize) -> Vec<Cell> {
+        vec![cell([1, 2, 3], [4, 5, 6]); len]
+    }
 
     #[test]
     fn roundtrip_keyframe_and_delta() {
-        let cols = 4u16;
-        let rows = 2u16;
+        let cols = 4u32;
+        let rows = 2u32;
         let total_cells = (cols as usize) * (rows as usize);
 
         // Build a keyframe grid
         let mut cells: Vec<Cell> = Vec::new();
         for i in 0..total_cells {
             let v = i as u8;
-            cells.push(Cell {
-                bg_r: v, bg_g: v + 10, bg_b: v + 20,
-                fg_r: v + 30, fg_g: v + 40, fg_b: v + 50,
-            });
+            cells.push(cell([v, v + 10, v + 20], [v + 30, v + 40, v + 50]));
         }
 
         // Build some deltas
         let deltas = vec![
-            DeltaCell { x: 1, y: 0, cell: Cell { bg_r: 255, bg_g: 0, bg_b: 0, fg_r: 0, fg_g: 255, fg_b: 0 } },
-            DeltaCell { x: 3, y: 1, cell: Cell { bg_r: 0, bg_g: 0, bg_b: 255, fg_r: 128, fg_g: 128, fg_b: 128 } },
+            DeltaCell { x: 1, y: 0, cell: cell([255, 0, 0], [0, 255, 0]) },
+            DeltaCell { x: 3, y: 1, cell: cell([0, 0, 255], [128, 128, 128]) },
         ];
 
         // Audio data
@@ -53,12 +71,12 @@ mod tests {
         assert_eq!(reader.header.audio_channels, 2);
 
         // Verify keyframe
-        assert!(matches!(reader.frame_type(0), FrameType::Keyframe));
+        assert!(matches!(reader.frame_type(0).unwrap(), FrameType::Keyframe));
         let read_cells = reader.read_keyframe(0).unwrap();
         assert_eq!(read_cells, cells);
 
         // Verify delta
-        assert!(matches!(reader.frame_type(1), FrameType::Delta));
+        assert!(matches!(reader.frame_type(1).unwrap(), FrameType::Delta));
         let read_deltas = reader.read_delta(1).unwrap();
         assert_eq!(read_deltas, deltas);
 
@@ -66,4 +84,1455 @@ mod tests {
         let read_audio = reader.read_audio().unwrap();
         assert_eq!(read_audio, audio_pcm);
     }
+
+    #[test]
+    fn fractional_fps_roundtrip() {
+        let buf = Cursor::new(Vec::new());
+        let writer = RsfxWriter::new_with_fps(buf, 2, 1, 24000, 1001, 30).unwrap();
+        let buf = writer.finish().unwrap();
+
+        let reader = RsfxReader::new(Cursor::new(buf.into_inner())).unwrap();
+        assert_eq!(reader.header.fps_num, 24000);
+        assert_eq!(reader.header.fps_den, 1001);
+        assert!((reader.fps() - 23.976).abs() < 0.001);
+    }
+
+    #[test]
+    fn streaming_writer_roundtrip() {
+        let cells = solid_frame(4);
+        let deltas = vec![DeltaCell { x: 1, y: 1, cell: cell([9, 9, 9], [0, 0, 0]) }];
+        let audio_pcm = vec![7u8; 64];
+
+        // A plain Vec is Write but not Seek, like a pipe
+        let mut writer = RsfxWriter::new_streaming(Vec::new(), 2, 2, 25, 1, 30).unwrap();
+        writer.write_keyframe(&cells).unwrap();
+        writer.write_delta(&deltas).unwrap();
+        writer.write_audio(&audio_pcm, 44100, 2).unwrap();
+        let buf = writer.finish_streaming().unwrap();
+
+        let mut reader = RsfxReader::new(Cursor::new(buf)).unwrap();
+        assert_ne!(reader.header.flags & FLAG_FOOTER, 0);
+        assert_eq!(reader.header.frame_count, 2);
+        assert_eq!(reader.header.audio_channels, 2);
+        assert_eq!(reader.read_keyframe(0).unwrap(), cells);
+        assert_eq!(reader.read_delta(1).unwrap(), deltas);
+        assert_eq!(reader.read_audio().unwrap(), audio_pcm);
+    }
+
+    #[test]
+    fn sequential_reader_reads_streamed_file() {
+        let cells = vec![cell([10, 20, 30], [40, 50, 60]); 6];
+        let deltas = vec![DeltaCell { x: 2, y: 0, cell: cell([1, 1, 1], [2, 2, 2]) }];
+        let audio_pcm = vec![3u8; 128];
+
+        // Audio first, the way the converter streams it
+        let mut writer = RsfxWriter::new_streaming(Vec::new(), 3, 2, 30, 1, 30).unwrap();
+        writer.write_audio(&audio_pcm, 22050, 1).unwrap();
+        writer.write_keyframe(&cells).unwrap();
+        writer.write_delta(&deltas).unwrap();
+        writer.write_delta(&[]).unwrap();
+        let buf = writer.finish_streaming().unwrap();
+
+        // Indexed reads still work with the record prefixes
+        let mut reader = RsfxReader::new(Cursor::new(buf.clone())).unwrap();
+        assert_eq!(reader.read_keyframe(0).unwrap(), cells);
+        assert_eq!(reader.read_delta(1).unwrap(), deltas);
+        assert_eq!(reader.read_audio().unwrap(), audio_pcm);
+
+        // Sequential reads from a Read-only source
+        let mut seq = SequentialReader::new(buf.as_slice()).unwrap();
+        assert_eq!(seq.header.audio_sample_rate, 22050);
+        assert_eq!(seq.header.audio_channels, 1);
+        assert_eq!(seq.take_audio().unwrap(), audio_pcm);
+        assert_eq!(seq.next_frame().unwrap(), Some(Frame::Keyframe(cells)));
+        assert_eq!(seq.next_frame().unwrap(), Some(Frame::Delta(deltas)));
+        assert_eq!(seq.next_frame().unwrap(), Some(Frame::Delta(Vec::new())));
+        assert_eq!(seq.next_frame().unwrap(), None);
+    }
+
+    #[test]
+    fn sequential_reader_reads_seekable_file() {
+        let cells = vec![cell([5, 5, 5], [6, 6, 6]); 2];
+
+        let mut writer = RsfxWriter::new(Cursor::new(Vec::new()), 2, 1, 30, 30).unwrap();
+        writer.write_keyframe(&cells).unwrap();
+        writer.write_audio(&[1, 2, 3, 4], 44100, 2).unwrap();
+        let buf = writer.finish().unwrap().into_inner();
+
+        let mut seq = SequentialReader::new(buf.as_slice()).unwrap();
+        assert_eq!(seq.header.frame_count, 1);
+        assert_eq!(seq.next_frame().unwrap(), Some(Frame::Keyframe(cells)));
+        // Audio written after the frames shows up once the reader gets past them
+        assert_eq!(seq.next_frame().unwrap(), None);
+        assert_eq!(seq.take_audio(), Some(vec![1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn sequential_reader_rejects_oversized_records() {
+        let mut header = RsfxHeader::new(2, 1, 30, 1, 30).unwrap();
+        header.flags |= FLAG_INLINE_FRAMES | FLAG_RAW_FRAMES | FLAG_FOOTER;
+        let mut buf = header.to_bytes().to_vec();
+
+        // An audio record claiming far more than the stream holds
+        let mut audio = buf.clone();
+        audio.push(RECORD_AUDIO);
+        audio.extend_from_slice(&44100u32.to_le_bytes());
+        audio.extend_from_slice(&2u16.to_le_bytes());
+        audio.extend_from_slice(&u64::MAX.to_le_bytes());
+        audio.extend_from_slice(&[1, 2, 3, 4]);
+        assert!(SequentialReader::new(audio.as_slice()).is_err());
+
+        // Likewise a frame record
+        buf.push(FrameType::Keyframe as u8 | FRAME_STORED_RAW);
+        buf.extend_from_slice(&u32::MAX.to_le_bytes());
+        buf.extend_from_slice(&[0; 12]);
+        let mut seq = SequentialReader::new(buf.as_slice()).unwrap();
+        assert!(seq.next_frame().is_err());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn with_inline_frames_is_a_no_op() {
+        let cells = solid_frame(2);
+        let write = |opt_in: bool| {
+            let mut writer = RsfxWriter::new(Cursor::new(Vec::new()), 2, 1, 30, 30).unwrap();
+            if opt_in {
+                writer = writer.with_inline_frames().unwrap();
+            }
+            writer.write_keyframe(&cells).unwrap();
+            // No longer an error once frames are written
+            if opt_in {
+                writer = writer.with_inline_frames().unwrap();
+            }
+            writer.finish().unwrap().into_inner()
+        };
+
+        let buf = write(true);
+        assert_eq!(buf, write(false));
+        let mut seq = SequentialReader::new(buf.as_slice()).unwrap();
+        assert_ne!(seq.header.flags & FLAG_INLINE_FRAMES, 0);
+        assert_eq!(seq.next_frame().unwrap(), Some(Frame::Keyframe(cells)));
+    }
+
+    #[test]
+    fn damaged_index_is_rebuilt_from_inline_records() {
+        let cells = solid_frame(4);
+        let deltas = vec![DeltaCell { x: 0, y: 1, cell: cell([7, 7, 7], [8, 8, 8]) }];
+
+        let mut writer = RsfxWriter::new(Cursor::new(Vec::new()), 2, 2, 30, 30).unwrap();
+        writer.write_audio(&[9; 32], 44100, 2).unwrap();
+        writer.write_keyframe(&cells).unwrap();
+        writer.write_delta(&deltas).unwrap();
+        let mut buf = writer.finish().unwrap().into_inner();
+
+        // Cut off the index entirely
+        let index_offset = u64::from_le_bytes(buf[42..50].try_into().unwrap()) as usize;
+        buf.truncate(index_offset + 4);
+
+        let mut reader = RsfxReader::new(Cursor::new(buf)).unwrap();
+        assert_eq!(reader.header.frame_count, 2);
+        assert!(matches!(reader.frame_type(0).unwrap(), FrameType::Keyframe));
+        assert!(matches!(reader.frame_type(1).unwrap(), FrameType::Delta));
+        assert_eq!(reader.read_keyframe(0).unwrap(), cells);
+        assert_eq!(reader.read_delta(1).unwrap(), deltas);
+        assert_eq!(reader.read_audio().unwrap(), vec![9; 32]);
+    }
+
+    #[test]
+    fn checkpointed_file_opens_when_cut_off() {
+        use std::cell::RefCell;
+        use std::io::{Seek, SeekFrom, Write};
+        use std::rc::Rc;
+        use crate::encode::RsfxBuilder;
+
+        /// Output the test can look at while the writer still holds it
+        #[derive(Clone, Default)]
+        struct Shared(Rc<RefCell<Cursor<Vec<u8>>>>);
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.borrow_mut().write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        impl Seek for Shared {
+            fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+                self.0.borrow_mut().seek(pos)
+            }
+        }
+
+        let frames: Vec<Vec<Cell>> = (0..10u8)
+            .map(|i| vec![cell([i, 2, 3], [4, 5, i]); 6])
+            .collect();
+        let out = Shared::default();
+        let mut writer = RsfxBuilder::new(3, 2).checkpoint_interval(4).build(out.clone()).unwrap();
+        writer.write_audio(&[7; 16], 44100, 2).unwrap();
+        let mut snapshots = Vec::new();
+        for frame in &frames {
+            writer.write_frame(frame).unwrap();
+            snapshots.push(out.0.borrow().get_ref().clone());
+        }
+        let finished = writer.finish().unwrap().0.borrow().get_ref().clone();
+
+        // Before the first checkpoint the header still says there's nothing
+        assert_eq!(RsfxReader::new(Cursor::new(snapshots[2].clone())).unwrap().len(), 0);
+
+        // Past one, every frame written in full comes back, a partial one doesn't
+        let mut killed = snapshots[8].clone();
+        assert_eq!(RsfxHeader::from_bytes(killed[..HEADER_SIZE].try_into().unwrap()).unwrap().frame_count, 8);
+        let mut reader = RsfxReader::new(Cursor::new(killed.clone())).unwrap();
+        assert_eq!(reader.len(), 9);
+        assert_eq!(reader.reconstruct_frame(8).unwrap(), frames[8]);
+        assert_eq!(reader.read_audio().unwrap(), vec![7; 16]);
+        killed.truncate(killed.len() - 2);
+        assert_eq!(RsfxReader::new(Cursor::new(killed)).unwrap().len(), 8);
+
+        // Checkpoints leave no trace in a finished file
+        let mut plain = RsfxBuilder::new(3, 2).build(Cursor::new(Vec::new())).unwrap();
+        plain.write_audio(&[7; 16], 44100, 2).unwrap();
+        for frame in &frames {
+            plain.write_frame(frame).unwrap();
+        }
+        assert_eq!(plain.finish().unwrap().into_inner(), finished);
+    }
+
+    #[test]
+    fn threaded_writer_matches_single_threaded_output() {
+        let write = |threads: usize| {
+            let mut writer = RsfxWriter::new(Cursor::new(Vec::new()), 8, 4, 30, 10)
+                .unwrap()
+                .with_threads(threads)
+                .unwrap();
+            for i in 0..40u8 {
+                let cells: Vec<Cell> = (0..32u8)
+                    .map(|j| cell([i, j, i ^ j], [j, i, 0]))
+                    .collect();
+                if i % 10 == 0 {
+                    writer.write_keyframe(&cells).unwrap();
+                } else {
+                    let deltas = vec![DeltaCell { x: i as u32 % 8, y: 1, cell: cells[0] }];
+                    writer.write_delta(&deltas).unwrap();
+                }
+                if i == 20 {
+                    writer.write_audio(&[1, 2, 3, 4], 44100, 2).unwrap();
+                }
+            }
+            writer.finish().unwrap().into_inner()
+        };
+
+        let single = write(1);
+        assert_eq!(write(4), single);
+        let reader = RsfxReader::new(Cursor::new(single)).unwrap();
+        assert_eq!(reader.header.frame_count, 40);
+    }
+
+    #[test]
+    fn compressed_frames_copy_verbatim() {
+        // Big enough that the keyframe compresses; the delta is kept raw
+        let cells = vec![cell([10, 20, 30], [40, 50, 60]); 64];
+        let deltas = vec![DeltaCell { x: 2, y: 0, cell: cell([1, 1, 1], [2, 2, 2]) }];
+
+        let mut writer = RsfxWriter::new(Cursor::new(Vec::new()), 16, 4, 30, 30).unwrap();
+        writer.write_keyframe(&cells).unwrap();
+        writer.write_delta(&deltas).unwrap();
+        let src = writer.finish().unwrap().into_inner();
+
+        let mut reader = RsfxReader::new(Cursor::new(src.clone())).unwrap();
+        let stored_raw: Vec<bool> = reader.index.iter().map(|e| e.stored_raw).collect();
+        assert_eq!(stored_raw, [false, true]);
+        let mut copy = RsfxWriter::new(Cursor::new(Vec::new()), 16, 4, 30, 30).unwrap();
+        for i in 0..reader.index.len() {
+            let blob = reader.read_frame_compressed(i).unwrap();
+            copy.write_frame_compressed(&blob, reader.frame_type(i).unwrap()).unwrap();
+        }
+        let dst = copy.finish().unwrap().into_inner();
+
+        // Same records, same index, same bytes
+        assert_eq!(dst, src);
+        let mut copied = RsfxReader::new(Cursor::new(dst)).unwrap();
+        assert_eq!(copied.read_frame(0).unwrap(), Frame::Keyframe(cells));
+        assert_eq!(copied.read_frame(1).unwrap(), Frame::Delta(deltas));
+    }
+
+    #[test]
+    fn alpha_cells_roundtrip_and_composite() {
+        let mut header = RsfxHeader::new(2, 1, 30, 1, 30).unwrap();
+        header.flags |= FLAG_ALPHA;
+        let cells = vec![
+            Cell { fg_a: 0, ..cell([200, 100, 0], [0, 0, 0]) },
+            Cell { bg_a: 128, ..cell([255, 255, 255], [10, 20, 30]) },
+        ];
+        let deltas = vec![DeltaCell { x: 1, y: 0, cell: Cell { bg_a: 0, ..cells[0] } }];
+
+        let mut writer = RsfxWriter::with_header(Cursor::new(Vec::new()), header).unwrap();
+        writer.write_keyframe(&cells).unwrap();
+        writer.write_delta(&deltas).unwrap();
+        let buf = writer.finish().unwrap().into_inner();
+
+        let mut reader = RsfxReader::new(Cursor::new(buf)).unwrap();
+        assert_eq!(reader.read_keyframe(0).unwrap(), cells);
+        assert_eq!(reader.read_delta(1).unwrap(), deltas);
+
+        // Transparent halves take the background, half-transparent ones mix
+        let over = cells[1].composite((0, 0, 0));
+        assert_eq!((over.bg_r, over.fg_r, over.bg_a), (128, 10, 255));
+        let over = cells[0].composite((1, 2, 3));
+        assert_eq!((over.bg_r, over.fg_r, over.fg_g, over.fg_b), (200, 1, 2, 3));
+    }
+
+    #[test]
+    fn glyph_cells_roundtrip_with_and_without_alpha() {
+        let base = cell([0, 0, 0], [255, 255, 255]);
+        let cells = vec![Cell { glyph: Some('H'), ..base }, Cell { glyph: Some('\u{2588}'), fg_a: 40, ..base }, base];
+        let deltas = vec![DeltaCell { x: 2, y: 0, cell: Cell { glyph: Some('!'), ..base } }];
+
+        for flags in [FLAG_GLYPHS, FLAG_GLYPHS | FLAG_ALPHA] {
+            let mut header = RsfxHeader::new(3, 1, 30, 1, 30).unwrap();
+            header.flags |= flags;
+            let layout = CellLayout::from_flags(header.flags);
+            assert_eq!(layout.cell_size(), if flags & FLAG_ALPHA != 0 { 12 } else { 10 });
+
+            let mut writer = RsfxWriter::with_header(Cursor::new(Vec::new()), header).unwrap();
+            writer.write_keyframe(&cells).unwrap();
+            writer.write_delta(&deltas).unwrap();
+            let buf = writer.finish().unwrap().into_inner();
+
+            let mut reader = RsfxReader::new(Cursor::new(buf)).unwrap();
+            let mut expected = cells.clone();
+            if flags & FLAG_ALPHA == 0 {
+                expected[1].fg_a = Cell::OPAQUE;
+            }
+            assert_eq!(reader.read_keyframe(0).unwrap(), expected);
+            assert_eq!(reader.read_delta(1).unwrap(), deltas);
+        }
+
+        // Control characters (ESC, BEL, CSI) would reach the terminal as is, so
+        // they aren't glyphs, whether read from a file or written to one
+        let layout = CellLayout::from_flags(FLAG_GLYPHS);
+        for c in ['\x1b', '\x07', '\u{9b}'] {
+            let mut raw = Vec::new();
+            layout.write_cell(&base, &mut raw);
+            raw[Cell::SIZE..].copy_from_slice(&(c as u32).to_le_bytes());
+            assert_eq!(layout.read_cell(&raw), base);
+            let mut written = Vec::new();
+            layout.write_cell(&Cell { glyph: Some(c), ..base }, &mut written);
+            assert_eq!(layout.read_cell(&written), base);
+        }
+    }
+
+    #[test]
+    fn index_records_raw_frame_size() {
+        let cells = vec![cell([3, 3, 3], [4, 4, 4]); 12];
+        let deltas = vec![DeltaCell { x: 1, y: 1, cell: cells[0] }; 2];
+
+        let mut writer = RsfxWriter::new(Cursor::new(Vec::new()), 4, 3, 30, 30).unwrap();
+        writer.write_keyframe(&cells).unwrap();
+        writer.write_delta(&deltas).unwrap();
+        let buf = writer.finish().unwrap().into_inner();
+
+        let mut reader = RsfxReader::new(Cursor::new(buf)).unwrap();
+        assert_eq!(reader.index[0].raw_size as usize, 12 * Cell::SIZE);
+        assert_eq!(reader.index[1].raw_size as usize, 2 * DeltaCell::SIZE);
+        assert_eq!(reader.read_keyframe(0).unwrap(), cells);
+        assert_eq!(reader.read_delta(1).unwrap(), deltas);
+
+        // Sizes that don't fit in 24 bits are stored as unknown
+        let entry = FrameIndexEntry { offset: 64, compressed_size: 10, frame_type: FrameType::Keyframe, raw_size: 1 << 24, stored_raw: false };
+        assert_eq!(FrameIndexEntry::from_bytes(&entry.to_bytes()).raw_size, 0);
+    }
+
+    #[test]
+    fn decode_stats_count_frames_and_bytes() {
+        let cells = solid_frame(40);
+        let deltas = vec![DeltaCell { x: 2, y: 0, cell: cells[0] }; 3];
+
+        let mut writer = RsfxWriter::new(Cursor::new(Vec::new()), 8, 5, 30, 30).unwrap();
+        writer.write_keyframe(&cells).unwrap();
+        writer.write_delta(&deltas).unwrap();
+        writer.write_delta(&[]).unwrap();
+        let buf = writer.finish().unwrap().into_inner();
+
+        let mut reader = RsfxReader::new(Cursor::new(buf)).unwrap();
+        assert_eq!(reader.stats(), DecodeStats::default());
+        for idx in 0..3 {
+            reader.read_frame(idx).unwrap();
+        }
+        let stats = reader.stats();
+        assert_eq!(stats.frames_decoded, 3);
+        let stored: u64 = reader.index.iter().map(|entry| entry.compressed_size as u64).sum();
+        assert_eq!(stats.bytes_read, stored);
+        assert_eq!(stats.bytes_decompressed as usize, 40 * Cell::SIZE + 3 * DeltaCell::SIZE);
+
+        // Copying compressed frames reads without decoding
+        reader.read_frame_compressed(0).unwrap();
+        assert_eq!(reader.stats().frames_decoded, 3);
+        assert_eq!(reader.stats().bytes_read, stored + reader.index[0].compressed_size as u64);
+    }
+
+    #[test]
+    fn header_only_skips_the_index() {
+        let cells = vec![cell([5, 6, 7], [8, 9, 10]); 6];
+
+        let mut writer = RsfxWriter::new_streaming(Vec::new(), 3, 2, 24, 1, 30).unwrap();
+        writer.write_keyframe(&cells).unwrap();
+        writer.write_keyframe(&cells).unwrap();
+        let buf = writer.finish_streaming().unwrap();
+
+        // Counts come from the footer, but no index is read
+        let mut reader = RsfxReader::header_only(Cursor::new(buf)).unwrap();
+        assert_eq!((reader.header.cols, reader.header.rows, reader.header.frame_count), (3, 2, 2));
+        assert!(reader.index.is_empty());
+
+        reader.load_index().unwrap();
+        assert_eq!(reader.index.len(), 2);
+        assert_eq!(reader.read_keyframe(1).unwrap(), cells);
+    }
+
+    #[test]
+    fn header_versions() {
+        let mut header = RsfxHeader::new(3, 2, 24, 1, 30).unwrap();
+        let with_version = |header: &RsfxHeader, version: u16| {
+            let mut buf = header.to_bytes();
+            buf[4..6].copy_from_slice(&version.to_le_bytes());
+            RsfxHeader::from_bytes(&buf)
+        };
+        assert_eq!(u16::from_le_bytes(header.to_bytes()[4..6].try_into().unwrap()), VERSION);
+
+        // Version 1 files had no flags
+        assert_eq!(with_version(&header, 1).unwrap().cols, 3);
+        header.flags = FLAG_INLINE_FRAMES | FLAG_RAW_FRAMES;
+        assert!(with_version(&header, 1).is_err());
+        assert!(with_version(&header, 2).is_ok());
+        assert!(with_version(&header, 0).is_err());
+        assert!(with_version(&header, VERSION + 1).is_err());
+    }
+
+    #[test]
+    fn sections_and_loop_hint_roundtrip_in_both_layouts() {
+        let srt = "1\r\n00:00:01,000 --> 00:00:02,500\r\n<i>Hello</i>\r\nthere\r\n\r\n2\r\n00:01:00,000 --> 00:01:01,000 X1:0\r\nBye\r\n";
+        let entries = subtitle::parse_srt(srt).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                SubtitleEntry { start_ms: 1000, end_ms: 2500, text: "Hello\nthere".into() },
+                SubtitleEntry { start_ms: 60_000, end_ms: 61_000, text: "Bye".into() },
+            ]
+        );
+        assert_eq!(subtitle::active_at(&entries, 2499).map(|e| e.start_ms), Some(1000));
+        assert!(subtitle::active_at(&entries, 2500).is_none());
+
+        let cells = solid_frame(4);
+
+        let metadata = vec![("source".to_string(), "clip.mp4".to_string()), ("cols".to_string(), "2".to_string())];
+
+        let mut writer = RsfxWriter::new(Cursor::new(Vec::new()), 2, 2, 30, 30).unwrap();
+        writer.set_loop(true).unwrap();
+        writer.write_keyframe(&cells).unwrap();
+        writer.write_subtitles(&entries).unwrap();
+        writer.write_metadata(&metadata).unwrap();
+        let buf = writer.finish().unwrap().into_inner();
+        let mut reader = RsfxReader::new(Cursor::new(buf)).unwrap();
+        assert_eq!(reader.read_subtitles().unwrap(), entries);
+        assert_eq!(reader.read_metadata().unwrap(), metadata);
+        assert!(reader.loop_hint());
+        assert_eq!(reader.read_keyframe(0).unwrap(), cells);
+
+        let mut writer = RsfxWriter::new_streaming(Vec::new(), 2, 2, 30, 1, 30).unwrap();
+        assert!(writer.set_loop(true).is_err());
+        writer.write_keyframe(&cells).unwrap();
+        writer.write_subtitles(&entries).unwrap();
+        let buf = writer.finish_streaming().unwrap();
+        let mut reader = RsfxReader::new(Cursor::new(buf.clone())).unwrap();
+        assert_eq!(reader.read_subtitles().unwrap(), entries);
+        assert!(!reader.loop_hint());
+
+        // Sequential readers step over the section
+        let mut seq = SequentialReader::new(Cursor::new(buf)).unwrap();
+        assert_eq!(seq.next_frame().unwrap(), Some(Frame::Keyframe(cells)));
+        assert_eq!(seq.next_frame().unwrap(), None);
+    }
+
+    #[test]
+    fn subtitles_drop_control_characters() {
+        // An OSC 52 clipboard write and a bell, in a subtitle file and in a section
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\nHi\x1b]52;c;aGk=\x07 there\nBye\x1b[2J\n";
+        let entries = subtitle::parse_srt(srt).unwrap();
+        assert_eq!(entries[0].text, "Hi]52;c;aGk= there\nBye[2J");
+
+        let raw = vec![SubtitleEntry { start_ms: 0, end_ms: 1000, text: "a\x1b[31m\tb\r\nc\u{9b}0m".into() }];
+        let mut writer = RsfxWriter::new(Cursor::new(Vec::new()), 1, 1, 30, 30).unwrap();
+        writer.write_keyframe(&solid_frame(1)).unwrap();
+        writer.write_subtitles(&raw).unwrap();
+        let buf = writer.finish().unwrap().into_inner();
+        let mut reader = RsfxReader::new(Cursor::new(buf)).unwrap();
+        assert_eq!(reader.read_subtitles().unwrap()[0].text, "a[31mb\nc0m");
+    }
+
+    #[test]
+    fn oversized_or_truncated_sections_are_errors() {
+        let mut writer = RsfxWriter::new(Cursor::new(Vec::new()), 1, 1, 30, 30).unwrap();
+        writer.write_keyframe(&solid_frame(1)).unwrap();
+        writer.write_metadata(&[("source".to_string(), "clip.mp4".to_string())]).unwrap();
+        writer.write_subtitles(&[SubtitleEntry { start_ms: 0, end_ms: 1000, text: "Hi".into() }]).unwrap();
+        let buf = writer.finish().unwrap().into_inner();
+        let sections_offset = RsfxReader::new(Cursor::new(buf.clone())).unwrap().header.sections_offset as usize;
+        let len_at = sections_offset + 2;
+
+        // A length far past the end of the file, for the section looked up and one skipped over
+        let mut oversized = buf.clone();
+        oversized[len_at..len_at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut reader = RsfxReader::new(Cursor::new(oversized)).unwrap();
+        let err = reader.read_metadata().unwrap_err();
+        assert!(err.to_string().contains("runs past the end of the file"), "{err}");
+        assert!(reader.read_subtitles().is_err());
+
+        // A file cut off inside the first section
+        let mut reader = RsfxReader::header_only(Cursor::new(buf[..len_at + 6].to_vec())).unwrap();
+        assert!(reader.read_metadata().is_err());
+
+        let mut reader = RsfxReader::new(Cursor::new(buf)).unwrap();
+        assert_eq!(reader.read_metadata().unwrap().len(), 1);
+        assert_eq!(reader.read_subtitles().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn incompressible_and_tiny_frames_are_stored_raw() {
+        // Noise: every cell a pseudo-random color
+        let mut seed = 0x2545_f491u32;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as u8
+        };
+        let noise: Vec<Cell> = (0..64 * 32)
+            .map(|_| cell([next(), next(), next()], [next(), next(), next()]))
+            .collect();
+        let flat = vec![noise[0]; 64 * 32];
+        let tiny = vec![DeltaCell { x: 3, y: 4, cell: noise[1] }];
+
+        let mut writer = RsfxWriter::new(Cursor::new(Vec::new()), 64, 32, 30, 30).unwrap();
+        writer.write_keyframe(&noise).unwrap();
+        writer.write_keyframe(&flat).unwrap();
+        writer.write_delta(&tiny).unwrap();
+        let buf = writer.finish().unwrap().into_inner();
+
+        let mut reader = RsfxReader::new(Cursor::new(buf.clone())).unwrap();
+        let stored_raw: Vec<bool> = reader.index.iter().map(|e| e.stored_raw).collect();
+        assert_eq!(stored_raw, [true, false, true]);
+        assert_eq!(reader.read_keyframe(0).unwrap(), noise);
+        assert_eq!(reader.read_keyframe(1).unwrap(), flat);
+        assert_eq!(reader.read_delta(2).unwrap(), tiny);
+
+        // Copies get the frames as stored
+        let blob = reader.read_frame_compressed(0).unwrap();
+        assert!(blob.stored_raw);
+        assert_eq!(blob.data.len(), noise.len() * Cell::SIZE);
+        assert!(!reader.read_frame_compressed(1).unwrap().stored_raw);
+
+        let mut seq = SequentialReader::new(Cursor::new(buf)).unwrap();
+        assert_eq!(seq.next_frame().unwrap(), Some(Frame::Keyframe(noise)));
+        assert_eq!(seq.next_frame().unwrap(), Some(Frame::Keyframe(flat)));
+        assert_eq!(seq.next_frame().unwrap(), Some(Frame::Delta(tiny)));
+    }
+
+    #[test]
+    fn static_runs_store_empty_deltas() {
+        let cells = vec![cell([9, 8, 7], [6, 5, 4]); 16];
+        let mut writer = RsfxWriter::new(Cursor::new(Vec::new()), 4, 4, 30, 30).unwrap();
+        writer.write_keyframe(&cells).unwrap();
+        for _ in 0..20 {
+            writer.write_delta(&[]).unwrap();
+        }
+        let buf = writer.finish().unwrap().into_inner();
+
+        let mut reader = RsfxReader::new(Cursor::new(buf.clone())).unwrap();
+        for idx in 1..=20 {
+            let entry = reader.index[idx];
+            assert!(entry.stored_raw && entry.compressed_size == 0, "frame {idx}: {entry:?}");
+            assert_eq!(reader.read_delta(idx).unwrap(), []);
+        }
+        assert_eq!(reader.read_keyframe(0).unwrap(), cells);
+
+        let mut seq = SequentialReader::new(Cursor::new(buf)).unwrap();
+        assert_eq!(seq.next_frame().unwrap(), Some(Frame::Keyframe(cells)));
+        for _ in 0..20 {
+            assert_eq!(seq.next_frame().unwrap(), Some(Frame::Delta(Vec::new())));
+        }
+        assert_eq!(seq.next_frame().unwrap(), None);
+    }
+
+    /// Cells that survive a round trip through a file with `layout`:
+    /// opaque without alpha, no glyph without glyphs.
+    fn any_cell(layout: CellLayout) -> impl Strategy<Value = Cell> + Clone {
+        let printable = proptest::char::range('!', '\u{2FFF}').prop_filter("a control character", |c| !c.is_control());
+        let glyph = proptest::option::weighted(0.3, printable);
+        (any::<[u8; 8]>(), glyph).prop_map(move |(b, glyph)| {
+            let (bg_a, fg_a) = if layout.alpha { (b[6], b[7]) } else { (255, 255) };
+            let glyph = glyph.filter(|_| layout.glyph);
+            Cell { bg_r: b[0], bg_g: b[1], bg_b: b[2], fg_r: b[3], fg_g: b[4], fg_b: b[5], bg_a, fg_a, glyph }
+        })
+    }
+
+    /// Up to `max` changed cells in a `cols x rows` grid, in row-major order
+    /// with no repeats (what `compute_delta` produces).
+    fn any_deltas(cols: u32, rows: u32, max: usize, layout: CellLayout) -> impl Strategy<Value = Vec<DeltaCell>> {
+        vec(((0..rows, 0..cols), any_cell(layout)), 0..=max).prop_map(|mut changes| {
+            changes.sort_by_key(|&(pos, _)| pos);
+            changes.dedup_by_key(|&mut (pos, _)| pos);
+            changes.into_iter().map(|((y, x), cell)| DeltaCell { x, y, cell }).collect()
+        })
+    }
+
+    /// Clips up to 40x20 with any mix of the layout flags: a keyframe first, then
+    /// keyframes, deltas and runs of empty deltas; no audio, an empty track, or
+    /// whole stereo samples.
+    fn any_clip() -> impl Strategy<Value = Clip> {
+        let flags = [FLAG_ALPHA, FLAG_GLYPHS, FLAG_REGION_DELTAS, FLAG_LOOP, FLAG_FRAMED_KEYFRAMES];
+        let flags = proptest::sample::subsequence(flags.to_vec(), 0..=flags.len())
+            .prop_map(|flags| flags.into_iter().fold(0, |all, flag| all | flag));
+        (1..=40u32, 1..=20u32, flags).prop_flat_map(|(cols, rows, flags)| {
+            let layout = CellLayout::from_flags(flags);
+            let cell_count = cols as usize * rows as usize;
+            let keyframe = vec(any_cell(layout), cell_count).prop_map(Frame::Keyframe);
+            let frame = prop_oneof![
+                1 => keyframe.clone(),
+                1 => Just(Frame::Delta(Vec::new())),
+                3 => any_deltas(cols, rows, cell_count, layout).prop_map(Frame::Delta),
+            ];
+            let audio = prop_oneof![
+                Just(None),
+                Just(Some(Vec::new())),
+                vec(any::<[u8; 4]>(), 1..2000).prop_map(|samples| Some(samples.concat())),
+            ];
+            (keyframe, vec(frame, 0..12), audio).prop_map(move |(first, rest, audio)| {
+                let mut header = RsfxHeader::new(cols, rows, 30, 1, 30).unwrap();
+                header.flags |= flags;
+                Clip { header, frames: std::iter::once(first).chain(rest).collect(), audio }
+            })
+        })
+    }
+
+    /// What the write → read round trips check: frames in order, plus audio.
+    #[derive(Debug)]
+    struct Clip {
+        header: RsfxHeader,
+        frames: Vec<Frame>,
+        audio: Option<Vec<u8>>,
+    }
+
+    impl Clip {
+        /// Write with `threads` compression threads, streamed (footer layout) or
+        /// seekable, then check both readers return exactly what went in.
+        fn check_round_trip(&self, threads: usize, streamed: bool) {
+            let mut header = self.header.clone();
+            if streamed {
+                header.flags |= FLAG_FOOTER;
+            }
+            let mut writer = RsfxWriter::with_header(Cursor::new(Vec::new()), header).unwrap().with_threads(threads).unwrap();
+            if let Some(pcm) = &self.audio {
+                writer.write_audio(pcm, 44100, 2).unwrap();
+            }
+            for frame in &self.frames {
+                match frame {
+                    Frame::Keyframe(cells) => writer.write_keyframe(cells).unwrap(),
+                    Frame::Delta(deltas) => writer.write_delta(deltas).unwrap(),
+                }
+            }
+            let buf = writer.finish().unwrap().into_inner();
+            let context = format!(
+                "{}x{} flags {:#x}, {} frames, threads {threads}",
+                self.header.cols,
+                self.header.rows,
+                self.header.flags,
+                self.frames.len()
+            );
+
+            let mut reader = RsfxReader::new(Cursor::new(buf.clone())).unwrap();
+            assert_eq!(reader.header.frame_count as usize, self.frames.len(), "{context}");
+            assert_eq!(reader.index.len(), self.frames.len(), "{context}");
+            for (idx, frame) in self.frames.iter().enumerate() {
+                assert_eq!(&reader.read_frame(idx).unwrap(), frame, "frame {idx}, {context}");
+            }
+            let audio = self.audio.clone().unwrap_or_default();
+            assert_eq!(reader.read_audio().unwrap(), audio, "{context}");
+
+            let mut seq = SequentialReader::new(Cursor::new(buf)).unwrap();
+            assert_eq!(seq.take_audio().unwrap_or_default(), audio, "{context}");
+            for (idx, frame) in self.frames.iter().enumerate() {
+                assert_eq!(seq.next_frame().unwrap().as_ref(), Some(frame), "frame {idx}, {context}");
+            }
+            assert_eq!(seq.next_frame().unwrap(), None, "{context}");
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn random_clips_round_trip(clip in any_clip(), threads in prop_oneof![Just(1), Just(4)], streamed: bool) {
+            clip.check_round_trip(threads, streamed);
+        }
+    }
+
+    #[test]
+    fn keyframe_regions_decode_in_part() {
+        // Wide enough that a keyframe spans many blocks, smooth enough to compress
+        let (cols, rows) = (300u32, 120u32);
+        let grid = |t: u32| -> Vec<Cell> {
+            (0..cols * rows)
+                .map(|i| {
+                    let (x, y) = ((i % cols) as u8, (i / cols) as u8);
+                    Cell::from_pixels([x / 4, y / 4, t as u8], [y / 4, x / 4, 0])
+                })
+                .collect()
+        };
+        let crop = |cells: &[Cell], r: CellRect| -> Vec<Cell> {
+            let mut out = Vec::new();
+            for y in r.y..r.y + r.height {
+                let start = (y * cols + r.x) as usize;
+                out.extend_from_slice(&cells[start..start + r.width as usize]);
+            }
+            out
+        };
+
+        for framed in [true, false] {
+            let mut writer = crate::encode::RsfxBuilder::new(cols, rows)
+                .framed_keyframes(framed)
+                .threads(if framed { 3 } else { 1 })
+                .build(Cursor::new(Vec::new()))
+                .unwrap();
+            writer.write_keyframe(&grid(0)).unwrap();
+            writer.write_keyframe(&grid(1)).unwrap();
+            writer.write_delta(&[]).unwrap();
+            let buf = writer.finish().unwrap().into_inner();
+
+            let mut reader = RsfxReader::new(Cursor::new(buf.clone())).unwrap();
+            assert_eq!(reader.header.keyframe_block_size().is_some(), framed);
+            assert!(!reader.index[1].stored_raw);
+            assert_eq!(reader.read_keyframe(1).unwrap(), grid(1));
+            for rect in [
+                CellRect { x: 0, y: 0, width: cols, height: rows },
+                CellRect { x: 10, y: 5, width: 20, height: 3 },
+                CellRect { x: 250, y: 100, width: 50, height: 20 },
+                CellRect { x: 0, y: 60, width: 1, height: 1 },
+            ] {
+                assert_eq!(reader.read_keyframe_region(1, rect).unwrap(), crop(&grid(1), rect), "{rect:?}");
+            }
+            // Clamped to the grid, or empty when outside it
+            let rect = CellRect { x: 290, y: 115, width: 40, height: 40 };
+            let clamped = CellRect { x: 290, y: 115, width: 10, height: 5 };
+            assert_eq!(rect.clamped(cols, rows), clamped);
+            assert_eq!(reader.read_keyframe_region(0, rect).unwrap(), crop(&grid(0), clamped));
+            let outside = CellRect { x: 400, y: 0, width: 10, height: 10 };
+            assert_eq!(reader.read_keyframe_region(0, outside).unwrap(), []);
+            assert!(reader.read_keyframe_region(2, rect).is_err());
+
+            let mut seq = SequentialReader::new(Cursor::new(buf)).unwrap();
+            assert_eq!(seq.next_frame().unwrap(), Some(Frame::Keyframe(grid(0))));
+
+            // Copying into a file of the same kind keeps the keyframe as stored
+            let compressed = reader.read_frame_compressed(1).unwrap();
+            assert_eq!(compressed.block_size.is_some(), framed);
+            let decoded = reader.stats().frames_decoded;
+            let mut copy = crate::encode::RsfxBuilder::new(cols, rows)
+                .framed_keyframes(framed)
+                .build(Cursor::new(Vec::new()))
+                .unwrap();
+            copy.write_frame_compressed(&compressed, FrameType::Keyframe).unwrap();
+            let buf = copy.finish().unwrap().into_inner();
+            let mut copied = RsfxReader::new(Cursor::new(buf)).unwrap();
+            assert_eq!(copied.read_frame_compressed(0).unwrap(), compressed);
+            assert_eq!(copied.read_keyframe(0).unwrap(), grid(1));
+            assert_eq!(reader.stats().frames_decoded, decoded);
+
+            // Into a file of the other kind it's re-encoded to suit it
+            let mut copy = crate::encode::RsfxBuilder::new(cols, rows)
+                .framed_keyframes(!framed)
+                .build(Cursor::new(Vec::new()))
+                .unwrap();
+            copy.write_frame_compressed(&compressed, FrameType::Keyframe).unwrap();
+            let buf = copy.finish().unwrap().into_inner();
+            assert_eq!(RsfxReader::new(Cursor::new(buf)).unwrap().read_keyframe(0).unwrap(), grid(1));
+        }
+    }
+
+    proptest! {
+        // Few cases: the largest grids below are 65535 cells each
+        #![proptest_config(ProptestConfig::with_cases(8))]
+        #[test]
+        fn edge_case_clips_round_trip(
+            cells in vec(any_cell(CellLayout::from_flags(0)), 64),
+            deltas in vec(any_deltas(8, 8, 10, CellLayout::from_flags(0)), 20),
+        ) {
+            let clip = |cols: u32, rows: u32, frames: Vec<Frame>, audio: Option<Vec<u8>>| Clip {
+                header: RsfxHeader::new(cols, rows, 30, 1, 30).unwrap(),
+                frames,
+                audio,
+            };
+
+            let mut clips = vec![
+                // Zero frames, with and without audio
+                clip(8, 4, Vec::new(), None),
+                clip(8, 4, Vec::new(), Some(vec![1, 2, 3, 4])),
+                // A single keyframe, and one followed only by empty deltas
+                clip(3, 3, vec![Frame::Keyframe(cells[..9].to_vec())], Some(Vec::new())),
+            ];
+            let mut static_run = vec![Frame::Keyframe(cells[..6].to_vec())];
+            static_run.extend((0..30).map(|_| Frame::Delta(Vec::new())));
+            clips.push(clip(3, 2, static_run, None));
+
+            // All deltas after the first frame, every one touching the far corner
+            let mut deltas_only = vec![Frame::Keyframe(cells.clone())];
+            for (i, mut deltas) in deltas.into_iter().enumerate() {
+                deltas.retain(|d| (d.x, d.y) != (7, 7));
+                deltas.push(DeltaCell { x: 7, y: 7, cell: cells[i] });
+                deltas_only.push(Frame::Delta(deltas));
+            }
+            clips.push(clip(8, 8, deltas_only, None));
+
+            // The largest grids one row or column across, with deltas at the last cell
+            for (cols, rows) in [(u16::MAX as u32, 1), (1, u16::MAX as u32)] {
+                let grid = vec![cells[0]; cols as usize * rows as usize];
+                let last = DeltaCell { x: cols - 1, y: rows - 1, cell: cells[1] };
+                clips.push(clip(cols, rows, vec![Frame::Keyframe(grid), Frame::Delta(vec![last])], None));
+            }
+
+            for clip in &clips {
+                for (threads, streamed) in [(1, false), (1, true), (3, false)] {
+                    clip.check_round_trip(threads, streamed);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn framed_compression_roundtrips_across_blocks() {
+        // Compressible, but different in every block
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i / 7 % 251) as u8).collect();
+        for (block_size, threads) in [(1024, 1), (1000, 4), (10_000, 2), (64 * 1024, 8)] {
+            let framed = compress::compress_framed(&data, block_size, threads);
+            assert!(framed.len() < data.len());
+            assert_eq!(compress::decompress_framed(&framed).unwrap(), data);
+            assert_eq!(framed, compress::compress_framed(&data, block_size, 1), "threads: {threads}");
+
+            // Ranges inside one block, across a boundary, spanning several, and empty
+            for range in [0..10, 990..1030, 1500..4200, 9990..10_000, 5000..5000, 0..10_000] {
+                let part = compress::decompress_framed_range(&framed, range.clone()).unwrap();
+                assert_eq!(part, data[range.clone()], "block size {block_size}, range {range:?}");
+            }
+            assert!(compress::decompress_framed_range(&framed, 9000..10_001).is_err());
+        }
+
+        let empty = compress::compress_framed(&[], 1024, 4);
+        assert_eq!(compress::decompress_framed(&empty).unwrap(), Vec::<u8>::new());
+        let framed = compress::compress_framed(&data, 1024, 1);
+        assert!(compress::decompress_framed(&framed[..framed.len() - 1]).is_err());
+        assert!(compress::decompress_framed(&framed[..6]).is_err());
+    }
+
+    #[test]
+    fn compress_into_reuses_buffers() {
+        let frames: Vec<Vec<u8>> = (0..3u8).map(|i| (0..4000).map(|j| (j / 50) as u8 ^ i).collect()).collect();
+        let mut compressed = vec![0xAA; 10];
+        let mut raw = vec![0x55; 100_000];
+        for frame in &frames {
+            compress::compress_into(frame, &mut compressed);
+            // Same format as before: LZ4 with the size prepended
+            assert_eq!(lz4_flex::decompress_size_prepended(&compressed).unwrap(), *frame);
+            compress::decompress_into(&compressed, None, &mut raw).unwrap();
+            assert_eq!(raw, *frame);
+        }
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn http_reader_fetches_frames_on_demand() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+        use std::sync::{Arc, Mutex};
+        use proptest::strategy::ValueTree;
+
+        // Six incompressible 100x50 keyframes, about 180 KB
+        let mut runner = proptest::test_runner::TestRunner::deterministic();
+        let frames = vec(vec(any_cell(CellLayout::from_flags(0)), 5000), 6).new_tree(&mut runner).unwrap().current();
+        let mut writer = RsfxWriter::new(Cursor::new(Vec::new()), 100, 50, 30, 30).unwrap();
+        for frame in &frames {
+            writer.write_keyframe(frame).unwrap();
+        }
+        let file = writer.finish().unwrap().into_inner();
+
+        // A server that answers each connection's one Range request, logging it
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/clip.rsfx", listener.local_addr().unwrap());
+        let ranges = Arc::new(Mutex::new(Vec::new()));
+        let (data, log) = (file.clone(), ranges.clone());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut range = None;
+                for line in BufReader::new(stream.try_clone().unwrap()).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((start, end)) = line.strip_prefix("Range: bytes=").and_then(|r| r.split_once('-')) {
+                        range = Some((start.parse::<usize>().unwrap(), end.parse::<usize>().unwrap()));
+                    }
+                }
+                let (start, end) = range.unwrap();
+                let end = end.min(data.len() - 1);
+                log.lock().unwrap().push(start);
+                write!(
+                    stream,
+                    "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {start}-{end}/{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    data.len(),
+                    end + 1 - start
+                )
+                .unwrap();
+                stream.write_all(&data[start..=end]).unwrap();
+            }
+        });
+
+        // The header, then the index at the end
+        let mut reader = RsfxReader::new(crate::http::HttpReader::open(&url).unwrap()).unwrap();
+        assert_eq!(ranges.lock().unwrap().len(), 2);
+        assert_eq!(reader.read_keyframe(5).unwrap(), frames[5]);
+        assert_eq!(reader.read_keyframe(0).unwrap(), frames[0]);
+        let fetched = ranges.lock().unwrap().len();
+
+        // Cached chunks aren't fetched again
+        assert_eq!(reader.read_keyframe(5).unwrap(), frames[5]);
+        assert_eq!(reader.read_keyframe(0).unwrap(), frames[0]);
+        assert_eq!(ranges.lock().unwrap().len(), fetched);
+        for (idx, frame) in frames.iter().enumerate() {
+            assert_eq!(&reader.read_keyframe(idx).unwrap(), frame);
+        }
+    }
+
+    #[test]
+    fn keyframe_lookup_around_a_frame() {
+        let cells = solid_frame(4);
+        let deltas = vec![DeltaCell { x: 1, y: 0, cell: cells[0] }];
+
+        // Keyframes at 0 and 3
+        let mut writer = RsfxWriter::new(Cursor::new(Vec::new()), 2, 2, 30, 3).unwrap();
+        for i in 0..5 {
+            if i % 3 == 0 {
+                writer.write_keyframe(&cells).unwrap();
+            } else {
+                writer.write_delta(&deltas).unwrap();
+            }
+        }
+        let reader = RsfxReader::new(Cursor::new(writer.finish().unwrap().into_inner())).unwrap();
+
+        let before: Vec<Option<usize>> = (0..6).map(|i| reader.keyframe_before(i)).collect();
+        assert_eq!(before, [Some(0), Some(0), Some(0), Some(3), Some(3), Some(3)]);
+        let after: Vec<Option<usize>> = (0..6).map(|i| reader.keyframe_after(i)).collect();
+        assert_eq!(after, [Some(0), Some(3), Some(3), Some(3), None, None]);
+        assert_eq!(reader.keyframe_before(usize::MAX), Some(3));
+    }
+
+    #[test]
+    fn frame_lookup_by_time() {
+        let cells = solid_frame(4);
+        let mut writer = RsfxWriter::new_with_fps(Cursor::new(Vec::new()), 2, 2, 30000, 1001, 30).unwrap();
+        for _ in 0..10 {
+            writer.write_keyframe(&cells).unwrap();
+        }
+        let reader = RsfxReader::new(Cursor::new(writer.finish().unwrap().into_inner())).unwrap();
+
+        // Exact hits, including times computed the same way
+        for i in 0..10 {
+            assert_eq!(reader.frame_at_time(reader.frame_time(i)), i);
+        }
+        assert_eq!(reader.frame_at_time(0.1001), 3);
+        // Between frames: the earlier one is still on screen
+        assert_eq!(reader.frame_at_time(0.05), 1);
+        assert_eq!(reader.frame_at_time(0.1), 2);
+        // Clamped to the file
+        assert_eq!(reader.frame_at_time(-1.0), 0);
+        assert_eq!(reader.frame_at_time(100.0), 9);
+    }
+
+    #[test]
+    fn leading_delta_is_rejected() {
+        let cells = solid_frame(4);
+        let deltas = vec![DeltaCell { x: 1, y: 0, cell: cells[0] }];
+
+        let mut writer = RsfxWriter::new(Cursor::new(Vec::new()), 2, 2, 30, 30).unwrap();
+        assert!(writer.write_delta(&deltas).is_err());
+        writer.write_keyframe(&cells).unwrap();
+        writer.write_delta(&deltas).unwrap();
+        let mut buf = writer.finish().unwrap().into_inner();
+
+        // Hand-edit frame 0 into a delta, both in its record tag and its index entry
+        let index_offset = u64::from_le_bytes(buf[42..50].try_into().unwrap()) as usize;
+        buf[HEADER_SIZE] |= FrameType::Delta as u8;
+        buf[index_offset + 12] = FrameType::Delta as u8;
+
+        let err = RsfxReader::new(Cursor::new(buf.clone())).err().unwrap();
+        assert!(err.to_string().contains("not a keyframe"));
+        let mut seq = SequentialReader::new(Cursor::new(buf)).unwrap();
+        assert!(seq.next_frame().is_err());
+    }
+
+    #[test]
+    fn compute_delta_promotes_large_changes_to_keyframe() {
+        use crate::delta::{compute_delta, compute_delta_into, DeltaOptions, FrameDiff};
+
+        let black = cell([0, 0, 0], [0, 0, 0]);
+        let white = cell([255, 255, 255], [255, 255, 255]);
+        let prev = vec![black; 10];
+
+        let mut small = prev.clone();
+        small[7] = white;
+        match compute_delta(&prev, &small, 5, false) {
+            FrameDiff::Delta(d) => assert_eq!(d, vec![DeltaCell { x: 2, y: 1, cell: white }]),
+            FrameDiff::Keyframe(_) => panic!("expected a delta"),
+        }
+
+        let large = vec![white; 10];
+        assert!(matches!(compute_delta(&prev, &large, 5, false), FrameDiff::Keyframe(_)));
+        assert!(matches!(compute_delta(&prev, &small, 5, true), FrameDiff::Keyframe(_)));
+        assert!(matches!(compute_delta(&[], &small, 5, false), FrameDiff::Keyframe(_)));
+
+        // The buffer version clears and reuses the caller's vector
+        let options = DeltaOptions::default();
+        let mut deltas = Vec::with_capacity(4);
+        let buffer = deltas.as_ptr();
+        assert!(!compute_delta_into(&prev, &small, 5, false, &options, &mut deltas));
+        assert!(!compute_delta_into(&prev, &small, 5, false, &options, &mut deltas));
+        assert_eq!(deltas, vec![DeltaCell { x: 2, y: 1, cell: white }]);
+        assert_eq!(deltas.as_ptr(), buffer);
+        assert!(compute_delta_into(&prev, &large, 5, false, &options, &mut deltas));
+    }
+
+    #[test]
+    fn delta_byte_budget_is_configurable() {
+        use crate::delta::{compute_delta, compute_delta_with, DeltaOptions, FrameDiff};
+
+        let black = cell([0, 0, 0], [0, 0, 0]);
+        let white = Cell { bg_r: 255, ..black };
+        let prev = vec![black; 10];
+        // 5 of 10 cells changed: 50 delta bytes vs 60 keyframe bytes
+        let mut half = prev.clone();
+        half[..5].fill(white);
+
+        assert!(matches!(compute_delta(&prev, &half, 10, false), FrameDiff::Delta(_)));
+        let strict = DeltaOptions { max_delta_ratio: 0.5, ..Default::default() };
+        assert!(matches!(compute_delta_with(&prev, &half, 10, false, &strict), FrameDiff::Keyframe(_)));
+    }
+
+    #[test]
+    fn keyframe_delta_flag_round_trips() {
+        let cells = vec![cell([1, 1, 1], [2, 2, 2]); 4];
+        let mut header = RsfxHeader::new(2, 2, 30, 1, 30).unwrap();
+        header.flags |= FLAG_KEYFRAME_DELTAS;
+        let mut writer = RsfxWriter::with_header(Cursor::new(Vec::new()), header).unwrap();
+        writer.write_keyframe(&cells).unwrap();
+        let reader = RsfxReader::new(Cursor::new(writer.finish().unwrap().into_inner())).unwrap();
+        assert!(reader.header.keyframe_deltas());
+        assert!(!RsfxHeader::new(2, 2, 30, 1, 30).unwrap().keyframe_deltas());
+    }
+
+    #[test]
+    fn region_deltas_round_trip() {
+        use crate::delta::{compute_delta_with, find_regions, DeltaOptions, FrameDiff};
+
+        let (cols, rows) = (12u32, 8u32);
+        let black = cell([0, 0, 0], [0, 0, 0]);
+        let prev = vec![black; cols as usize * rows as usize];
+        // A moving subject: a 4x3 block, one cell of it unchanged
+        let mut current = prev.clone();
+        for y in 2..5 {
+            for x in 3..7 {
+                current[y * cols as usize + x] = Cell { bg_r: (x * 20) as u8, fg_g: (y * 30) as u8, ..black };
+            }
+        }
+        current[3 * cols as usize + 4] = black;
+        // Plus two stray cells far apart on one row
+        current[7 * cols as usize] = Cell { fg_b: 9, ..black };
+        current[7 * cols as usize + 11] = Cell { fg_b: 9, ..black };
+
+        let options = DeltaOptions { regions: true, ..Default::default() };
+        let FrameDiff::Delta(deltas) = compute_delta_with(&prev, &current, cols, false, &options) else {
+            panic!("expected a delta");
+        };
+        let regions = find_regions(&deltas, &current, cols);
+        let spans: Vec<_> = regions.iter().map(|r| (r.x, r.y, r.width, r.height)).collect();
+        assert_eq!(spans, [(3, 2, 4, 3), (0, 7, 1, 1), (11, 7, 1, 1)]);
+
+        let mut header = RsfxHeader::new(cols, rows, 30, 1, 30).unwrap();
+        header.flags |= FLAG_REGION_DELTAS;
+        let mut writer = RsfxWriter::with_header(Cursor::new(Vec::new()), header).unwrap();
+        writer.write_keyframe(&prev).unwrap();
+        writer.write_regions(&regions).unwrap();
+        // Plain deltas are stored as regions too
+        let singles = [DeltaCell { x: 1, y: 1, cell: current[0] }, DeltaCell { x: 2, y: 1, cell: current[0] }];
+        writer.write_delta(&singles).unwrap();
+        let mut reader = RsfxReader::new(Cursor::new(writer.finish().unwrap().into_inner())).unwrap();
+
+        // Reads back as cells covering each region, applying to the new frame
+        let mut cells = reader.read_keyframe(0).unwrap();
+        for d in reader.read_delta(1).unwrap() {
+            cells[d.y as usize * cols as usize + d.x as usize] = d.cell;
+        }
+        assert_eq!(cells, current);
+        assert_eq!(reader.read_delta(2).unwrap(), singles);
+
+        // Runs over the same columns on consecutive rows share a region
+        let at = |x: u32, y: u32| DeltaCell { x, y, ..singles[0] };
+        let stacked = [at(0, 0), at(2, 1), at(3, 1), at(2, 2), at(3, 2), at(2, 3), at(3, 3), at(9, 3), at(9, 4), at(9, 6)];
+        let runs = Region::from_deltas(&stacked);
+        let spans: Vec<_> = runs.iter().map(|r| (r.x, r.y, r.width, r.height)).collect();
+        assert_eq!(spans, [(0, 0, 1, 1), (2, 1, 2, 3), (9, 3, 1, 2), (9, 6, 1, 1)]);
+        let mut header = RsfxHeader::new(cols, rows, 30, 1, 30).unwrap();
+        header.flags |= FLAG_REGION_DELTAS;
+        let mut writer = RsfxWriter::with_header(Cursor::new(Vec::new()), header).unwrap();
+        writer.write_keyframe(&prev).unwrap();
+        writer.write_delta(&stacked).unwrap();
+        let mut stacked_reader = RsfxReader::new(Cursor::new(writer.finish().unwrap().into_inner())).unwrap();
+        // Read back in row-major order all the same
+        assert_eq!(stacked_reader.read_delta(1).unwrap(), stacked);
+
+        // Scattered changes stay as small regions rather than one big box
+        let mut sparse = prev.clone();
+        sparse[0] = current[2 * cols as usize + 3];
+        sparse[rows as usize * cols as usize - 1] = current[2 * cols as usize + 3];
+        let deltas: Vec<DeltaCell> = [0, rows as usize * cols as usize - 1]
+            .iter()
+            .map(|&i| DeltaCell { x: (i % cols as usize) as u32, y: (i / cols as usize) as u32, cell: sparse[i] })
+            .collect();
+        assert_eq!(find_regions(&deltas, &sparse, cols).len(), 2);
+
+        // Truncated region data reads as the complete regions only
+        let layout = CellLayout::from_flags(FLAG_REGION_DELTAS);
+        let mut raw = Vec::new();
+        regions.iter().for_each(|r| layout.write_region(r, &mut raw));
+        raw.truncate(raw.len() - 3);
+        assert_eq!(layout.read_regions(&raw).len(), 2);
+    }
+
+    #[test]
+    fn keyframe_threshold_boundaries() {
+        use crate::delta::{compute_delta_with, DeltaOptions, FrameDiff};
+
+        let black = cell([0, 0, 0], [0, 0, 0]);
+        let white = Cell { bg_r: 255, ..black };
+        let prev = vec![black; 10];
+        let changed = |n: usize| {
+            let mut cells = prev.clone();
+            cells[..n].fill(white);
+            cells
+        };
+        let is_keyframe = |pct: f32, n: usize| {
+            let options = DeltaOptions { keyframe_threshold: Some(pct), ..Default::default() };
+            matches!(compute_delta_with(&prev, &changed(n), 10, false, &options), FrameDiff::Keyframe(_))
+        };
+
+        // Exactly at the threshold stays a delta; one cell over promotes
+        assert!(!is_keyframe(50.0, 5));
+        assert!(is_keyframe(50.0, 6));
+        // 0: any change promotes, but an unchanged frame stays an (empty) delta
+        assert!(!is_keyframe(0.0, 0));
+        assert!(is_keyframe(0.0, 1));
+        // 100: never promotes, even with every cell changed
+        assert!(!is_keyframe(100.0, 10));
+    }
+
+    #[test]
+    fn write_frame_picks_keyframes_and_deltas() {
+        let black = cell([0, 0, 0], [0, 0, 0]);
+        let base = vec![black; 8];
+        let touched = |idx: usize, v: u8| {
+            let mut cells = base.clone();
+            cells[idx] = Cell { fg_r: v, ..black };
+            cells
+        };
+        let frames = [
+            base.clone(),
+            touched(1, 10),
+            touched(1, 10),
+            vec![Cell { bg_g: 99, ..black }; 8],
+            touched(2, 20),
+            touched(3, 30),
+            touched(3, 40),
+        ];
+        use FrameType::{Delta, Keyframe};
+        // First frame, then promotion (3), the interval (4) and a request (5)
+        let expected = [Keyframe, Delta, Delta, Keyframe, Keyframe, Keyframe, Delta];
+
+        for flags in [0, FLAG_KEYFRAME_DELTAS, FLAG_REGION_DELTAS] {
+            let mut header = RsfxHeader::new(4, 2, 30, 1, 4).unwrap();
+            header.flags |= flags;
+            let mut writer = RsfxWriter::with_header(Cursor::new(Vec::new()), header).unwrap();
+            let mut types = Vec::new();
+            for (idx, cells) in frames.iter().enumerate() {
+                if idx == 5 {
+                    writer.request_keyframe();
+                }
+                types.push(writer.write_frame(cells).unwrap());
+            }
+            assert!(writer.write_frame(&base[..4]).is_err());
+            assert_eq!(types, expected, "flags {flags:#x}");
+
+            let mut reader = RsfxReader::new(Cursor::new(writer.finish().unwrap().into_inner())).unwrap();
+            let (mut grid, mut keyframe) = (Vec::new(), Vec::new());
+            for (idx, cells) in frames.iter().enumerate() {
+                match reader.read_frame(idx).unwrap() {
+                    Frame::Keyframe(k) => {
+                        keyframe = k.clone();
+                        grid = k;
+                    }
+                    Frame::Delta(deltas) => {
+                        if flags == FLAG_KEYFRAME_DELTAS {
+                            grid.clone_from(&keyframe);
+                        }
+                        for d in deltas {
+                            grid[d.y as usize * 4 + d.x as usize] = d.cell;
+                        }
+                    }
+                }
+                assert_eq!(&grid, cells, "flags {flags:#x}, frame {idx}");
+            }
+        }
+    }
+
+    #[test]
+    fn reconstruct_frame_applies_deltas() {
+        use crate::encode::RsfxBuilder;
+
+        let black = cell([0, 0, 0], [0, 0, 0]);
+        let white = Cell { bg_r: 255, ..black };
+        // One more cell lit per frame
+        let frames: Vec<Vec<Cell>> = (0..4)
+            .map(|n| (0..8).map(|i| if i < n { white } else { black }).collect())
+            .collect();
+        for keyframe_deltas in [false, true] {
+            let mut writer = RsfxBuilder::new(4, 2)
+                .keyframe_interval(10)
+                .keyframe_deltas(keyframe_deltas)
+                .build(Cursor::new(Vec::new()))
+                .unwrap();
+            for cells in &frames {
+                writer.write_frame(cells).unwrap();
+            }
+            let data = writer.finish().unwrap().into_inner();
+            let mut reader = RsfxReader::new(Cursor::new(data)).unwrap();
+            assert_eq!((reader.len(), reader.frame_count()), (4, 4));
+            assert_eq!(reader.get(1), Some(FrameType::Delta));
+            assert_eq!(reader.get(4), None);
+            for (idx, cells) in frames.iter().enumerate() {
+                assert_eq!(&reader.reconstruct_frame(idx).unwrap(), cells, "frame {idx}");
+            }
+            assert!(reader.reconstruct_frame(frames.len()).is_err());
+            let err = reader.read_frame(frames.len()).unwrap_err();
+            assert_eq!(err.to_string(), "frame index 4 out of range (count 4)");
+            assert!(reader.frame_type(usize::MAX).is_err() && reader.read_frame_raw(4).is_err());
+        }
+    }
+
+    #[test]
+    fn builder_configures_writer() {
+        use crate::encode::RsfxBuilder;
+
+        let cell = Cell { bg_a: 128, ..cell([5, 6, 7], [8, 9, 10]) };
+        let metadata = vec![("source".to_string(), "test".to_string())];
+        let builder = RsfxBuilder::new(3, 2)
+            .fps(24000, 1001)
+            .keyframe_interval(12)
+            .alpha(true)
+            .looping(true)
+            .cell_aspect(2.2)
+            .metadata(metadata.clone());
+        for streamed in [false, true] {
+            let mut writer = if streamed {
+                builder.clone().build_streaming(Cursor::new(Vec::new())).unwrap()
+            } else {
+                builder.clone().build(Cursor::new(Vec::new())).unwrap()
+            };
+            writer.write_frame(&[cell; 6]).unwrap();
+            let data = if streamed { writer.finish_streaming() } else { writer.finish() }.unwrap().into_inner();
+
+            let mut reader = RsfxReader::new(Cursor::new(data)).unwrap();
+            let header = &reader.header;
+            assert_eq!((header.cols, header.rows, header.fps_num, header.fps_den), (3, 2, 24000, 1001));
+            assert_eq!(header.keyframe_interval, 12);
+            assert_eq!(header.flags & FLAG_FOOTER != 0, streamed);
+            assert!(header.flags & FLAG_ALPHA != 0 && header.loop_hint());
+            assert_eq!(header.cell_aspect, 220);
+            assert!((header.pixel_aspect() - 1.1).abs() < 1e-9);
+            assert_eq!(reader.read_metadata().unwrap(), metadata);
+            let Frame::Keyframe(cells) = reader.read_frame(0).unwrap() else { panic!("expected a keyframe") };
+            assert_eq!(cells, [cell; 6]);
+        }
+        assert!(RsfxBuilder::new(3, 2).fps(30, 0).build(Cursor::new(Vec::new())).is_err());
+    }
+
+    #[test]
+    fn wide_grid_round_trips() {
+        use crate::encode::RsfxBuilder;
+
+        let (cols, rows) = (70_000u32, 2u32);
+        let black = cell([0, 0, 0], [0, 0, 0]);
+        let first = vec![black; (cols * rows) as usize];
+        let mut second = first.clone();
+        // Past the u16 range on both sides of the boundary
+        for x in [65_534, 65_535, 65_536, cols - 1] {
+            second[(cols + x) as usize] = Cell { fg_g: (x % 251) as u8, ..black };
+        }
+
+        for region_deltas in [false, true] {
+            let header = RsfxHeader::new(cols, rows, 30, 1, 30).unwrap();
+            assert!(header.flags & FLAG_WIDE_GRID != 0);
+            let mut writer = RsfxBuilder::new(cols, rows).region_deltas(region_deltas).build(Cursor::new(Vec::new())).unwrap();
+            writer.write_frame(&first).unwrap();
+            assert_eq!(writer.write_frame(&second).unwrap(), FrameType::Delta);
+            let data = writer.finish().unwrap().into_inner();
+            assert_eq!((data[54], data[55]), (1, 0));
+
+            let mut reader = RsfxReader::new(Cursor::new(data)).unwrap();
+            assert_eq!((reader.header.cols, reader.header.rows), (cols, rows));
+            let Frame::Delta(deltas) = reader.read_frame(1).unwrap() else { panic!("expected a delta") };
+            let mut grid = reader.read_keyframe(0).unwrap();
+            for d in deltas {
+                grid[(d.y * cols + d.x) as usize] = d.cell;
+            }
+            assert!(grid == second, "region deltas: {region_deltas}");
+        }
+
+        // Narrow grids keep 16-bit coordinates, and the header has a limit
+        assert!(RsfxHeader::new(u16::MAX as u32, 1, 30, 1, 30).unwrap().flags & FLAG_WIDE_GRID == 0);
+        assert!(RsfxHeader::new(MAX_DIMENSION + 1, 1, 30, 1, 30).is_err());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "needs FLAG_WIDE_GRID")]
+    fn narrow_deltas_refuse_wide_coordinates() {
+        let delta = DeltaCell { x: u16::MAX as u32 + 1, y: 0, cell: solid_frame(1)[0] };
+        CellLayout::from_flags(0).write_delta(&delta, &mut Vec::new());
+    }
+
+    #[test]
+    fn cells_convert_to_and_from_pixels() {
+        let cell = cell([1, 2, 3], [4, 5, 6]);
+        assert_eq!((cell.bg_r, cell.bg_g, cell.bg_b, cell.fg_r, cell.fg_g, cell.fg_b), (1, 2, 3, 4, 5, 6));
+        assert!(cell.is_opaque() && cell.glyph.is_none());
+        assert_eq!(cell.to_pixels(), ([1, 2, 3], [4, 5, 6]));
+
+        let cell = Cell::from_rgba_pixels([1, 2, 3, 40], [4, 5, 6, 200]);
+        assert_eq!((cell.bg_a, cell.fg_a), (40, 200));
+        assert_eq!(cell.to_rgba_pixels(), ([1, 2, 3, 40], [4, 5, 6, 200]));
+        assert_eq!(cell.to_pixels(), ([1, 2, 3], [4, 5, 6]));
+    }
+
+    #[test]
+    fn protocol_messages_roundtrip() {
+        use crate::protocol::{self, ControlCmd, Message};
+
+        let messages = [
+            Message::Frame { width: 3, height: 2, timestamp_us: 1_700_000_000_123_456, rgb_data: (0..18).collect() },
+            Message::Frame { width: 0, height: 0, timestamp_us: 0, rgb_data: Vec::new() },
+            Message::Audio(vec![1, 2, 3, 4, 0xff, 0x7f]),
+            Message::Audio(Vec::new()),
+            Message::Control(ControlCmd::Ready),
+            Message::Control(ControlCmd::Start),
+            Message::Control(ControlCmd::Stop),
+            Message::Control(ControlCmd::Heartbeat),
+        ];
+        let mut wire = Vec::new();
+        for msg in &messages {
+            msg.encode(&mut wire).unwrap();
+        }
+        assert_eq!(&wire[..6], b"RF\x03\x00\x02\x00");
+
+        let mut input = Cursor::new(wire);
+        for msg in &messages {
+            assert_eq!(Message::decode(&mut input).unwrap().as_ref(), Some(msg));
+        }
+        assert_eq!(Message::decode(&mut input).unwrap(), None);
+
+        // A compressed frame reads back as the plain one, and a flat frame shrinks
+        let (width, height) = (40u16, 20u16);
+        let rgb_data = [10u8, 20, 30].repeat(width as usize * height as usize);
+        let mut wire = Vec::new();
+        protocol::encode_compressed_frame(&mut wire, width, height, 7, &rgb_data, &mut Vec::new()).unwrap();
+        assert_eq!(&wire[..2], b"RZ");
+        assert!(wire.len() < rgb_data.len() / 4);
+        let frame = Message::decode(&mut Cursor::new(&wire)).unwrap();
+        assert_eq!(frame, Some(Message::Frame { width, height, timestamp_us: 7, rgb_data }));
+        // The pixels have to match the size in the header
+        wire[2] += 1;
+        assert!(Message::decode(&mut Cursor::new(&wire)).is_err());
+
+        assert!(Message::decode(&mut Cursor::new(b"RX")).is_err());
+        assert!(Message::decode(&mut Cursor::new(b"RC\x09")).is_err());
+        // A message cut off partway is an error, not a clean end
+        assert!(Message::decode(&mut Cursor::new(b"RA\x04\x00\x00\x00\x01")).is_err());
+    }
+
+    #[test]
+    fn read_audio_wraps_as_wav() {
+        use crate::audio::{wrap_pcm_as_wav, WAV_HEADER_SIZE};
+
+        // A stereo ramp, stored and read back from a file
+        let samples: Vec<i16> = (0..200).map(|i| i * 100 - 10000).collect();
+        let pcm: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let mut writer = RsfxWriter::new(Cursor::new(Vec::new()), 1, 1, 30, 30).unwrap();
+        writer.write_keyframe(&[Cell::from_pixels([0, 0, 0], [0, 0, 0])]).unwrap();
+        writer.write_audio(&pcm, 22050, 2).unwrap();
+        let mut reader = RsfxReader::new(Cursor::new(writer.finish().unwrap().into_inner())).unwrap();
+        let (rate, channels) = (reader.header.audio_sample_rate, reader.header.audio_channels);
+        let wav = wrap_pcm_as_wav(&reader.read_audio().unwrap(), rate, channels, 16).unwrap();
+        assert_eq!(wav.len(), WAV_HEADER_SIZE + pcm.len());
+
+        let mut wav_reader = hound::WavReader::new(Cursor::new(wav)).unwrap();
+        let spec = wav_reader.spec();
+        assert_eq!((spec.sample_rate, spec.channels, spec.bits_per_sample), (22050, 2, 16));
+        assert_eq!(spec.sample_format, hound::SampleFormat::Int);
+        assert_eq!(wav_reader.duration(), 100);
+        let read: Vec<i16> = wav_reader.samples::<i16>().map(Result::unwrap).collect();
+        assert_eq!(read, samples);
+
+        // A trailing partial frame (two stereo frames plus a stray sample) is dropped
+        let wav = wrap_pcm_as_wav(&[0u8; 10], 44100, 2, 16).unwrap();
+        assert_eq!(u32::from_le_bytes(wav[40..44].try_into().unwrap()), 8);
+        assert_eq!(wav.len(), WAV_HEADER_SIZE + 8);
+        // 8-bit mono has one-byte frames
+        let wav = wrap_pcm_as_wav(&[128u8; 5], 8000, 1, 8).unwrap();
+        assert_eq!(hound::WavReader::new(Cursor::new(wav)).unwrap().duration(), 5);
+
+        // Header fields that would overflow, as a damaged file's header could ask for
+        assert!(wrap_pcm_as_wav(&[], 44100, u16::MAX, 16).is_err());
+        assert!(wrap_pcm_as_wav(&[], u32::MAX, 2, 16).is_err());
+    }
+
+    #[test]
+    fn zero_frame_files_open() {
+        use crate::encode::RsfxBuilder;
+
+        let pcm = vec![7u8; 400];
+        let mut writer = RsfxWriter::new(Cursor::new(Vec::new()), 4, 2, 30, 30).unwrap();
+        writer.write_audio(&pcm, 44100, 2).unwrap();
+        let seekable = writer.finish().unwrap().into_inner();
+        let mut writer = RsfxBuilder::new(4, 2).build_streaming(Vec::new()).unwrap();
+        writer.write_audio(&pcm, 44100, 2).unwrap();
+        let streamed = writer.finish_streaming().unwrap();
+
+        for file in [seekable, streamed] {
+            let mut reader = RsfxReader::new(Cursor::new(&file)).unwrap();
+            assert_eq!((reader.header.frame_count, reader.len()), (0, 0));
+            assert_eq!(reader.read_audio().unwrap(), pcm);
+            // Asking for a frame is an error, not a panic
+            assert!(reader.read_frame(0).is_err());
+            assert!(reader.reconstruct_frame(0).is_err());
+            assert_eq!((reader.keyframe_before(0), reader.keyframe_after(0)), (None, None));
+            assert_eq!(reader.frame_at_time(10.0), 0);
+
+            let mut seq = SequentialReader::new(file.as_slice()).unwrap();
+            assert!(seq.next_frame().unwrap().is_none());
+            assert_eq!(seq.take_audio(), Some(pcm.clone()));
+        }
+    }
 }
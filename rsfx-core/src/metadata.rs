@@ -0,0 +1,38 @@
+use anyhow::Context;
+
+/// Payload of a `SECTION_METADATA` section: pair count (u32), then per pair the
+/// key and the value, each as a length (u32) and UTF-8 bytes.
+pub(crate) fn to_bytes(pairs: &[(String, String)]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&(pairs.len() as u32).to_le_bytes());
+    for (key, value) in pairs {
+        for s in [key, value] {
+            buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+            buf.extend_from_slice(s.as_bytes());
+        }
+    }
+    buf
+}
+
+pub(crate) fn from_bytes(data: &[u8]) -> anyhow::Result<Vec<(String, String)>> {
+    let mut rest = data;
+    let mut take = |n: usize| -> anyhow::Result<&[u8]> {
+        anyhow::ensure!(rest.len() >= n, "metadata section truncated");
+        let (head, tail) = rest.split_at(n);
+        rest = tail;
+        Ok(head)
+    };
+
+    let count = u32::from_le_bytes(take(4)?.try_into().unwrap());
+    let mut read_string = || -> anyhow::Result<String> {
+        let len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+        String::from_utf8(take(len)?.to_vec()).context("metadata is not UTF-8")
+    };
+    let mut pairs = Vec::new();
+    for _ in 0..count {
+        let key = read_string()?;
+        let value = read_string()?;
+        pairs.push((key, value));
+    }
+    Ok(pairs)
+}
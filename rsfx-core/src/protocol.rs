@@ -0,0 +1,185 @@
+use std::io::{ErrorKind, Read, Write};
+
+use anyhow::{bail, Context};
+
+use crate::compress;
+
+/// Messages of the avatar renderer's wire protocol. Each starts with a two-byte
+/// magic; all integers are little-endian.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    /// `RF`: width (u16), height (u16), timestamp (u64), then `width * height`
+    /// RGB24 pixels. `timestamp_us` is the producer clock in microseconds (Unix
+    /// time), used for latency.
+    ///
+    /// `RZ` carries the same frame with the pixels LZ4-compressed: the same
+    /// header, then the compressed length (u32) and that many bytes in
+    /// `compress::compress`'s format, which opens with the uncompressed size.
+    /// It decodes to this variant too.
+    Frame {
+        width: u16,
+        height: u16,
+        timestamp_us: u64,
+        rgb_data: Vec<u8>,
+    },
+    /// `RA`: length (u32), then that many bytes of raw PCM (mono s16le)
+    Audio(Vec<u8>),
+    /// `RC`: one command byte
+    Control(ControlCmd),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlCmd {
+    Stop = 0,
+    Start = 1,
+    Ready = 2,
+    /// Keepalive from a producer with nothing else to send, so a renderer with
+    /// an idle timeout can tell it from a dead one
+    Heartbeat = 3,
+}
+
+impl ControlCmd {
+    fn from_byte(byte: u8) -> anyhow::Result<Self> {
+        Ok(match byte {
+            0 => Self::Stop,
+            1 => Self::Start,
+            2 => Self::Ready,
+            3 => Self::Heartbeat,
+            other => bail!("unknown control command: {other}"),
+        })
+    }
+}
+
+impl Message {
+    /// Write the message to `out`, without flushing.
+    pub fn encode(&self, out: &mut impl Write) -> std::io::Result<()> {
+        match self {
+            Self::Frame {
+                width,
+                height,
+                timestamp_us,
+                rgb_data,
+            } => encode_frame(out, *width, *height, *timestamp_us, rgb_data),
+            Self::Audio(pcm) => encode_audio(out, pcm),
+            Self::Control(cmd) => encode_control(out, *cmd),
+        }
+    }
+
+    /// Read the next message from `input`. Returns None on a clean end of stream
+    /// between messages.
+    pub fn decode(input: &mut impl Read) -> anyhow::Result<Option<Self>> {
+        let mut magic = [0u8; 2];
+        match input.read_exact(&mut magic) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e).context("reading message magic"),
+        }
+
+        let msg = match &magic {
+            b"RF" => {
+                let mut header = [0u8; 12]; // width:2 + height:2 + timestamp:8
+                input.read_exact(&mut header).context("reading frame header")?;
+                let width = u16::from_le_bytes([header[0], header[1]]);
+                let height = u16::from_le_bytes([header[2], header[3]]);
+                let timestamp_us = u64::from_le_bytes(header[4..12].try_into().unwrap());
+
+                let mut rgb_data = vec![0u8; width as usize * height as usize * 3];
+                input.read_exact(&mut rgb_data).context("reading frame rgb data")?;
+                Self::Frame {
+                    width,
+                    height,
+                    timestamp_us,
+                    rgb_data,
+                }
+            }
+            b"RZ" => {
+                let mut header = [0u8; 16]; // width:2 + height:2 + timestamp:8 + length:4
+                input.read_exact(&mut header).context("reading compressed frame header")?;
+                let width = u16::from_le_bytes([header[0], header[1]]);
+                let height = u16::from_le_bytes([header[2], header[3]]);
+                let timestamp_us = u64::from_le_bytes(header[4..12].try_into().unwrap());
+                let length = u32::from_le_bytes(header[12..16].try_into().unwrap()) as usize;
+
+                let mut compressed = vec![0u8; length];
+                input.read_exact(&mut compressed).context("reading compressed frame data")?;
+                let expected = width as usize * height as usize * 3;
+                let mut rgb_data = Vec::new();
+                compress::decompress_into(&compressed, Some(expected), &mut rgb_data)
+                    .context("decompressing frame")?;
+                if rgb_data.len() != expected {
+                    bail!("compressed {width}x{height} frame holds {} bytes, expected {expected}", rgb_data.len());
+                }
+                Self::Frame {
+                    width,
+                    height,
+                    timestamp_us,
+                    rgb_data,
+                }
+            }
+            b"RA" => {
+                let mut len_buf = [0u8; 4];
+                input.read_exact(&mut len_buf).context("reading audio length")?;
+                let mut pcm_data = vec![0u8; u32::from_le_bytes(len_buf) as usize];
+                input.read_exact(&mut pcm_data).context("reading audio pcm data")?;
+                Self::Audio(pcm_data)
+            }
+            b"RC" => {
+                let mut cmd = [0u8; 1];
+                input.read_exact(&mut cmd).context("reading control command")?;
+                Self::Control(ControlCmd::from_byte(cmd[0])?)
+            }
+            _ => bail!("unknown message magic: {:?}", magic),
+        };
+        Ok(Some(msg))
+    }
+}
+
+/// Write an `RF` frame straight from borrowed pixels, which a sender streaming
+/// video would otherwise have to copy into a `Message`.
+pub fn encode_frame(
+    out: &mut impl Write,
+    width: u16,
+    height: u16,
+    timestamp_us: u64,
+    rgb_data: &[u8],
+) -> std::io::Result<()> {
+    debug_assert_eq!(rgb_data.len(), width as usize * height as usize * 3);
+    out.write_all(b"RF")?;
+    out.write_all(&width.to_le_bytes())?;
+    out.write_all(&height.to_le_bytes())?;
+    out.write_all(&timestamp_us.to_le_bytes())?;
+    out.write_all(rgb_data)
+}
+
+/// Write an `RZ` frame: `encode_frame` with the pixels LZ4-compressed into
+/// `scratch` first, for links where bandwidth matters more than a little CPU.
+/// Only renderers that know `RZ` can read it; there's no negotiation, so the
+/// producer has to be told to use it.
+pub fn encode_compressed_frame(
+    out: &mut impl Write,
+    width: u16,
+    height: u16,
+    timestamp_us: u64,
+    rgb_data: &[u8],
+    scratch: &mut Vec<u8>,
+) -> std::io::Result<()> {
+    debug_assert_eq!(rgb_data.len(), width as usize * height as usize * 3);
+    compress::compress_into(rgb_data, scratch);
+    out.write_all(b"RZ")?;
+    out.write_all(&width.to_le_bytes())?;
+    out.write_all(&height.to_le_bytes())?;
+    out.write_all(&timestamp_us.to_le_bytes())?;
+    out.write_all(&(scratch.len() as u32).to_le_bytes())?;
+    out.write_all(scratch)
+}
+
+/// Write an `RA` message carrying `pcm`.
+pub fn encode_audio(out: &mut impl Write, pcm: &[u8]) -> std::io::Result<()> {
+    out.write_all(b"RA")?;
+    out.write_all(&(pcm.len() as u32).to_le_bytes())?;
+    out.write_all(pcm)
+}
+
+pub fn encode_control(out: &mut impl Write, cmd: ControlCmd) -> std::io::Result<()> {
+    out.write_all(&[b'R', b'C', cmd as u8])
+}
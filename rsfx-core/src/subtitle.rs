@@ -0,0 +1,120 @@
+use anyhow::Context;
+
+/// One subtitle, shown from `start_ms` up to (not including) `end_ms`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubtitleEntry {
+    pub start_ms: u32,
+    pub end_ms: u32,
+    pub text: String,
+}
+
+impl SubtitleEntry {
+    pub fn is_active(&self, time_ms: u32) -> bool {
+        (self.start_ms..self.end_ms).contains(&time_ms)
+    }
+}
+
+/// The entry to show at `time_ms`, if any. When entries overlap the first one wins.
+pub fn active_at(entries: &[SubtitleEntry], time_ms: u32) -> Option<&SubtitleEntry> {
+    entries.iter().find(|e| e.is_active(time_ms))
+}
+
+/// Payload of a `SECTION_SUBTITLES` section: entry count (u32), then per entry
+/// start and end in milliseconds (u32 each), text length (u32) and the UTF-8 text.
+/// Lines within an entry are separated by `\n`.
+pub(crate) fn to_bytes(entries: &[SubtitleEntry]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    for entry in entries {
+        buf.extend_from_slice(&entry.start_ms.to_le_bytes());
+        buf.extend_from_slice(&entry.end_ms.to_le_bytes());
+        buf.extend_from_slice(&(entry.text.len() as u32).to_le_bytes());
+        buf.extend_from_slice(entry.text.as_bytes());
+    }
+    buf
+}
+
+pub(crate) fn from_bytes(data: &[u8]) -> anyhow::Result<Vec<SubtitleEntry>> {
+    let mut rest = data;
+    let mut take = |n: usize| -> anyhow::Result<&[u8]> {
+        anyhow::ensure!(rest.len() >= n, "subtitle section truncated");
+        let (head, tail) = rest.split_at(n);
+        rest = tail;
+        Ok(head)
+    };
+    let read_u32 = |b: &[u8]| u32::from_le_bytes(b.try_into().unwrap());
+
+    let count = read_u32(take(4)?);
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let start_ms = read_u32(take(4)?);
+        let end_ms = read_u32(take(4)?);
+        let len = read_u32(take(4)?) as usize;
+        let mut text = String::from_utf8(take(len)?.to_vec()).context("subtitle text is not UTF-8")?;
+        strip_controls(&mut text);
+        entries.push(SubtitleEntry { start_ms, end_ms, text });
+    }
+    Ok(entries)
+}
+
+/// Parse SubRip (.srt) text. Cue numbers are optional, `<i>`-style tags are
+/// dropped, and blocks without a `-->` timing line are skipped.
+pub fn parse_srt(text: &str) -> anyhow::Result<Vec<SubtitleEntry>> {
+    let text = text.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    let mut entries = Vec::new();
+
+    for block in text.split("\n\n") {
+        let mut lines = block.lines().map(str::trim_end).filter(|l| !l.is_empty()).peekable();
+        // Skip the cue number, if there is one
+        if lines.peek().is_some_and(|l| !l.contains("-->")) {
+            lines.next();
+        }
+        let Some(timing) = lines.next() else { continue };
+        let Some((start, end)) = timing.split_once("-->") else { continue };
+        let start_ms = parse_srt_time(start).with_context(|| format!("bad subtitle timing `{timing}`"))?;
+        // The end time may be followed by position hints
+        let end = end.split_whitespace().next().unwrap_or("");
+        let end_ms = parse_srt_time(end).with_context(|| format!("bad subtitle timing `{timing}`"))?;
+
+        let text = lines.map(strip_tags).collect::<Vec<_>>().join("\n");
+        if !text.is_empty() {
+            entries.push(SubtitleEntry { start_ms, end_ms, text });
+        }
+    }
+
+    entries.sort_by_key(|e| e.start_ms);
+    Ok(entries)
+}
+
+/// `HH:MM:SS,mmm` (a `.` before the milliseconds is accepted too) to milliseconds.
+fn parse_srt_time(s: &str) -> anyhow::Result<u32> {
+    let s = s.trim();
+    let (hms, millis) = s.split_once([',', '.']).unwrap_or((s, "0"));
+    let parts: Vec<&str> = hms.split(':').collect();
+    let [h, m, sec] = parts[..] else {
+        anyhow::bail!("expected HH:MM:SS,mmm");
+    };
+    let num = |v: &str| v.trim().parse::<u32>().with_context(|| format!("invalid number `{v}`"));
+    Ok(((num(h)? * 60 + num(m)?) * 60 + num(sec)?) * 1000 + num(millis)?)
+}
+
+/// Drop control characters other than the `\n` between lines: players write
+/// subtitle text straight to the terminal, which would act on ESC and the like.
+fn strip_controls(text: &mut String) {
+    text.retain(|c| c == '\n' || !c.is_control());
+}
+
+/// Drop `<i>`-style tags, and control characters as `strip_controls` does.
+fn strip_tags(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut in_tag = false;
+    for c in line.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag && !c.is_control() => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}